@@ -0,0 +1,32 @@
+// Clears and snoozes without reloading the whole page. The forms still work without this script.
+document.addEventListener("submit", async (event) => {
+    const form = event.target;
+    if (!form.matches(".clear-form, .snooze-form")) {
+        return;
+    }
+    if (form.dataset.confirm && !confirm(form.dataset.confirm)) {
+        event.preventDefault();
+        return;
+    }
+    if (!window.fetch) {
+        return;
+    }
+    event.preventDefault();
+
+    const item = document.getElementById("alert-" + form.elements.hash.value);
+    item?.classList.add("pending");
+    try {
+        const response = await fetch(form.action, {
+            method: "POST",
+            body: new URLSearchParams(new FormData(form)),
+            credentials: "same-origin",
+        });
+        if (!response.ok) {
+            throw new Error((await response.text()) || response.statusText);
+        }
+        item?.remove();
+    } catch (error) {
+        item?.classList.remove("pending");
+        alert("Request failed: " + error.message);
+    }
+});
@@ -0,0 +1,61 @@
+use crate::auth::client_ip;
+use crate::config::CONFIG;
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use log::info;
+use serde::Deserialize;
+use std::time::Instant;
+
+fn exclude_default() -> Vec<String> {
+    vec!["/healthz".to_string(), "/metrics".to_string()]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessLogSettings {
+    // path prefixes below the web path prefix, scrapes and probes would drown everything else
+    #[serde(default = "exclude_default")]
+    exclude: Vec<String>,
+}
+
+impl AccessLogSettings {
+    fn is_excluded(&self, path: &str) -> bool {
+        let path = path.strip_prefix(CONFIG.web_path_prefix()).unwrap_or(path);
+        self.exclude.iter().any(|e| path.starts_with(e.as_str()))
+    }
+}
+
+// logged under the `access` target, so it can be filtered independently of the rest
+pub async fn access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if CONFIG
+        .access_log()
+        .is_none_or(|settings| settings.is_excluded(req.path()))
+    {
+        return next.call(req).await;
+    }
+
+    let start = Instant::now();
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    let client = match client_ip(req.peer_addr(), req.headers()) {
+        Some(ip) => ip.to_string(),
+        None => "-".to_string(),
+    };
+
+    let res = next.call(req).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    info!(
+        target: "access",
+        "method={method} path={path:?} status={} latency_ms={:.1} client={client}",
+        status.as_u16(),
+        start.elapsed().as_secs_f64() * 1000.0,
+    );
+    res
+}
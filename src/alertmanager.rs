@@ -1,96 +1,405 @@
 use crate::alerts::{Alert, Severity};
-use crate::config::CONFIG;
+use crate::business_hours::adjust_severity;
+use crate::config::{OversizedLabelPolicy, Settings};
 use crate::enrichment::AlertEnrichment;
-use crate::trap_db::TrapDb;
+use crate::maintenance::active_window;
+use crate::notifier::{Notifier, Schedule};
+use crate::quiet_hours::AfterQuietHours;
+use crate::relay_queue::RelayQueue;
+use crate::sanitize::clean_label_name;
+use crate::sites::site_labels;
+use async_trait::async_trait;
+use itertools::Itertools;
 use log::{debug, info, warn};
-use reqwest::Client;
-use serde::Serialize;
-use std::collections::BTreeMap;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Instant;
-use itertools::Itertools;
 use time::format_description::well_known::Rfc3339;
 use time::{Duration, OffsetDateTime};
+use tokio::sync::{RwLock, RwLockReadGuard};
 
-pub struct AlertmanagerRelay {
-    url: String,
-    client: Client,
-    db: Arc<TrapDb>,
-    last_announce_try: Instant,
-    enrichment: AlertEnrichment,
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryStatus {
+    pub last_success: Option<OffsetDateTime>,
+    pub last_error: Option<String>,
 }
 
-impl AlertmanagerRelay {
-    pub fn new(url: String, db: Arc<TrapDb>) -> anyhow::Result<Self> {
-        let mut enrichment = AlertEnrichment::new();
-        if let Some(alert_dir) = CONFIG.alert_dir() {
-            enrichment.load_directory(alert_dir)?;
+#[derive(Default)]
+pub struct RelayStatus {
+    last_success: RwLock<Option<OffsetDateTime>>,
+    deliveries: RwLock<HashMap<u64, DeliveryStatus>>,
+}
+
+impl RelayStatus {
+    pub async fn last_success(&self) -> Option<OffsetDateTime> {
+        *self.last_success.read().await
+    }
+
+    async fn record_success(&self) {
+        *self.last_success.write().await = Some(OffsetDateTime::now_utc());
+    }
+
+    pub async fn deliveries(&self) -> RwLockReadGuard<'_, HashMap<u64, DeliveryStatus>> {
+        self.deliveries.read().await
+    }
+
+    async fn record_delivery(&self, alerts: &[AlertmanagerAlert], error: Option<&str>) {
+        let now = OffsetDateTime::now_utc();
+        let mut deliveries = self.deliveries.write().await;
+        for hash in alerts.iter().filter_map(|a| a.source_hash) {
+            let status = deliveries.entry(hash).or_default();
+            match error {
+                None => {
+                    status.last_success = Some(now);
+                    status.last_error = None;
+                }
+                Some(error) => status.last_error = Some(error.to_string()),
+            }
         }
+    }
+
+    async fn forget_deliveries_except(&self, hashes: &HashSet<u64>) {
+        self.deliveries
+            .write()
+            .await
+            .retain(|hash, _| hashes.contains(hash));
+    }
+}
+
+enum Delivery {
+    Delivered,
+    Rejected(String),
+}
 
-        info!("Loaded {} alert enrichments", enrichment.count());
+pub struct AlertmanagerRelay {
+    name: String,
+    settings: Arc<Settings>,
+    client: Client,
+    status: Arc<RelayStatus>,
+    queue: Option<RelayQueue>,
+    announced: HashMap<u64, OffsetDateTime>,
+    withheld: HashSet<u64>,
+}
 
-        Ok(Self {
-            url,
+impl AlertmanagerRelay {
+    pub fn new(settings: Arc<Settings>, status: Arc<RelayStatus>) -> Self {
+        Self {
+            name: "alertmanager".to_string(),
+            queue: settings.relay_queue().cloned().map(RelayQueue::new),
+            settings,
             client: Client::default(),
-            db,
-            last_announce_try: Instant::now() - Duration::days(360),
-            enrichment,
-        })
+            status,
+            announced: HashMap::new(),
+            withheld: HashSet::new(),
+        }
     }
 
-    pub async fn run_relay_blocking(&mut self) {
-        loop {
-            let next_announce = self.last_announce_try + CONFIG.alertmanager_announce_duration();
-            tokio::time::sleep_until(next_announce.into()).await;
+    // withheld alerts are announced once the quiet hours are over, unless they should be dropped
+    fn withhold_quiet(&mut self, alerts: &mut Vec<AlertmanagerAlert>) {
+        let Some(quiet_hours) = self.settings.quiet_hours() else {
+            return;
+        };
+        self.withheld
+            .retain(|hash| alerts.iter().any(|a| a.source_hash == Some(*hash)));
 
-            match self.relay_alerts().await {
-                Ok(_) => {
-                    debug!("SNMP Trap alerts successfully relayed to Alertmanager");
+        if quiet_hours.is_active() {
+            alerts.retain(|alert| {
+                if !matches!(Severity::from_str(alert.severity()), Ok(Severity::Info)) {
+                    return true;
                 }
-                Err(e) => {
-                    warn!("Couldn't relay alerts to alertmanager: {e:?}");
+                debug!("Withholding alert {:?} during quiet hours", alert.name());
+                if let Some(hash) = alert.source_hash {
+                    self.withheld.insert(hash);
+                    self.announced.remove(&hash);
                 }
-            }
+                false
+            });
+        } else if quiet_hours.after() == AfterQuietHours::Discard {
+            alerts.retain(|a| {
+                a.source_hash
+                    .is_none_or(|hash| !self.withheld.contains(&hash))
+            });
+        } else {
+            self.withheld.clear();
+        }
+    }
+
+    // new alerts go out right away, known ones once their severity's cadence has passed
+    fn due_alerts(
+        &self,
+        alerts: &[AlertmanagerAlert],
+        now: OffsetDateTime,
+    ) -> Vec<AlertmanagerAlert> {
+        alerts
+            .iter()
+            .filter_map(|alert| {
+                let interval = self.announce_interval(alert);
+                let due = alert
+                    .source_hash
+                    .and_then(|hash| self.announced.get(&hash))
+                    .is_none_or(|last| now - *last >= interval);
+                due.then(|| {
+                    let mut alert = alert.clone();
+                    let ends_at: OffsetDateTime =
+                        now + (interval + self.settings.alertmanager_announce_jitter()) * 3;
+                    alert.ends_at = ends_at.format(&Rfc3339).unwrap();
+                    alert
+                })
+            })
+            .collect()
+    }
 
-            self.last_announce_try = Instant::now()
+    fn announce_interval(&self, alert: &AlertmanagerAlert) -> Duration {
+        match Severity::from_str(alert.severity()) {
+            Ok(severity) => self.settings.alertmanager_announce_duration_for(severity),
+            Err(_) => self.settings.alertmanager_announce_duration(),
         }
     }
 
-    pub async fn relay_alerts(&self) -> anyhow::Result<()> {
-        let alerts = self.db.cached_alerts().await;
-        let mut alerts_data = self.alerts_to_alertmanager(&*alerts);
-        drop(alerts);
-        self.enrich(&mut alerts_data)?;
+    // the alerts still active after filtering, and the ones due to be posted this cycle
+    pub fn payload(
+        &mut self,
+        alerts: &[AlertmanagerAlert],
+        now: OffsetDateTime,
+    ) -> (Vec<AlertmanagerAlert>, Vec<AlertmanagerAlert>) {
+        let mut alerts_data = alerts.to_vec();
+        for alert in &mut alerts_data {
+            let fixes = alert.validate(self.settings.alertmanager_max_alert_bytes());
+            if !fixes.is_empty() {
+                warn!(
+                    "Fixed alert {:?} before relaying: {}",
+                    alert.name(),
+                    fixes.join(", ")
+                );
+            }
+            if let Some(reason) = alert.suppressed() {
+                debug!("Not relaying alert {:?}: {reason}", alert.name());
+            }
+        }
+        alerts_data.retain(|a| a.suppressed().is_none());
+        self.withhold_quiet(&mut alerts_data);
 
-        self.client
-            .post(format!("{}/api/v2/alerts", self.url))
-            .json(&alerts_data)
-            .send()
-            .await?
-            .error_for_status()?;
+        self.announced
+            .retain(|hash, _| alerts_data.iter().any(|a| a.source_hash == Some(*hash)));
+        let due = self.due_alerts(&alerts_data, now);
+        (alerts_data, due)
+    }
 
-        Ok(())
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
     }
 
-    fn alerts_to_alertmanager<'a>(
+    // alerts still active are left out, the current cycle announces them with fresh end times
+    async fn flush_queue(
         &self,
-        alerts: impl IntoIterator<Item = &'a Alert>,
-    ) -> Vec<AlertmanagerAlert> {
-        alerts
+        queue: &RelayQueue,
+        current: &[AlertmanagerAlert],
+    ) -> anyhow::Result<()> {
+        let queued = queue
+            .load()
+            .await?
             .into_iter()
-            .map(AlertmanagerAlert::from)
-            .collect_vec()
+            .map(|q| q.with_label_names(&self.settings))
+            .filter(|q| !current.iter().any(|c| c.labels() == q.labels()))
+            .collect_vec();
+        if !queued.is_empty() {
+            info!(
+                "Flushing {} alerts queued during an Alertmanager outage",
+                queued.len()
+            );
+            self.deliver(&queued).await?;
+        }
+        queue.clear().await
+    }
+
+    async fn deliver<'a>(
+        &self,
+        alerts: &'a [AlertmanagerAlert],
+    ) -> anyhow::Result<Vec<&'a AlertmanagerAlert>> {
+        let rejected = match self.post_isolating(alerts).await {
+            Ok(rejected) => rejected,
+            Err(e) => {
+                self.status
+                    .record_delivery(alerts, Some(&e.to_string()))
+                    .await;
+                return Err(e);
+            }
+        };
+        if !rejected.is_empty() {
+            warn!(
+                "Alertmanager rejected {} of {} alerts, the rest were delivered",
+                rejected.len(),
+                alerts.len()
+            );
+        }
+        Ok(rejected)
+    }
+
+    // a rejected batch is split in halves until the offending alerts are isolated,
+    // so one bad payload doesn't keep all healthy alerts from being delivered
+    async fn post_isolating<'a>(
+        &self,
+        alerts: &'a [AlertmanagerAlert],
+    ) -> anyhow::Result<Vec<&'a AlertmanagerAlert>> {
+        let mut pending = vec![alerts];
+        let mut rejected = Vec::new();
+
+        while let Some(batch) = pending.pop() {
+            match self.post(batch).await? {
+                Delivery::Delivered => self.status.record_delivery(batch, None).await,
+                Delivery::Rejected(reason) if batch.len() <= 1 => {
+                    rejected.extend(batch);
+                    self.status.record_delivery(batch, Some(&reason)).await;
+                    if let Some(alert) = batch.first() {
+                        warn!(
+                            "Alertmanager rejected alert {:?}: {reason}. Payload: {}",
+                            alert.name(),
+                            serde_json::to_string(alert).unwrap_or_default()
+                        );
+                    }
+                }
+                Delivery::Rejected(_) => {
+                    let (left, right) = batch.split_at(batch.len() / 2);
+                    pending.push(right);
+                    pending.push(left);
+                }
+            }
+        }
+
+        Ok(rejected)
+    }
+
+    async fn post(&self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<Delivery> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/api/v2/alerts",
+                self.settings.alertmanager_url()
+            ))
+            .json(alerts)
+            .send()
+            .await?;
+
+        let status = response.status();
+        // only these are about the payload, other client errors like 401 or 429 hit every alert
+        // alike and are retried as delivery errors
+        if matches!(
+            status,
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY
+        ) {
+            let body = response.text().await.unwrap_or_default();
+            return Ok(Delivery::Rejected(format!("{status}: {}", body.trim())));
+        }
+        response.error_for_status()?;
+
+        Ok(Delivery::Delivered)
+    }
+}
+
+#[async_trait]
+impl Notifier for AlertmanagerRelay {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::new(
+            self.settings.alertmanager_min_announce_duration(),
+            self.settings.alertmanager_announce_jitter(),
+            self.settings.alertmanager_announce_splay(),
+        )
     }
 
-    fn enrich(&self, alerts: &mut [AlertmanagerAlert]) -> anyhow::Result<()> {
-        for alert in alerts.iter_mut() {
-            alert.enrich(&self.enrichment)?;
+    async fn notify(&mut self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        self.status
+            .forget_deliveries_except(&alerts.iter().filter_map(|a| a.source_hash).collect())
+            .await;
+
+        let now = OffsetDateTime::now_utc();
+        let (alerts_data, due) = self.payload(alerts, now);
+
+        // a failed flush leaves the queue for the next cycle, the current alerts still go out
+        let mut flush_error = None;
+        let rejected = if let Some(queue) = &self.queue {
+            if let Err(e) = self.flush_queue(queue, &alerts_data).await {
+                warn!("Failed to flush the Alertmanager relay queue: {e}");
+                flush_error = Some(e);
+            }
+            match self.deliver(&due).await {
+                Ok(rejected) => rejected,
+                Err(e) => {
+                    if let Err(queue_error) = queue.enqueue(&due).await {
+                        warn!("Failed to queue undelivered alerts: {queue_error}");
+                    }
+                    return Err(e);
+                }
+            }
+        } else {
+            self.deliver(&due).await?
+        };
+
+        // rejected alerts stay due, the relay only counts as healthy once everything got through
+        let rejected_hashes: HashSet<u64> = rejected.iter().filter_map(|a| a.source_hash).collect();
+        self.announced.extend(
+            due.iter()
+                .filter_map(|a| a.source_hash)
+                .filter(|hash| !rejected_hashes.contains(hash))
+                .map(|hash| (hash, now)),
+        );
+        if !rejected.is_empty() {
+            anyhow::bail!("Alertmanager rejected {} alerts", rejected.len());
         }
+        if let Some(e) = flush_error {
+            return Err(e);
+        }
+        self.status.record_success().await;
         Ok(())
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+pub fn prepare_alert(
+    alert: &mut AlertmanagerAlert,
+    enrichment: &AlertEnrichment,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    alert.enrich(enrichment)?;
+    if let Some(librenms) = settings.librenms() {
+        librenms.suppress_if_down(alert);
+    }
+    if let Some(display) = settings.community_display(alert.community()) {
+        if let Some(name) = display.name() {
+            alert.add_annotation("community_name", name);
+        }
+        if let Some(color) = display.color() {
+            alert.add_annotation("community_color", color);
+        }
+    }
+    if let Some(conventions) = settings.output_conventions() {
+        conventions.apply(alert)?;
+    }
+    adjust_severity(settings.severity_adjustments(), alert);
+    if let Some(window) = active_window(
+        settings.maintenance_windows(),
+        alert.name(),
+        alert.community(),
+        alert.labels(),
+    ) {
+        alert.add_annotation("in_maintenance", window.name());
+        alert.suppress(format!("maintenance window {:?}", window.name()));
+        alert.maintenance = Some(window.name().to_string());
+    }
+    if let Some(max_length) = settings.label_value_max_length() {
+        alert.limit_label_values(max_length, settings.label_value_oversized());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlertmanagerAlert {
     #[serde(rename = "startsAt")]
     starts_at: String,
@@ -100,33 +409,113 @@ pub struct AlertmanagerAlert {
     annotations: BTreeMap<String, String>,
     #[serde(rename = "generatorURL")]
     generator_url: String,
+    #[serde(skip)]
+    suppressed: Option<String>,
+    #[serde(skip)]
+    maintenance: Option<String>,
+    #[serde(skip)]
+    count: usize,
+    #[serde(skip)]
+    source_hash: Option<u64>,
+    #[serde(skip)]
+    community_label: String,
+    #[serde(skip)]
+    restricted_label_prefix: String,
+    #[serde(skip)]
+    language: Option<String>,
+    #[serde(skip)]
+    escalations: Vec<String>,
+    #[serde(skip)]
+    source: Option<IpAddr>,
 }
 
 impl AlertmanagerAlert {
     pub fn new(
+        settings: &Settings,
         starts_at: OffsetDateTime,
         ends_at: OffsetDateTime,
         name: impl Into<String>,
         community: impl Into<String>,
         severity: Severity,
         labels: Option<BTreeMap<String, String>>,
-        annotations: Option<BTreeMap<String, String>>,
     ) -> Self {
-        let mut labels = labels.unwrap_or_default();
-        labels.insert("alertname".to_string(), name.into());
-        labels.insert("severity".to_string(), severity.to_string());
-        labels.insert(
-            CONFIG.alertmanager_community_label().to_string(),
-            community.into(),
-        );
-
-        AlertmanagerAlert {
+        let mut alert = AlertmanagerAlert {
             starts_at: starts_at.format(&Rfc3339).unwrap(),
             ends_at: ends_at.format(&Rfc3339).unwrap(),
-            labels,
-            annotations: annotations.unwrap_or_default(),
-            generator_url: CONFIG.web_url().to_string(),
+            labels: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            generator_url: settings.web_url().to_string(),
+            suppressed: None,
+            maintenance: None,
+            count: 1,
+            source_hash: None,
+            community_label: String::new(),
+            restricted_label_prefix: String::new(),
+            language: None,
+            escalations: Vec::new(),
+            source: None,
         }
+        .with_label_names(settings);
+
+        alert.labels = labels
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| (alert.collision_safe(&k), v))
+            .collect();
+        alert.labels.insert("alertname".to_string(), name.into());
+        alert
+            .labels
+            .insert("severity".to_string(), severity.to_string());
+        let community = community.into();
+        alert.language = settings.annotation_language(&community).map(str::to_string);
+        alert
+            .labels
+            .insert(alert.community_label.clone(), community);
+        alert
+    }
+
+    pub fn from_alert(alert: &Alert, settings: &Settings) -> Self {
+        let starts_at: OffsetDateTime = alert.earliest();
+        let ends_at: OffsetDateTime = OffsetDateTime::now_utc()
+            + (settings.alertmanager_announce_duration_for(alert.severity())
+                + settings.alertmanager_announce_jitter())
+                * 3;
+
+        let labels = alert.pretty_labels(settings);
+
+        let mut am_alert = AlertmanagerAlert::new(
+            settings,
+            starts_at,
+            ends_at,
+            alert.pretty_name(),
+            alert.community(),
+            alert.severity(),
+            Some(labels),
+        );
+        am_alert.count = alert.count();
+        am_alert.source_hash = Some(alert.hash());
+        am_alert.source = alert.source();
+        // Alertmanager tells alerts apart by their labels, values that keep changing go elsewhere
+        am_alert.add_annotations(alert.volatile_labels());
+        // labels from the trap itself are more specific than the site of its sender
+        if let Some(site) = alert
+            .source()
+            .and_then(|ip| site_labels(settings.sites(), ip))
+        {
+            for (name, value) in site {
+                if !am_alert.labels.contains_key(name) {
+                    am_alert.add_label(name, value);
+                }
+            }
+        }
+        am_alert
+    }
+
+    // reserved label names aren't serialized, deserialized alerts have to be told about them again
+    pub fn with_label_names(mut self, settings: &Settings) -> Self {
+        self.community_label = settings.alertmanager_community_label().to_string();
+        self.restricted_label_prefix = settings.restricted_label_prefix().to_string();
+        self
     }
 
     pub fn enrich(&mut self, enrichment: &AlertEnrichment) -> anyhow::Result<()> {
@@ -141,27 +530,104 @@ impl AlertmanagerAlert {
             .unwrap_or("")
     }
 
+    pub fn starts_at(&self) -> &str {
+        &self.starts_at
+    }
+
+    pub fn ends_at(&self) -> &str {
+        &self.ends_at
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn source_hash(&self) -> Option<u64> {
+        self.source_hash
+    }
+
+    pub fn source(&self) -> Option<IpAddr> {
+        self.source
+    }
+
+    pub fn severity(&self) -> &str {
+        self.labels
+            .get("severity")
+            .map(|s| s.as_str())
+            .unwrap_or("")
+    }
+
+    pub fn community(&self) -> &str {
+        self.labels
+            .get(&self.community_label)
+            .map(|s| s.as_str())
+            .unwrap_or("")
+    }
+
     pub fn labels(&self) -> &BTreeMap<String, String> {
         debug_assert!(self.labels.contains_key("alertname"));
         debug_assert!(self.labels.contains_key("severity"));
-        debug_assert!(
-            self.labels
-                .contains_key(CONFIG.alertmanager_community_label())
-        );
+        debug_assert!(self.labels.contains_key(&self.community_label));
 
         &self.labels
     }
 
-    pub fn is_restricted_label(name: &str) -> bool {
-        name == "alertname" || name == "severity" || name == CONFIG.alertmanager_community_label()
+    pub fn set_severity(&mut self, severity: Severity) {
+        self.labels
+            .insert("severity".to_string(), severity.to_string());
+    }
+
+    pub fn mark_escalated(&mut self, policy: impl Into<String>) {
+        self.escalations.push(policy.into());
+    }
+
+    pub fn escalations(&self) -> &[String] {
+        &self.escalations
+    }
+
+    pub fn suppress(&mut self, reason: impl Into<String>) {
+        self.suppressed = Some(reason.into());
+    }
+
+    pub fn suppressed(&self) -> Option<&str> {
+        self.suppressed.as_deref()
+    }
+
+    pub fn maintenance(&self) -> Option<&str> {
+        self.maintenance.as_deref()
+    }
+
+    pub fn is_restricted_label(settings: &Settings, name: &str) -> bool {
+        is_restricted(settings.alertmanager_community_label(), name)
+    }
+
+    pub fn collision_safe_label(settings: &Settings, name: &str) -> String {
+        collision_safe(
+            settings.alertmanager_community_label(),
+            settings.restricted_label_prefix(),
+            name,
+        )
+    }
+
+    fn is_restricted(&self, name: &str) -> bool {
+        is_restricted(&self.community_label, name)
+    }
+
+    fn collision_safe(&self, name: &str) -> String {
+        collision_safe(&self.community_label, &self.restricted_label_prefix, name)
     }
 
     pub fn add_label(&mut self, name: impl Into<String>, value: impl Into<String>) {
         let name = name.into();
-        if Self::is_restricted_label(&name) {
-            return;
+        let safe_name = self.collision_safe(&name);
+        if safe_name != name {
+            debug!("Label {name:?} is reserved, adding it as {safe_name:?} instead");
         }
-        self.labels.insert(name, value.into());
+        self.labels.insert(safe_name, value.into());
     }
 
     pub fn add_labels<'a, L, S, S2>(&mut self, labels: L)
@@ -176,12 +642,91 @@ impl AlertmanagerAlert {
     }
 
     pub fn remove_label(&mut self, name: &str) -> Option<String> {
-        if Self::is_restricted_label(name) {
+        if self.is_restricted(name) {
             return None;
         }
         self.labels.remove(name)
     }
 
+    pub fn limit_label_values(&mut self, max_length: usize, policy: OversizedLabelPolicy) {
+        let oversized = self
+            .labels
+            .iter()
+            .filter(|(_, v)| v.chars().count() > max_length)
+            .map(|(k, _)| k.clone())
+            .collect_vec();
+
+        let mut truncated = false;
+        for name in oversized {
+            if policy == OversizedLabelPolicy::Annotate && !self.is_restricted(&name) {
+                if let Some(value) = self.labels.remove(&name) {
+                    self.annotations.insert(name, value);
+                }
+            } else if let Some(value) = self.labels.get_mut(&name) {
+                *value = value.chars().take(max_length).collect();
+                truncated = true;
+            }
+        }
+
+        if truncated {
+            self.labels
+                .insert("truncated".to_string(), "true".to_string());
+        }
+    }
+
+    // fixes what Alertmanager would reject the whole batch for, unfixable alerts get suppressed
+    pub fn validate(&mut self, max_bytes: Option<usize>) -> Vec<String> {
+        let mut fixes = Vec::new();
+        if self.name().is_empty() {
+            self.suppress("invalid: empty alertname");
+        }
+
+        for map in [&mut self.labels, &mut self.annotations] {
+            let invalid = map
+                .iter()
+                .filter(|(k, v)| v.is_empty() || clean_label_name(k) != **k)
+                .map(|(k, _)| k.clone())
+                .collect_vec();
+
+            for name in invalid {
+                let Some(value) = map.remove(&name) else {
+                    continue;
+                };
+                if value.is_empty() {
+                    fixes.push(format!("dropped empty {name:?}"));
+                    continue;
+                }
+
+                let clean = clean_label_name(&name);
+                match map.entry(clean) {
+                    Entry::Occupied(e) => {
+                        fixes.push(format!("dropped {name:?}, {:?} already exists", e.key()))
+                    }
+                    Entry::Vacant(e) => {
+                        fixes.push(format!("renamed {name:?} to {:?}", e.key()));
+                        e.insert(value);
+                    }
+                }
+            }
+        }
+
+        if let Some(max_bytes) = max_bytes
+            && let Ok(payload) = serde_json::to_vec(self)
+            && payload.len() > max_bytes
+        {
+            self.suppress(format!(
+                "invalid: {} bytes exceeds the {max_bytes} byte limit",
+                payload.len()
+            ));
+        }
+
+        fixes
+    }
+
+    pub fn annotations(&self) -> &BTreeMap<String, String> {
+        &self.annotations
+    }
+
     pub fn add_annotation(&mut self, name: impl Into<String>, value: impl Into<String>) {
         self.annotations.insert(name.into(), value.into());
     }
@@ -198,22 +743,14 @@ impl AlertmanagerAlert {
     }
 }
 
-impl From<&Alert> for AlertmanagerAlert {
-    fn from(alert: &Alert) -> Self {
-        let starts_at: OffsetDateTime = alert.earliest();
-        let ends_at: OffsetDateTime =
-            OffsetDateTime::now_utc() + CONFIG.alertmanager_announce_duration() * 3;
-
-        let labels = alert.pretty_labels();
+fn is_restricted(community_label: &str, name: &str) -> bool {
+    name == "alertname" || name == "severity" || name == community_label
+}
 
-        AlertmanagerAlert::new(
-            starts_at,
-            ends_at,
-            alert.pretty_name(),
-            alert.community(),
-            alert.severity(),
-            Some(labels),
-            None
-        )
+fn collision_safe(community_label: &str, prefix: &str, name: &str) -> String {
+    if is_restricted(community_label, name) {
+        format!("{prefix}{name}")
+    } else {
+        name.to_string()
     }
 }
@@ -1,28 +1,46 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::bucketing::{BucketRule, bucket_labels};
+use crate::config::Settings;
+use crate::decode::{auto_decode_labels, name_label_values};
+use crate::filter::SourceFilter;
+use crate::reboot::reboot_alerts;
+use crate::row_errors::RowErrorReport;
 use crate::sanitize::{
     clean_alert_name, greedy_truncate_labels_prefix, greedy_truncate_labels_suffix,
 };
 use anyhow::{anyhow, bail};
 use itertools::Itertools;
 use log::warn;
-use serde::Serialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::postgres::PgRow;
 use sqlx::{Column, Row};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::Display;
 use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
 use std::str::FromStr;
 use time::{Duration, OffsetDateTime, PrimitiveDateTime};
 
-const DROP_COLUMNS: &[&str] = &["mib", "oid", "source", "version", "sysUpTime.0", "host"];
+pub const FIRST_TIME_COLUMN: &str = "_first_time";
+pub const OCCURRENCES_COLUMN: &str = "_occurrences";
+// received alerts have no table to aggregate them, past this their occurrences are only counted
+const MAX_RECEIVED_TIMES: usize = 1000;
 
-#[derive(Debug, Clone, Eq, Serialize)]
+#[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 pub struct Alert {
     hash: u64,
     severity: Severity,
     community: String,
     name: String,
     times: Vec<OffsetDateTime>,
+    // occurrences aggregated by the database whose timestamps were never fetched
+    #[serde(default)]
+    folded_occurrences: usize,
     labels: BTreeMap<String, String>,
+    // left out of the identity so jittering varbinds don't split the alert, the newest value wins
+    #[serde(default)]
+    volatile_labels: BTreeMap<String, String>,
+    source: Option<IpAddr>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize)]
@@ -32,6 +50,10 @@ pub enum Severity {
     Critical = 2,
 }
 
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Critical, Severity::Warning, Severity::Info];
+}
+
 impl Display for Severity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
@@ -44,6 +66,13 @@ impl Display for Severity {
     }
 }
 
+impl<'de> Deserialize<'de> for Severity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Severity::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 impl FromStr for Severity {
     type Err = anyhow::Error;
 
@@ -81,15 +110,67 @@ impl Alert {
             community,
             name,
             times,
+            folded_occurrences: 0,
             labels,
+            volatile_labels: BTreeMap::new(),
+            source: None,
         };
+        alert.rehash();
+
+        alert
+    }
 
+    fn rehash(&mut self) {
         let mut hasher = DefaultHasher::default();
-        Hash::hash(&alert, &mut hasher);
-        let hash = hasher.finish();
-        alert.hash = hash;
+        Hash::hash(self, &mut hasher);
+        self.hash = hasher.finish();
+    }
 
-        alert
+    pub fn add_label(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.labels.insert(name.into(), value.into());
+        self.rehash();
+    }
+
+    pub fn with_source(mut self, source: Option<IpAddr>) -> Alert {
+        self.source = source;
+        self
+    }
+
+    pub fn with_agent_address(mut self, source: Option<IpAddr>, label: Option<&str>) -> Alert {
+        if let (Some(label), Some(source)) = (label, source) {
+            self.add_label(label, source.to_string());
+        }
+        self.with_source(source)
+    }
+
+    pub fn with_bucketed_labels(mut self, rules: &[BucketRule]) -> Alert {
+        bucket_labels(rules, &mut self.labels);
+        self.rehash();
+        self
+    }
+
+    pub fn exclude_from_identity(mut self, names: &[String]) -> Alert {
+        for name in names {
+            if let Some(value) = self.labels.remove(name) {
+                self.volatile_labels.insert(name.clone(), value);
+            }
+        }
+        self.rehash();
+        self
+    }
+
+    pub fn source(&self) -> Option<IpAddr> {
+        self.source
+    }
+
+    pub fn from_occurrence(
+        name: String,
+        community: String,
+        time: OffsetDateTime,
+        mut labels: BTreeMap<String, String>,
+    ) -> Alert {
+        let severity = extract_severity(&mut labels).unwrap_or(Severity::Critical);
+        Alert::new(name, severity, community, BTreeSet::from([time]), labels)
     }
 
     pub fn earliest(&self) -> OffsetDateTime {
@@ -116,13 +197,21 @@ impl Alert {
         &self.name
     }
 
-    pub fn pretty_labels(&self) -> BTreeMap<String, String> {
+    pub fn pretty_labels(&self, settings: &Settings) -> BTreeMap<String, String> {
         let mut labels = self.labels.clone();
         _ = greedy_truncate_labels_prefix(&mut labels);
         _ = greedy_truncate_labels_suffix(&mut labels);
+        if settings.auto_decode_values() {
+            auto_decode_labels(&mut labels);
+        }
+        name_label_values(&mut labels, settings.value_names());
         labels
     }
 
+    pub fn volatile_labels(&self) -> &BTreeMap<String, String> {
+        &self.volatile_labels
+    }
+
     pub fn raw_labels(&self) -> &BTreeMap<String, String> {
         &self.labels
     }
@@ -135,21 +224,76 @@ impl Alert {
         &self.times
     }
 
+    pub fn count(&self) -> usize {
+        self.times.len() + self.folded_occurrences
+    }
+
+    pub fn retain_times(mut self, keep: impl Fn(OffsetDateTime) -> bool) -> Option<Alert> {
+        self.times.retain(|t| keep(*t));
+        (!self.times.is_empty()).then_some(self)
+    }
+
+    // only the first and last occurrence of an aggregate are known, the rest is counted
+    fn with_aggregate(mut self, first: OffsetDateTime, count: usize) -> Alert {
+        self.times.insert(0, first);
+        self.times.dedup();
+        self.folded_occurrences = count.saturating_sub(self.times.len());
+        self
+    }
+
+    // like an aggregate, the first occurrence and the newest ones are kept and the rest is counted
+    fn fold_times(&mut self, max: usize) {
+        let excess = self.times.len().saturating_sub(max.max(2));
+        if excess > 0 {
+            self.times.drain(1..=excess);
+            self.folded_occurrences += excess;
+        }
+    }
+
+    pub fn occurrence_histogram(&self, buckets: usize) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 || self.times.is_empty() {
+            return histogram;
+        }
+
+        let earliest = self.earliest();
+        let span = self.latest() - earliest;
+        if span.is_zero() {
+            histogram[buckets - 1] = self.times.len();
+            return histogram;
+        }
+
+        for time in &self.times {
+            let offset = (*time - earliest) / span;
+            let bucket = ((offset * buckets as f64) as usize).min(buckets - 1);
+            histogram[bucket] += 1;
+        }
+
+        histogram
+    }
+
     pub fn iter_intervals(&self) -> impl Iterator<Item = Duration> {
         self.times.windows(2).map(|w| w[1] - w[0])
     }
 
     pub fn interval_min(&self) -> Option<Duration> {
+        if self.folded_occurrences > 0 {
+            return None;
+        }
         self.iter_intervals().min()
     }
 
     pub fn interval_avg(&self) -> Option<Duration> {
-        self.iter_intervals()
-            .sum1()
-            .map(|sum: Duration| sum / self.times.windows(2).count() as f32)
+        if self.count() < 2 {
+            return None;
+        }
+        Some((self.latest() - self.earliest()) / (self.count() - 1) as f32)
     }
 
     pub fn interval_max(&self) -> Option<Duration> {
+        if self.folded_occurrences > 0 {
+            return None;
+        }
         self.iter_intervals().max()
     }
 
@@ -180,42 +324,107 @@ impl PartialEq for Alert {
     }
 }
 
-pub fn map_traps_to_alerts(traps: &[PgRow]) -> HashSet<Alert> {
-    let raw_alerts = traps.iter().map(TryInto::try_into).filter_map(|r| match r {
+pub fn map_traps_to_alerts(
+    traps: &[PgRow],
+    settings: &Settings,
+    errors: &mut RowErrorReport,
+) -> HashSet<Alert> {
+    let raw_alerts = valid_alerts(
+        traps
+            .iter()
+            .map(|row| (Alert::from_row(row, settings), row)),
+        settings.source_filter(),
+        errors,
+    );
+
+    if settings.reboot_detection() {
+        return generate_alerts(raw_alerts.chain(reboot_alerts(traps, settings)));
+    }
+    generate_alerts(raw_alerts)
+}
+
+// aggregated rows carry one alert each, uptimes are the raw rows reboot detection needs
+pub fn map_aggregates_to_alerts(
+    aggregates: &[PgRow],
+    uptimes: &[PgRow],
+    settings: &Settings,
+    errors: &mut RowErrorReport,
+) -> HashSet<Alert> {
+    let raw_alerts = valid_alerts(
+        aggregates
+            .iter()
+            .map(|row| (Alert::from_aggregate_row(row, settings), row)),
+        settings.source_filter(),
+        errors,
+    );
+
+    if settings.reboot_detection() {
+        return generate_alerts(raw_alerts.chain(reboot_alerts(uptimes, settings)));
+    }
+    generate_alerts(raw_alerts)
+}
+
+fn valid_alerts<'a>(
+    rows: impl Iterator<Item = (anyhow::Result<Alert>, &'a PgRow)>,
+    filter: &SourceFilter,
+    errors: &mut RowErrorReport,
+) -> impl Iterator<Item = Alert> {
+    rows.filter_map(|(r, row)| match r {
         Ok(alert) => Some(alert),
         Err(e) => {
-            warn!("Invalid alert database row: {e}");
+            errors.record(&e, row);
             None
         }
-    });
-
-    generate_alerts(raw_alerts)
+    })
+    .filter_map(|alert| filter.apply(alert))
 }
 
-impl TryFrom<&PgRow> for Alert {
-    type Error = anyhow::Error;
+impl Alert {
+    pub fn from_aggregate_row(row: &PgRow, settings: &Settings) -> anyhow::Result<Alert> {
+        let first: PrimitiveDateTime = row.try_get(FIRST_TIME_COLUMN)?;
+        let occurrences: i64 = row.try_get(OCCURRENCES_COLUMN)?;
+
+        Ok(
+            Alert::from_row(row, settings)?
+                .with_aggregate(first.assume_utc(), occurrences as usize),
+        )
+    }
 
-    fn try_from(row: &PgRow) -> Result<Self, Self::Error> {
+    pub fn from_row<R: TrapRow>(row: &R, settings: &Settings) -> anyhow::Result<Alert> {
         let mut name: Option<String> = None;
         let mut labels = BTreeMap::new();
         let mut time: Option<PrimitiveDateTime> = None;
         let mut community: Option<String> = None;
+        let mut source: Option<IpAddr> = None;
+
+        for (ordinal, column) in row.column_names().into_iter().enumerate() {
+            if matches!(column, "source" | "host") && source.is_none() {
+                source = row
+                    .text(ordinal)
+                    .ok()
+                    .flatten()
+                    .and_then(|s| parse_source_address(&s));
+            }
 
-        for col in row.columns() {
-            if DROP_COLUMNS.contains(&col.name()) {
+            if settings.drop_columns().iter().any(|c| c == column) {
                 continue;
             }
 
-            match col.name() {
-                "time" => time = Some(row.try_get(col.ordinal())?),
-                "name" => name = Some(row.try_get(col.ordinal())?),
-                "community" => community = Some(row.try_get(col.ordinal())?),
+            match column {
+                "time" => time = Some(row.time(ordinal)?),
+                "name" => name = row.text(ordinal)?,
+                "community" => community = row.text(ordinal)?,
+                FIRST_TIME_COLUMN | OCCURRENCES_COLUMN => {}
                 _ => {
-                    if labels.contains_key(col.name()) {
+                    let key = AlertmanagerAlert::collision_safe_label(
+                        settings,
+                        settings.mapped_label(column),
+                    );
+                    if labels.contains_key(&key) {
                         continue;
                     }
 
-                    let Some(value) = row.try_get::<'_, Option<String>, _>(col.ordinal())? else {
+                    let Some(value) = row.text(ordinal)? else {
                         continue; // null value in column means it's a label for a different trap
                     };
 
@@ -223,18 +432,25 @@ impl TryFrom<&PgRow> for Alert {
                         continue; // empty values are kind of useless
                     }
 
-                    let key = col.name().to_owned();
-
                     labels.insert(key, value);
                 }
             }
         }
 
-        let Some(name) = name else {
+        // some snmptrapd-sql setups leave the name empty, another column may still identify the trap
+        let Some(name) = name
+            .filter(|n| !n.is_empty())
+            .or_else(|| fallback_value(row, settings.name_fallback_columns()))
+        else {
             bail!("No name in database row found for alert");
         };
 
-        let Some(community) = community else {
+        let fallback = settings.community_fallback();
+        let Some(community) = community
+            .filter(|c| !c.is_empty())
+            .or_else(|| fallback_value(row, fallback.columns()))
+            .or_else(|| fallback.value().map(str::to_string))
+        else {
             bail!("No community in database row found for alert");
         };
 
@@ -242,19 +458,54 @@ impl TryFrom<&PgRow> for Alert {
             bail!("No time in database row found for alert");
         };
 
-        let severity = extract_severity(&mut labels).unwrap_or(Severity::Critical);
-        let time = time.assume_utc();
+        Ok(
+            Alert::from_occurrence(name, community, time.assume_utc(), labels)
+                .with_agent_address(source, settings.instance_label())
+                .with_bucketed_labels(settings.label_buckets())
+                .exclude_from_identity(settings.identity_exclude_labels()),
+        )
+    }
+}
 
-        Ok(Alert::new(
-            name,
-            severity,
-            community,
-            BTreeSet::from([time]),
-            labels,
-        ))
+fn fallback_value<R: TrapRow>(row: &R, columns: &[String]) -> Option<String> {
+    let names = row.column_names();
+    columns.iter().find_map(|column| {
+        let ordinal = names.iter().position(|name| name == column)?;
+        row.text(ordinal).ok().flatten().filter(|v| !v.is_empty())
+    })
+}
+
+// what mapping needs from a row of the trap table, so alerts can be mapped without Postgres
+pub trait TrapRow {
+    fn column_names(&self) -> Vec<&str>;
+    fn time(&self, ordinal: usize) -> anyhow::Result<PrimitiveDateTime>;
+    fn text(&self, ordinal: usize) -> anyhow::Result<Option<String>>;
+}
+
+impl TrapRow for PgRow {
+    fn column_names(&self) -> Vec<&str> {
+        self.columns().iter().map(|c| c.name()).collect()
+    }
+
+    fn time(&self, ordinal: usize) -> anyhow::Result<PrimitiveDateTime> {
+        Ok(self.try_get(ordinal)?)
+    }
+
+    fn text(&self, ordinal: usize) -> anyhow::Result<Option<String>> {
+        Ok(self.try_get(ordinal)?)
     }
 }
 
+pub fn parse_source_address(source: &str) -> Option<IpAddr> {
+    if let Ok(ip) = source.trim().parse() {
+        return Some(ip);
+    }
+
+    let start = source.find('[')? + 1;
+    let end = start + source[start..].find(']')?;
+    source[start..end].parse().ok()
+}
+
 fn extract_severity(labels: &mut BTreeMap<String, String>) -> Option<Severity> {
     const SEVERITY: &[&str] = &["severity"];
     let (k, v) = labels.iter().find(|(k, _)| {
@@ -276,20 +527,170 @@ fn extract_severity(labels: &mut BTreeMap<String, String>) -> Option<Severity> {
     Some(severity)
 }
 
-fn generate_alerts(raw_alerts: impl IntoIterator<Item = Alert>) -> HashSet<Alert> {
+pub fn generate_alerts(raw_alerts: impl IntoIterator<Item = Alert>) -> HashSet<Alert> {
     let mut alerts = HashSet::new();
 
     for alert in raw_alerts {
-        let entry = alerts.take(&alert);
-        match entry {
-            None => alerts.insert(alert),
-            Some(mut existing) => {
-                existing.times.extend(alert.times);
-                existing.times.sort();
-                alerts.insert(existing)
-            }
-        };
+        merge_alert(&mut alerts, alert);
     }
 
     alerts
 }
+
+pub fn merge_alert(alerts: &mut HashSet<Alert>, alert: Alert) {
+    let merged = merged_alert(alerts, alert);
+    alerts.insert(merged);
+}
+
+// received alerts are merged for as long as the device keeps sending, so their times are capped
+pub fn merge_received_alert(alerts: &mut HashSet<Alert>, alert: Alert) {
+    let mut merged = merged_alert(alerts, alert);
+    merged.fold_times(MAX_RECEIVED_TIMES);
+    alerts.insert(merged);
+}
+
+fn merged_alert(alerts: &mut HashSet<Alert>, alert: Alert) -> Alert {
+    let Some(mut existing) = alerts.take(&alert) else {
+        return alert;
+    };
+    if alert.latest() > existing.latest() {
+        existing.volatile_labels = alert.volatile_labels;
+    }
+    existing.times.extend(alert.times);
+    existing.times.sort();
+    existing.folded_occurrences += alert.folded_occurrences;
+    existing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::{
+        Alert, Severity, generate_alerts, merge_received_alert, parse_source_address,
+    };
+    use std::collections::HashSet;
+    use std::collections::{BTreeMap, BTreeSet};
+    use time::OffsetDateTime;
+    use time::ext::NumericalDuration;
+
+    #[test]
+    fn histogram_spreads_occurrences() {
+        let now = OffsetDateTime::now_utc();
+        let times = BTreeSet::from([
+            now,
+            now + 1.seconds(),
+            now + 5.seconds(),
+            now + 10.seconds(),
+        ]);
+        let alert = Alert::new(
+            "testAlert".to_string(),
+            Severity::Info,
+            "public".to_string(),
+            times,
+            BTreeMap::new(),
+        );
+
+        assert_eq!(alert.occurrence_histogram(5), vec![2, 0, 1, 0, 1]);
+        assert_eq!(alert.occurrence_histogram(0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn aggregate_counts_folded_occurrences() {
+        let now = OffsetDateTime::now_utc();
+        let alert = Alert::new(
+            "testAlert".to_string(),
+            Severity::Info,
+            "public".to_string(),
+            BTreeSet::from([now]),
+            BTreeMap::new(),
+        )
+        .with_aggregate(now - 30.seconds(), 4);
+
+        assert_eq!(alert.count(), 4);
+        assert_eq!(alert.earliest(), now - 30.seconds());
+        assert_eq!(alert.interval_avg(), Some(10.seconds()));
+        assert_eq!(alert.interval_min(), None);
+    }
+
+    #[test]
+    fn source_address_parsing() {
+        assert_eq!(
+            parse_source_address("UDP: [10.1.2.3]:50312->[10.0.0.1]:162"),
+            Some("10.1.2.3".parse().unwrap())
+        );
+        assert_eq!(
+            parse_source_address("2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(parse_source_address("router.example.com"), None);
+    }
+
+    #[test]
+    fn volatile_labels_merge() {
+        let now = OffsetDateTime::now_utc();
+        let occurrence = |time: OffsetDateTime, uptime: &str| {
+            Alert::from_occurrence(
+                "linkDown".to_string(),
+                "public".to_string(),
+                time,
+                BTreeMap::from([
+                    ("ifIndex".to_string(), "3".to_string()),
+                    ("sysUpTime".to_string(), uptime.to_string()),
+                ]),
+            )
+            .exclude_from_identity(&["sysUpTime".to_string()])
+        };
+
+        let alerts = generate_alerts([
+            occurrence(now, "1200"),
+            occurrence(now - 10.seconds(), "200"),
+        ]);
+        assert_eq!(alerts.len(), 1);
+        let alert = alerts.iter().next().unwrap();
+        assert_eq!(alert.count(), 2);
+        assert!(!alert.raw_labels().contains_key("sysUpTime"));
+        assert_eq!(alert.volatile_labels["sysUpTime"], "1200");
+    }
+
+    #[test]
+    fn received_times_are_folded() {
+        let now = OffsetDateTime::now_utc();
+        let mut received = HashSet::new();
+        for i in 0..1500 {
+            merge_received_alert(
+                &mut received,
+                Alert::from_occurrence(
+                    "linkDown".to_string(),
+                    "public".to_string(),
+                    now + i.seconds(),
+                    BTreeMap::new(),
+                ),
+            );
+        }
+
+        let alert = received.iter().next().unwrap();
+        assert_eq!(alert.count(), 1500);
+        assert_eq!(alert.times().len(), 1000);
+        assert_eq!(alert.earliest(), now);
+        assert_eq!(alert.latest(), now + 1499.seconds());
+    }
+
+    #[test]
+    fn alert_serde_roundtrip() {
+        let alert = Alert::from_occurrence(
+            "linkDown".to_string(),
+            "public".to_string(),
+            OffsetDateTime::now_utc(),
+            BTreeMap::from([("severity".to_string(), "warning".to_string())]),
+        )
+        .with_source(parse_source_address("10.0.0.1"));
+
+        let json = serde_json::to_string(&alert).unwrap();
+        let restored: Alert = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, alert);
+        assert_eq!(restored.hash(), alert.hash());
+        assert_eq!(restored.severity(), Severity::Warning);
+        assert_eq!(restored.times(), alert.times());
+        assert_eq!(restored.source(), alert.source());
+    }
+}
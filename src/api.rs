@@ -0,0 +1,338 @@
+use crate::alertmanager::RelayStatus;
+use crate::auth::{API_KEY_HEADER, client_ip};
+use crate::changes::{AlertChangeSet, AlertChanges};
+use crate::config::CONFIG;
+use crate::enrichment::AlertEnrichment;
+use crate::identity_preview::{IdentityRules, MergePreview};
+use crate::notifier::NotifierStats;
+use crate::reload_diff::{EnrichmentReloader, ReloadDiff};
+use crate::row_errors::RowErrorReport;
+use crate::status::Status;
+use crate::summary::{Summary, Wallboard};
+use crate::trap_db::{DeadLetterEntry, TrapDb, TrapSchema};
+use crate::trap_search::{TrapSearch, TrapSearchPage};
+use crate::web::{
+    AlertView, LabelStages, cache_control, conditional_json, find_label_stages, sorted_alert_views,
+};
+use crate::webhook::IncomingAlerts;
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, post};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::openapi::{OpenApi as OpenApiDoc, Server};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        summary_api,
+        status_api,
+        row_errors_api,
+        dead_letters_api,
+        wallboard_api,
+        alerts_api,
+        alert_changes_api,
+        ingest_alerts_api,
+        label_stages_api,
+        trap_search_api,
+        schema_api,
+        identity_preview_api,
+        reload_diff_api,
+        crate::web::clear_alert,
+        crate::web::snooze_alert
+    ),
+    modifiers(&ApiKeySecurity)
+)]
+struct ApiDoc;
+
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+    }
+}
+
+pub fn openapi() -> OpenApiDoc {
+    let mut doc = ApiDoc::openapi();
+    if !CONFIG.web_path_prefix().is_empty() {
+        doc.servers = Some(vec![Server::new(CONFIG.web_path_prefix())]);
+    }
+    doc
+}
+
+#[utoipa::path(responses((status = 200, body = Summary)))]
+#[get("/api/summary")]
+async fn summary_api(db: Data<TrapDb>, relay_status: Data<RelayStatus>) -> impl Responder {
+    Json(Summary::collect(&db, &relay_status).await)
+        .customize()
+        .insert_header(cache_control())
+}
+
+#[utoipa::path(responses((status = 200, body = Status)))]
+#[get("/api/status")]
+async fn status_api(
+    db: Data<TrapDb>,
+    relay_status: Data<RelayStatus>,
+    notifier_stats: Data<NotifierStats>,
+    enrichment: Data<AlertEnrichment>,
+) -> impl Responder {
+    Json(Status::collect(&db, &relay_status, &notifier_stats, &enrichment).await)
+}
+
+#[utoipa::path(responses((status = 200, body = RowErrorReport)))]
+#[get("/api/errors")]
+async fn row_errors_api(db: Data<TrapDb>) -> impl Responder {
+    Json(db.row_errors().await.clone())
+}
+
+const DEAD_LETTER_LIMIT: i64 = 500;
+
+#[utoipa::path(responses(
+    (status = 200, body = [DeadLetterEntry]),
+    (status = 404, description = "Dead-lettering is not enabled"),
+    (status = 500, description = "Database error while reading dead letters"),
+))]
+#[get("/api/dead-letters")]
+async fn dead_letters_api(db: Data<TrapDb>) -> HttpResponse {
+    if !db.settings().dead_letter() {
+        return HttpResponse::NotFound().body("Dead-lettering is not enabled");
+    }
+
+    match db.dead_letters(DEAD_LETTER_LIMIT).await {
+        Ok(letters) => HttpResponse::Ok().json(letters),
+        Err(e) => {
+            error!("Failed to read dead letters: {e}");
+            HttpResponse::InternalServerError().body("Failed to read dead letters")
+        }
+    }
+}
+
+#[utoipa::path(params(TrapSearch), responses(
+    (status = 200, body = TrapSearchPage),
+    (status = 400, description = "Unknown column or empty time range"),
+    (status = 500, description = "Database error while searching, e.g. an invalid pattern"),
+))]
+#[get("/api/traps/search")]
+async fn trap_search_api(db: Data<TrapDb>, Query(search): Query<TrapSearch>) -> HttpResponse {
+    let columns = match db.trap_columns().await {
+        Ok(columns) => columns,
+        Err(e) => {
+            error!("Failed to read trap columns: {e}");
+            return HttpResponse::InternalServerError().body("Failed to search traps");
+        }
+    };
+    if let Err(reason) = search.validate(&columns) {
+        return HttpResponse::BadRequest().body(reason);
+    }
+
+    match db.search_traps(&search, &columns).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => {
+            error!("Failed to search traps: {e}");
+            HttpResponse::InternalServerError().body(format!("Failed to search traps: {e}"))
+        }
+    }
+}
+
+#[utoipa::path(responses(
+    (status = 200, body = TrapSchema),
+    (status = 500, description = "Database error while scanning the trap table"),
+))]
+#[get("/api/schema")]
+async fn schema_api(db: Data<TrapDb>) -> HttpResponse {
+    match db.schema_report().await {
+        Ok(schema) => HttpResponse::Ok().json(schema),
+        Err(e) => {
+            error!("Failed to summarize the trap table: {e}");
+            HttpResponse::InternalServerError()
+                .body(format!("Failed to summarize the trap table: {e}"))
+        }
+    }
+}
+
+// a dry run, nothing is changed but the rows are read like for a refresh
+#[utoipa::path(
+    request_body = IdentityRules,
+    responses(
+        (status = 200, body = MergePreview),
+        (status = 500, description = "Database error while reading the trap rows"),
+    ),
+    security(("api_key" = []))
+)]
+#[post("/api/identity/preview")]
+async fn identity_preview_api(db: Data<TrapDb>, Json(rules): Json<IdentityRules>) -> HttpResponse {
+    match db.preview_identity(&rules).await {
+        Ok(preview) => HttpResponse::Ok().json(preview),
+        Err(e) => {
+            error!("Failed to preview identity rules: {e}");
+            HttpResponse::InternalServerError()
+                .body(format!("Failed to preview identity rules: {e}"))
+        }
+    }
+}
+
+#[utoipa::path(responses(
+    (status = 200, body = ReloadDiff),
+    (status = 404, description = "No enrichment reload was recorded yet"),
+))]
+#[get("/api/enrichment/reload-diff")]
+async fn reload_diff_api(reloader: Data<EnrichmentReloader>) -> HttpResponse {
+    match reloader.last_diff() {
+        Some(diff) => HttpResponse::Ok().json(&*diff),
+        None if !CONFIG.record_reload_diffs() => {
+            HttpResponse::NotFound().body("Recording reload diffs is not enabled")
+        }
+        None => HttpResponse::NotFound().body("No enrichment reload was recorded yet"),
+    }
+}
+
+const WALLBOARD_LIMIT_DEFAULT: usize = 10;
+const WALLBOARD_LIMIT_MAX: usize = 100;
+
+#[derive(Deserialize, IntoParams)]
+struct WallboardQuery {
+    // how many of the most severe alerts to list, at most 100
+    limit: Option<usize>,
+}
+
+#[utoipa::path(params(WallboardQuery), responses(
+    (status = 200, body = Wallboard),
+    (status = 304, description = "Wallboard unchanged since the ETag sent in If-None-Match"),
+))]
+#[get("/api/wallboard")]
+async fn wallboard_api(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    Query(query): Query<WallboardQuery>,
+) -> HttpResponse {
+    let limit = query
+        .limit
+        .unwrap_or(WALLBOARD_LIMIT_DEFAULT)
+        .min(WALLBOARD_LIMIT_MAX);
+    conditional_json(&req, &Wallboard::collect(&db, limit).await)
+}
+
+#[utoipa::path(responses(
+    (status = 200, body = [AlertView]),
+    (status = 304, description = "Alerts unchanged since the ETag sent in If-None-Match"),
+))]
+#[get("/api/alerts")]
+async fn alerts_api(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    relay_status: Data<RelayStatus>,
+    enrichment: Data<AlertEnrichment>,
+) -> HttpResponse {
+    conditional_json(
+        &req,
+        &sorted_alert_views(&db, &relay_status, &enrichment).await,
+    )
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ChangesQuery {
+    // the cursor of the previous response, all alerts are returned without one
+    since: Option<String>,
+    // seconds to wait for a change before answering with an empty set, at most 60
+    wait: Option<u64>,
+}
+
+#[utoipa::path(params(ChangesQuery), responses((status = 200, body = AlertChangeSet)))]
+#[get("/api/alerts/changes")]
+async fn alert_changes_api(
+    db: Data<TrapDb>,
+    relay_status: Data<RelayStatus>,
+    enrichment: Data<AlertEnrichment>,
+    changes: Data<AlertChanges>,
+    Query(query): Query<ChangesQuery>,
+) -> impl Responder {
+    let wait = Duration::from_secs(query.wait.unwrap_or(0));
+    Json(
+        changes
+            .wait_for_changes(
+                &db,
+                &relay_status,
+                &enrichment,
+                query.since.as_deref(),
+                wait,
+            )
+            .await,
+    )
+}
+
+#[derive(Serialize, ToSchema)]
+struct IngestResult {
+    accepted: usize,
+    rejected: usize,
+}
+
+#[utoipa::path(
+    request_body = IncomingAlerts,
+    responses(
+        (status = 202, body = IngestResult),
+        (status = 400, description = "Alert payload could not be converted"),
+        (status = 401, description = "Invalid API key"),
+        (status = 403, description = "Missing or invalid CSRF token"),
+    ),
+    security(("api_key" = []))
+)]
+#[post("/api/alerts")]
+async fn ingest_alerts_api(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    Json(incoming): Json<IncomingAlerts>,
+) -> HttpResponse {
+    let alerts = match incoming.into_alerts() {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            warn!("Rejected webhook alerts: {e}");
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+    };
+
+    let source = client_ip(req.peer_addr(), req.headers());
+    let total = alerts.len();
+    let mut accepted = 0;
+    for alert in alerts {
+        if let Some(alert) = CONFIG.source_filter().apply(alert.with_source(source)) {
+            db.ingest(alert).await;
+            accepted += 1;
+        }
+    }
+
+    info!("Ingested {accepted} of {total} webhook alerts");
+    HttpResponse::Accepted().json(IngestResult {
+        accepted,
+        rejected: total - accepted,
+    })
+}
+
+#[utoipa::path(
+    params(("hash" = u64, Path, description = "Alert hash as listed by /api/alerts")),
+    responses(
+        (status = 200, body = LabelStages),
+        (status = 404, description = "No active alert with this hash"),
+    )
+)]
+#[get("/api/alerts/{hash}/labels")]
+async fn label_stages_api(
+    db: Data<TrapDb>,
+    enrichment: Data<AlertEnrichment>,
+    hash: Path<u64>,
+) -> HttpResponse {
+    match find_label_stages(&db, &enrichment, hash.into_inner()).await {
+        Some(Ok(stages)) => HttpResponse::Ok().json(stages),
+        Some(Err(e)) => {
+            error!("Failed to enrich alert for label stages: {e}");
+            HttpResponse::InternalServerError().body("Failed to enrich alert")
+        }
+        None => HttpResponse::NotFound().body("Alert not found"),
+    }
+}
@@ -0,0 +1,315 @@
+use crate::alerts::Alert;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures::TryStreamExt;
+use itertools::Itertools;
+use log::{debug, info, warn};
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::macros::format_description;
+
+const FILE_PREFIX: &str = "alerts-";
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// batches are written from blocking tasks, interleaved writes would corrupt the gzip members
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Hourly,
+    #[default]
+    Daily,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArchiveSettings {
+    dir: PathBuf,
+    #[serde(default)]
+    gzip: bool,
+    #[serde(default)]
+    rotation: Rotation,
+    max_files: Option<usize>,
+    s3: Option<S3Settings>,
+    // pipelines share the archive files, each record names the one it came from
+    #[serde(skip)]
+    pipeline: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct S3Settings {
+    bucket: String,
+    #[serde(default)]
+    prefix: String,
+    endpoint: Option<String>,
+    region: Option<String>,
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    #[serde(
+        default = "upload_interval_sec_default",
+        deserialize_with = "crate::config::interval_sec"
+    )]
+    upload_interval_sec: u64,
+    retention_days: Option<u64>,
+}
+
+fn upload_interval_sec_default() -> u64 {
+    3600
+}
+
+#[derive(Serialize)]
+struct ArchiveRecord<'a> {
+    #[serde(with = "time::serde::rfc3339")]
+    archived_at: OffsetDateTime,
+    reason: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pipeline: Option<&'a str>,
+    name: &'a str,
+    severity: String,
+    community: &'a str,
+    labels: &'a BTreeMap<String, String>,
+    #[serde(with = "time::serde::rfc3339")]
+    first_seen: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    last_seen: OffsetDateTime,
+    count: usize,
+}
+
+impl<'a> ArchiveRecord<'a> {
+    fn new(
+        alert: &'a Alert,
+        reason: &'a str,
+        pipeline: Option<&'a str>,
+        archived_at: OffsetDateTime,
+    ) -> Self {
+        ArchiveRecord {
+            archived_at,
+            reason,
+            pipeline,
+            name: alert.raw_name(),
+            severity: alert.severity().to_string(),
+            community: alert.community(),
+            labels: alert.raw_labels(),
+            first_seen: alert.earliest(),
+            last_seen: alert.latest(),
+            count: alert.count(),
+        }
+    }
+}
+
+impl ArchiveSettings {
+    pub fn for_pipeline(&self, pipeline: &str) -> ArchiveSettings {
+        ArchiveSettings {
+            pipeline: Some(pipeline.to_string()),
+            ..self.clone()
+        }
+    }
+
+    fn file_path(&self, now: OffsetDateTime) -> anyhow::Result<PathBuf> {
+        let stamp = match self.rotation {
+            Rotation::Hourly => now.format(format_description!("[year]-[month]-[day]T[hour]"))?,
+            Rotation::Daily => now.format(format_description!("[year]-[month]-[day]"))?,
+        };
+        let extension = if self.gzip { "ndjson.gz" } else { "ndjson" };
+        Ok(self.dir.join(format!("{FILE_PREFIX}{stamp}.{extension}")))
+    }
+
+    pub fn s3(&self) -> Option<&S3Settings> {
+        self.s3.as_ref()
+    }
+
+    fn archive_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        list_archives(&self.dir)
+    }
+
+    pub fn archive<'a>(
+        &self,
+        reason: &str,
+        alerts: impl IntoIterator<Item = &'a Alert>,
+    ) -> anyhow::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let mut lines = Vec::new();
+        for alert in alerts {
+            serde_json::to_writer(
+                &mut lines,
+                &ArchiveRecord::new(alert, reason, self.pipeline.as_deref(), now),
+            )?;
+            lines.push(b'\n');
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file_path(now)?)?;
+
+        // each batch becomes its own gzip member, which standard tools read as one stream
+        if self.gzip {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(&lines)?;
+            encoder.finish()?;
+        } else {
+            let mut file = file;
+            file.write_all(&lines)?;
+        }
+
+        if let Some(max_files) = self.max_files {
+            prune_archives(&self.dir, max_files)?;
+        }
+
+        Ok(())
+    }
+
+    // the file IO blocks, so it's moved off the async runtime
+    pub fn archive_logged(&self, reason: &'static str, alerts: Vec<Alert>) {
+        if alerts.is_empty() {
+            return;
+        }
+        let archive = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = archive.archive(reason, &alerts) {
+                warn!(
+                    "Failed to archive {reason} alerts to {:?}: {e}",
+                    archive.dir
+                );
+            }
+        });
+    }
+}
+
+fn list_archives(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(dir
+        .read_dir()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(FILE_PREFIX) && n.contains(".ndjson"))
+        })
+        .sorted()
+        .collect_vec())
+}
+
+fn prune_archives(dir: &Path, max_files: usize) -> anyhow::Result<()> {
+    let archives = list_archives(dir)?;
+
+    let excess = archives.len().saturating_sub(max_files);
+    for path in &archives[..excess] {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+pub struct S3Uploader {
+    archive: ArchiveSettings,
+    settings: S3Settings,
+    store: AmazonS3,
+}
+
+impl S3Uploader {
+    pub fn new(archive: ArchiveSettings, settings: S3Settings) -> anyhow::Result<Self> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&settings.bucket);
+        if let Some(endpoint) = &settings.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(region) = &settings.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(key_id) = &settings.access_key_id {
+            builder = builder.with_access_key_id(key_id);
+        }
+        if let Some(secret) = &settings.secret_access_key {
+            builder = builder.with_secret_access_key(secret);
+        }
+
+        Ok(S3Uploader {
+            archive,
+            store: builder.build()?,
+            settings,
+        })
+    }
+
+    pub async fn run_uploader_blocking(&self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.settings.upload_interval_sec));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.upload_archives().await {
+                warn!("Couldn't upload alert archives to S3: {e}");
+            }
+            if let Err(e) = self.apply_retention().await {
+                warn!("Couldn't apply S3 archive retention: {e}");
+            }
+        }
+    }
+
+    fn object_path(&self, file_name: &str) -> ObjectPath {
+        ObjectPath::from(format!("{}{file_name}", self.settings.prefix))
+    }
+
+    // the file for the current rotation period is still being appended to and is left for the next run
+    async fn upload_archives(&self) -> anyhow::Result<()> {
+        let current = self.archive.file_path(OffsetDateTime::now_utc())?;
+        for file in self.archive.archive_files()? {
+            if file == current {
+                continue;
+            }
+            let Some(file_name) = file.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+
+            let content = tokio::fs::read(&file).await?;
+            self.store
+                .put(&self.object_path(file_name), PutPayload::from(content))
+                .await?;
+            tokio::fs::remove_file(&file).await?;
+            info!("Uploaded alert archive {file_name} to S3");
+        }
+
+        Ok(())
+    }
+
+    async fn apply_retention(&self) -> anyhow::Result<()> {
+        let Some(retention_days) = self.settings.retention_days else {
+            return Ok(());
+        };
+
+        let cutoff = chrono::Utc::now() - Duration::from_secs(retention_days * SECONDS_PER_DAY);
+        let prefix = ObjectPath::from(self.settings.prefix.as_str());
+        let expired: Vec<_> = self
+            .store
+            .list(Some(&prefix))
+            .try_filter(|meta| futures::future::ready(meta.last_modified < cutoff))
+            .try_collect()
+            .await?;
+
+        for meta in expired {
+            self.store.delete(&meta.location).await?;
+            debug!("Deleted expired alert archive {} from S3", meta.location);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::config::CONFIG;
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::web::Path;
+use actix_web::{HttpResponse, get};
+use log::warn;
+use std::io::ErrorKind;
+use std::path::{Component, PathBuf};
+
+const STATIC_MAX_AGE_SEC: u32 = 3600;
+
+// built into the binary, so the UI works on networks without access to a CDN
+const EMBEDDED: &[(&str, &[u8])] = &[
+    ("app.js", include_bytes!("../assets/app.js")),
+    ("base.css", include_bytes!("../assets/base.css")),
+    ("favicon.svg", include_bytes!("../assets/favicon.svg")),
+];
+
+fn content_type(path: &std::path::Path) -> ContentType {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let mime = match extension.as_deref() {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    };
+    ContentType(mime.parse().unwrap())
+}
+
+fn cache_control() -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(STATIC_MAX_AGE_SEC),
+    ])
+}
+
+#[get("/assets/{name}")]
+async fn embedded_asset(name: Path<String>) -> HttpResponse {
+    match EMBEDDED.iter().find(|(n, _)| *n == name.as_str()) {
+        Some((name, content)) => HttpResponse::Ok()
+            .insert_header(content_type(std::path::Path::new(name)))
+            .insert_header(cache_control())
+            .body(*content),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// only plain path segments, `..` or an absolute path could leave the directory
+fn resolve(dir: &std::path::Path, requested: &str) -> Option<PathBuf> {
+    let requested = std::path::Path::new(requested);
+    if !requested
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(dir.join(requested))
+}
+
+// site-provided stylesheets, logos and scripts for customized templates
+#[get("/static/{path:.*}")]
+async fn static_asset(path: Path<String>) -> HttpResponse {
+    let Some(dir) = CONFIG.static_dir() else {
+        return HttpResponse::NotFound().finish();
+    };
+    let Some(file) = resolve(dir, &path) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    // a symlink pointing out of the directory is not served either
+    match (file.canonicalize(), dir.canonicalize()) {
+        (Ok(file), Ok(dir)) if file.starts_with(&dir) && file.is_file() => {}
+        _ => return HttpResponse::NotFound().finish(),
+    }
+
+    match tokio::fs::read(&file).await {
+        Ok(content) => HttpResponse::Ok()
+            .insert_header(content_type(&file))
+            .insert_header(cache_control())
+            .body(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            warn!("Failed to read static file {file:?}: {e}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assets::resolve;
+    use std::path::Path;
+
+    #[test]
+    fn stays_in_static_dir() {
+        let dir = Path::new("/srv/static");
+        assert_eq!(resolve(dir, "css/site.css"), Some(dir.join("css/site.css")));
+        assert_eq!(resolve(dir, "../secrets.yaml"), None);
+        assert_eq!(resolve(dir, "css/../../secrets.yaml"), None);
+        assert_eq!(resolve(dir, "/etc/passwd"), None);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::config::Settings;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use time::OffsetDateTime;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub action: String,
+    pub alert_hash: u64,
+    pub alert_name: String,
+    pub reason: String,
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(
+        action: &'static str,
+        alert_hash: u64,
+        alert_name: impl Into<String>,
+        reason: impl Into<String>,
+        actor: impl Into<String>,
+    ) -> Self {
+        AuditEntry {
+            time: OffsetDateTime::now_utc(),
+            action: action.to_string(),
+            alert_hash,
+            alert_name: alert_name.into(),
+            reason: reason.into(),
+            actor: actor.into(),
+            pipeline: None,
+        }
+    }
+
+    // pipelines write to the same audit log
+    pub fn with_pipeline(mut self, pipeline: Option<&str>) -> Self {
+        self.pipeline = pipeline.map(str::to_string);
+        self
+    }
+
+    pub async fn record(&self, settings: &Settings) {
+        info!(
+            target: "audit",
+            "{} {} ({}) by {}: {}",
+            self.action, self.alert_name, self.alert_hash, self.actor, self.reason
+        );
+
+        let Some(path) = settings.audit_log() else {
+            return;
+        };
+        if let Err(e) = self.append(path).await {
+            warn!("Failed to write audit log entry to {path:?}: {e}");
+        }
+    }
+
+    async fn append(&self, path: &Path) -> anyhow::Result<()> {
+        let line = serde_json::to_string(self)? + "\n";
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
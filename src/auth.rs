@@ -0,0 +1,187 @@
+use crate::config::CONFIG;
+use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::http::Method;
+use actix_web::http::header::{CONTENT_TYPE, HeaderMap};
+use actix_web::middleware::Next;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage};
+use ipnet::IpNet;
+use log::warn;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_FIELD: &str = "csrf_token";
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) fn is_valid_api_key(key: &[u8]) -> bool {
+    CONFIG
+        .api_keys()
+        .iter()
+        .any(|k| constant_time_eq(k.as_bytes(), key))
+}
+
+pub(crate) fn cookie_path() -> &'static str {
+    match CONFIG.web_path_prefix() {
+        "" => "/",
+        prefix => prefix,
+    }
+}
+
+// forwarded headers are only believed when they come from one of our own proxies
+pub(crate) fn client_ip(peer: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+    forwarded_client(peer?.ip(), headers, CONFIG.trusted_proxies())
+}
+
+// every proxy appends the address it got the request from, so the first untrusted one from the
+// right is the client. Anything left of it could have been made up by the client
+pub(crate) fn forwarded_client(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted: &[IpNet],
+) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let hops: Vec<&str> = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect();
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        let Ok(ip) = hop.trim().parse() else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+pub(crate) fn is_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+pub async fn mutation_guard(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if is_mutating(req.method()) {
+        if let Some(key) = req.headers().get(API_KEY_HEADER) {
+            if !is_valid_api_key(key.as_bytes()) {
+                warn!(
+                    "Rejected {} {} from {:?}: invalid API key",
+                    req.method(),
+                    req.path(),
+                    client_ip(req.peer_addr(), req.headers())
+                );
+                return Err(ErrorUnauthorized("Invalid API key"));
+            }
+            return next.call(req).await;
+        }
+
+        let Some(cookie) = req.cookie(CSRF_COOKIE) else {
+            warn!(
+                "Rejected {} {}: missing CSRF cookie",
+                req.method(),
+                req.path()
+            );
+            return Err(ErrorForbidden("Missing CSRF token"));
+        };
+
+        let submitted = match req.headers().get(CSRF_HEADER) {
+            Some(header) => Some(header.as_bytes().to_vec()),
+            None => csrf_form_field(&mut req).await?.map(String::into_bytes),
+        };
+
+        if !submitted.is_some_and(|t| constant_time_eq(cookie.value().as_bytes(), &t)) {
+            warn!(
+                "Rejected {} {}: CSRF token mismatch",
+                req.method(),
+                req.path()
+            );
+            return Err(ErrorForbidden("Invalid CSRF token"));
+        }
+
+        return next.call(req).await;
+    }
+
+    let existing = req.cookie(CSRF_COOKIE).map(|c| c.value().to_string());
+    let token = existing.clone().unwrap_or_else(generate_token);
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+
+    let mut res = next.call(req).await?;
+    if existing.is_none() {
+        let cookie = Cookie::build(CSRF_COOKIE, token)
+            .path(cookie_path())
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .finish();
+        res.response_mut().add_cookie(&cookie)?;
+    }
+
+    Ok(res)
+}
+
+async fn csrf_form_field(req: &mut ServiceRequest) -> Result<Option<String>, Error> {
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Ok(None);
+    }
+
+    let body = req.extract::<Bytes>().await?;
+    let mut fields: HashMap<String, String> =
+        serde_urlencoded::from_bytes(&body).unwrap_or_default();
+    req.set_payload(Payload::from(body));
+
+    Ok(fields.remove(CSRF_FIELD))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::forwarded_client;
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use ipnet::IpNet;
+
+    #[test]
+    fn forwarded_only_from_trusted_proxies() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_static("203.0.113.9, 198.51.100.7, 10.1.1.1"),
+        );
+
+        let client = |peer: &str| forwarded_client(peer.parse().unwrap(), &headers, &trusted);
+        assert_eq!(client("10.0.0.2"), "198.51.100.7".parse().ok());
+        assert_eq!(client("192.0.2.1"), "192.0.2.1".parse().ok());
+    }
+}
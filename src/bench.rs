@@ -0,0 +1,197 @@
+use crate::alertmanager::{AlertmanagerAlert, prepare_alert};
+use crate::alerts::{Alert, TrapRow, generate_alerts};
+use crate::config::Settings;
+use crate::enrichment::{AlertEnrichment, AlertEnrichmentFile};
+use anyhow::anyhow;
+use clap::Args;
+use itertools::Itertools;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use time::PrimitiveDateTime;
+use time::ext::NumericalDuration;
+use time::macros::datetime;
+
+// rules are spread over the trap names, so every alert is matched by a share of them
+const TRAP_NAMES: usize = 20;
+const START: PrimitiveDateTime = datetime!(2025-01-01 00:00);
+
+#[derive(Debug, Clone, Args)]
+pub struct BenchOptions {
+    #[arg(
+        long,
+        default_value_t = 100_000,
+        help = "How many synthetic traps to generate"
+    )]
+    traps: usize,
+    #[arg(
+        long,
+        default_value_t = 1_000,
+        help = "How many distinct alerts the traps deduplicate into"
+    )]
+    distinct: usize,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = [0, 10, 100],
+        help = "Enrichment rule counts to measure, comma separated"
+    )]
+    rules: Vec<usize>,
+}
+
+// a trap table row that only lives in memory
+struct SyntheticTrap {
+    time: PrimitiveDateTime,
+    columns: Vec<(&'static str, String)>,
+}
+
+impl TrapRow for SyntheticTrap {
+    fn column_names(&self) -> Vec<&str> {
+        std::iter::once("time")
+            .chain(self.columns.iter().map(|(name, _)| *name))
+            .collect()
+    }
+
+    fn time(&self, ordinal: usize) -> anyhow::Result<PrimitiveDateTime> {
+        match ordinal {
+            0 => Ok(self.time),
+            _ => Err(anyhow!("column {ordinal} is not a timestamp")),
+        }
+    }
+
+    fn text(&self, ordinal: usize) -> anyhow::Result<Option<String>> {
+        let (_, value) = self
+            .columns
+            .get(ordinal.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("column {ordinal} is not text"))?;
+        Ok(Some(value.clone()))
+    }
+}
+
+fn synthetic_traps(amount: usize, distinct: usize) -> Vec<SyntheticTrap> {
+    (0..amount)
+        .map(|i| {
+            let id = i % distinct.max(1);
+            SyntheticTrap {
+                time: START + (i as i64).seconds(),
+                columns: vec![
+                    ("name", format!("benchTrap{}", id % TRAP_NAMES)),
+                    ("community", "public".to_string()),
+                    ("source", format!("10.{}.{}.1", id / 256 % 256, id % 256)),
+                    ("port", id.to_string()),
+                    ("interface", format!("GigabitEthernet0/{id}")),
+                    ("status", "down".to_string()),
+                ],
+            }
+        })
+        .collect()
+}
+
+fn synthetic_rules(amount: usize) -> anyhow::Result<AlertEnrichment> {
+    let mut rules = String::from("alerts:\n");
+    for i in 0..amount {
+        writeln!(rules, "- name: benchTrap{}", i % TRAP_NAMES)?;
+        writeln!(rules, "  labels:")?;
+        writeln!(rules, "    rule_{i}: \"{{{{ labels.port }}}}\"")?;
+        writeln!(rules, "  annotations:")?;
+        writeln!(rules, "    summary: \"{{{{ labels.interface }}}} is down\"")?;
+        writeln!(rules, "  drop_labels: []")?;
+    }
+
+    let mut enrichment = AlertEnrichment::new();
+    if amount > 0 {
+        enrichment.load_files([AlertEnrichmentFile::parse(&rules)?])?;
+    }
+    Ok(enrichment)
+}
+
+fn map_traps(traps: &[SyntheticTrap], settings: &Settings) -> anyhow::Result<Vec<Alert>> {
+    traps
+        .iter()
+        .map(|trap| Alert::from_row(trap, settings))
+        .filter_map_ok(|alert| settings.source_filter().apply(alert))
+        .collect()
+}
+
+fn enrich(
+    alerts: &[&Alert],
+    enrichment: &AlertEnrichment,
+    settings: &Settings,
+) -> anyhow::Result<usize> {
+    let mut prepared = alerts
+        .iter()
+        .map(|alert| AlertmanagerAlert::from_alert(alert, settings))
+        .collect_vec();
+    enrichment.set_active_alerts(&prepared);
+    for alert in &mut prepared {
+        prepare_alert(alert, enrichment, settings)?;
+    }
+    Ok(prepared.len())
+}
+
+fn per_sec(amount: usize, elapsed: Duration) -> f64 {
+    amount as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+// nothing touches the database, what's measured is the CPU time of each stage
+pub fn run(options: &BenchOptions, settings: &Settings) -> anyhow::Result<()> {
+    let traps = synthetic_traps(options.traps, options.distinct);
+
+    let started = Instant::now();
+    let mapped = map_traps(&traps, settings)?;
+    let mapping = started.elapsed();
+    println!(
+        "mapping: {} rows in {:.3}s, {:.0} rows/s",
+        traps.len(),
+        mapping.as_secs_f64(),
+        per_sec(traps.len(), mapping)
+    );
+
+    let started = Instant::now();
+    let alerts = generate_alerts(mapped);
+    let dedup = started.elapsed();
+    println!(
+        "dedup: {} rows into {} alerts in {:.3}s, {:.0} rows/s",
+        traps.len(),
+        alerts.len(),
+        dedup.as_secs_f64(),
+        per_sec(traps.len(), dedup)
+    );
+
+    let alerts = alerts.iter().collect_vec();
+    println!("rules  enrich_sec  alerts/s  pipeline rows/s");
+    for &rules in &options.rules {
+        let enrichment = synthetic_rules(rules)?;
+        let started = Instant::now();
+        let enriched = enrich(&alerts, &enrichment, settings)?;
+        let enriching = started.elapsed();
+        println!(
+            "{rules:>5}  {:>10.3}  {:>8.0}  {:>15.0}",
+            enriching.as_secs_f64(),
+            per_sec(enriched, enriching),
+            per_sec(traps.len(), mapping + dedup + enriching)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::generate_alerts;
+    use crate::bench::{enrich, map_traps, synthetic_rules, synthetic_traps};
+    use crate::config::test_settings;
+
+    #[test]
+    fn synthetic_pipeline() {
+        let settings = test_settings("");
+
+        let traps = synthetic_traps(100, 10);
+        let alerts = generate_alerts(map_traps(&traps, &settings).unwrap());
+        assert_eq!(alerts.len(), 10);
+        assert!(alerts.iter().all(|a| a.count() == 10));
+
+        let enrichment = synthetic_rules(40).unwrap();
+        assert_eq!(enrichment.count(), 40);
+        let alerts = alerts.iter().collect::<Vec<_>>();
+        assert_eq!(enrich(&alerts, &enrichment, &settings).unwrap(), 10);
+    }
+}
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// either equally wide bins or explicit boundaries, like `width: 5` or `bounds: [10, 100, 1000]`
+#[derive(Debug, Clone, Deserialize)]
+pub struct BucketRule {
+    label: String,
+    width: Option<f64>,
+    #[serde(default)]
+    bounds: Vec<f64>,
+}
+
+impl BucketRule {
+    fn bucket(&self, value: f64) -> Option<String> {
+        if let Some(width) = self.width.filter(|w| *w > 0.0) {
+            let lower = (value / width).floor() * width;
+            return Some(format!("{lower}-{}", lower + width));
+        }
+
+        let upper = self.bounds.iter().position(|b| value < *b);
+        match upper {
+            _ if self.bounds.is_empty() => None,
+            Some(0) => Some(format!("<{}", self.bounds[0])),
+            Some(i) => Some(format!("{}-{}", self.bounds[i - 1], self.bounds[i])),
+            None => Some(format!(">={}", self.bounds[self.bounds.len() - 1])),
+        }
+    }
+
+    // the numbers a bucket stands for, as lower (inclusive) and upper (exclusive) bound
+    pub fn range_of(&self, bucket: &str) -> Option<(Option<f64>, Option<f64>)> {
+        if let Some(width) = self.width.filter(|w| *w > 0.0) {
+            // negative numbers have their own dash, try every split
+            return bucket.match_indices('-').find_map(|(i, _)| {
+                let lower: f64 = bucket[..i].parse().ok()?;
+                (self.bucket(lower)? == bucket).then_some((Some(lower), Some(lower + width)))
+            });
+        }
+
+        let edges = self.bounds.iter().map(|b| Some(*b));
+        let lowers = std::iter::once(None).chain(edges.clone());
+        let uppers = edges.chain(std::iter::once(None));
+        lowers.zip(uppers).find(|(lower, upper)| {
+            let probe = lower.or(upper.map(|u| u - 1.0));
+            probe.and_then(|p| self.bucket(p)).as_deref() == Some(bucket)
+        })
+    }
+}
+
+pub fn bucket_rule<'a>(rules: &'a [BucketRule], label: &str) -> Option<&'a BucketRule> {
+    rules.iter().find(|rule| rule.label == label)
+}
+
+// values that aren't numbers stay as they are
+pub fn bucket_labels(rules: &[BucketRule], labels: &mut BTreeMap<String, String>) {
+    for rule in rules {
+        let Some(value) = labels.get_mut(&rule.label) else {
+            continue;
+        };
+        if let Some(bucket) = value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+            .and_then(|number| rule.bucket(number))
+        {
+            *value = bucket;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bucketing::{bucket_labels, bucket_rule};
+    use crate::config::test_settings;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn buckets_numbers() {
+        let settings = test_settings(
+            r#"
+label_buckets:
+  - label: temperature
+    width: 5
+  - label: errorCount
+    bounds: [10, 100, 1000]
+"#,
+        );
+        let bucketed = |label: &str, value: &str| {
+            let mut labels = BTreeMap::from([(label.to_string(), value.to_string())]);
+            bucket_labels(settings.label_buckets(), &mut labels);
+            labels.remove(label).unwrap()
+        };
+
+        assert_eq!(bucketed("temperature", "47"), "45-50");
+        assert_eq!(bucketed("temperature", "-3"), "-5-0");
+        assert_eq!(bucketed("errorCount", "3"), "<10");
+        assert_eq!(bucketed("errorCount", "42"), "10-100");
+        assert_eq!(bucketed("errorCount", "5000"), ">=1000");
+        assert_eq!(bucketed("errorCount", "n/a"), "n/a");
+        assert_eq!(bucketed("ifIndex", "42"), "42");
+
+        let temperature = bucket_rule(settings.label_buckets(), "temperature").unwrap();
+        assert_eq!(temperature.range_of("-5-0"), Some((Some(-5.0), Some(0.0))));
+        let errors = bucket_rule(settings.label_buckets(), "errorCount").unwrap();
+        assert_eq!(errors.range_of("<10"), Some((None, Some(10.0))));
+        assert_eq!(errors.range_of(">=1000"), Some((Some(1000.0), None)));
+        assert_eq!(errors.range_of("n/a"), None);
+    }
+}
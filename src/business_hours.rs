@@ -0,0 +1,163 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::alerts::Severity;
+use crate::enrichment::is_full_match;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+fn weekdays_default() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err: Display>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn parsed_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err: Display>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BusinessHours {
+    #[serde(default = "weekdays_default", deserialize_with = "parsed_list")]
+    days: Vec<Weekday>,
+    #[serde(deserialize_with = "parsed")]
+    start: NaiveTime,
+    #[serde(deserialize_with = "parsed")]
+    end: NaiveTime,
+    #[serde(default, deserialize_with = "parsed_list")]
+    holidays: Vec<NaiveDate>,
+}
+
+impl BusinessHours {
+    // an end before the start means the hours run over midnight
+    fn contains(&self, at: NaiveDateTime) -> bool {
+        if self.holidays.contains(&at.date()) || !self.days.contains(&at.weekday()) {
+            return false;
+        }
+
+        let time = at.time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SeverityAdjustment {
+    community: Option<String>,
+    #[serde(default, with = "serde_regex")]
+    alert_name: Option<regex::Regex>,
+    business_hours: BusinessHours,
+    #[serde(default)]
+    during_hours: HashMap<Severity, Severity>,
+    #[serde(default)]
+    outside_hours: HashMap<Severity, Severity>,
+}
+
+impl SeverityAdjustment {
+    fn matches(&self, alert: &AlertmanagerAlert) -> bool {
+        self.community
+            .as_ref()
+            .is_none_or(|c| c == alert.community())
+            && self
+                .alert_name
+                .as_ref()
+                .is_none_or(|rgx| is_full_match(rgx, alert.name()))
+    }
+
+    fn adjusted(&self, severity: Severity, at: NaiveDateTime) -> Option<Severity> {
+        let mapping = match self.business_hours.contains(at) {
+            true => &self.during_hours,
+            false => &self.outside_hours,
+        };
+        mapping.get(&severity).copied()
+    }
+}
+
+// the first rule for the alert decides, the original severity stays visible as an annotation
+pub fn adjust_severity(adjustments: &[SeverityAdjustment], alert: &mut AlertmanagerAlert) {
+    let Some(adjustment) = adjustments.iter().find(|a| a.matches(alert)) else {
+        return;
+    };
+    let Ok(severity) = Severity::from_str(alert.severity()) else {
+        return;
+    };
+
+    if let Some(adjusted) = adjustment.adjusted(severity, Local::now().naive_local())
+        && adjusted != severity
+    {
+        alert.add_annotation("original_severity", severity.to_string());
+        alert.set_severity(adjusted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::Severity;
+    use crate::config::test_settings;
+    use chrono::NaiveDateTime;
+
+    #[test]
+    fn night_downgrade() {
+        let settings = test_settings(
+            r#"
+severity_adjustments:
+  - community: lab
+    business_hours:
+      start: "08:00"
+      end: "18:00"
+      holidays: ["2025-12-25"]
+    outside_hours:
+      critical: warning
+"#,
+        );
+        let adjustment = &settings.severity_adjustments()[0];
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+
+        // a tuesday during the day, at night, a holiday and a saturday
+        assert_eq!(
+            adjustment.adjusted(Severity::Critical, at("2025-12-23 10:00")),
+            None
+        );
+        assert_eq!(
+            adjustment.adjusted(Severity::Critical, at("2025-12-23 22:00")),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            adjustment.adjusted(Severity::Critical, at("2025-12-25 10:00")),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            adjustment.adjusted(Severity::Critical, at("2025-12-27 10:00")),
+            Some(Severity::Warning)
+        );
+        assert_eq!(
+            adjustment.adjusted(Severity::Info, at("2025-12-23 22:00")),
+            None
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use crate::alertmanager::AlertmanagerAlert;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use itertools::Itertools;
+use log::{debug, info, warn};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OnCallCalendarSettings {
+    url: String,
+    community: Option<String>,
+    #[serde(default = "annotation_default")]
+    annotation: String,
+    #[serde(
+        default = "interval_sec_default",
+        deserialize_with = "crate::config::interval_sec"
+    )]
+    interval_sec: u64,
+}
+
+fn annotation_default() -> String {
+    "oncall".to_string()
+}
+
+fn interval_sec_default() -> u64 {
+    900
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Shift {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    person: String,
+}
+
+pub struct OnCallCalendar {
+    settings: OnCallCalendarSettings,
+    shifts: RwLock<Vec<Shift>>,
+    client: Client,
+}
+
+impl OnCallCalendar {
+    pub fn new(settings: OnCallCalendarSettings) -> Self {
+        Self {
+            settings,
+            shifts: RwLock::default(),
+            client: Client::default(),
+        }
+    }
+
+    // a failed fetch keeps the shifts from before, an outdated schedule beats no schedule
+    pub async fn run_refresh_blocking(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.interval_sec));
+        loop {
+            interval.tick().await;
+
+            match self.fetch().await {
+                Ok(shifts) => {
+                    debug!(
+                        "Loaded {} on-call shifts from {}",
+                        shifts.len(),
+                        self.settings.url
+                    );
+                    *self.shifts.write().expect("on-call shifts lock poisoned") = shifts;
+                }
+                Err(e) => warn!(
+                    "Couldn't fetch on-call calendar {}: {e:#}",
+                    self.settings.url
+                ),
+            }
+        }
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<Shift>> {
+        let ics = self
+            .client
+            .get(&self.settings.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(parse_shifts(&ics))
+    }
+
+    fn on_call(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.shifts
+            .read()
+            .expect("on-call shifts lock poisoned")
+            .iter()
+            .filter(|shift| shift.start <= now && now < shift.end)
+            .map(|shift| shift.person.clone())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct OnCallCalendars {
+    calendars: Vec<Arc<OnCallCalendar>>,
+}
+
+impl OnCallCalendars {
+    pub fn new(settings: &[OnCallCalendarSettings]) -> Self {
+        let calendars = settings
+            .iter()
+            .map(|s| Arc::new(OnCallCalendar::new(s.clone())))
+            .collect_vec();
+        if !calendars.is_empty() {
+            info!("Following {} on-call calendars", calendars.len());
+        }
+        Self { calendars }
+    }
+
+    pub fn calendars(&self) -> &[Arc<OnCallCalendar>] {
+        &self.calendars
+    }
+
+    pub fn annotate(&self, alerts: &mut [AlertmanagerAlert]) {
+        if self.calendars.is_empty() {
+            return;
+        }
+
+        let now = Utc::now();
+        let on_call = self
+            .calendars
+            .iter()
+            .map(|calendar| (calendar, calendar.on_call(now)))
+            .filter(|(_, people)| !people.is_empty())
+            .collect_vec();
+
+        for alert in alerts {
+            let mut annotations: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+            for (calendar, people) in &on_call {
+                if calendar
+                    .settings
+                    .community
+                    .as_ref()
+                    .is_none_or(|c| c == alert.community())
+                {
+                    annotations
+                        .entry(&calendar.settings.annotation)
+                        .or_default()
+                        .extend(people.iter().map(String::as_str));
+                }
+            }
+            for (annotation, people) in annotations {
+                alert.add_annotation(annotation, people.into_iter().unique().join(", "));
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Event {
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    summary: Option<String>,
+}
+
+impl Event {
+    fn into_shift(self) -> Option<Shift> {
+        Some(Shift {
+            start: self.start?,
+            end: self.end?,
+            person: self.summary?,
+        })
+    }
+}
+
+// only single events are understood, schedules exported from paging tools list every shift.
+// Times with a TZID are taken as local time
+fn parse_shifts(ics: &str) -> Vec<Shift> {
+    let mut shifts = Vec::new();
+    let mut event: Option<Event> = None;
+
+    for line in unfold_lines(ics) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = key.split_once(';').unwrap_or((key, ""));
+        match (name.to_ascii_uppercase().as_str(), &mut event) {
+            ("BEGIN", _) if value == "VEVENT" => event = Some(Event::default()),
+            ("END", _) if value == "VEVENT" => {
+                shifts.extend(event.take().and_then(Event::into_shift));
+            }
+            ("DTSTART", Some(event)) => event.start = parse_time(value, params),
+            ("DTEND", Some(event)) => event.end = parse_time(value, params),
+            ("SUMMARY", Some(event)) => event.summary = Some(unescape_text(value)),
+            _ => {}
+        }
+    }
+
+    shifts
+}
+
+// long lines are folded by continuing them with a leading space or tab
+fn unfold_lines(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn parse_time(value: &str, params: &str) -> Option<DateTime<Utc>> {
+    if params
+        .split(';')
+        .any(|p| p.eq_ignore_ascii_case("VALUE=DATE"))
+    {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return local_time(date.and_hms_opt(0, 0, 0)?);
+    }
+
+    match value.strip_suffix('Z') {
+        Some(utc) => Some(
+            NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+                .ok()?
+                .and_utc(),
+        ),
+        None => local_time(NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?),
+    }
+}
+
+fn local_time(time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Some(
+        Local
+            .from_local_datetime(&time)
+            .earliest()?
+            .with_timezone(&Utc),
+    )
+}
+
+fn unescape_text(value: &str) -> String {
+    value
+        .replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::parse_shifts;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn parses_shifts() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART:20250106T080000Z\r\n\
+                   DTEND:20250113T080000Z\r\n\
+                   SUMMARY:Alice\\, Network\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART:20250113T080000Z\r\n\
+                   SUMMARY:Bob without an end\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART:20250113T080000Z\r\n\
+                   DTEND:20250120T080000Z\r\n\
+                   SUMMARY:Car\r\n \
+                   ol\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+
+        let shifts = parse_shifts(ics);
+
+        assert_eq!(shifts.len(), 2);
+        assert_eq!(shifts[0].person, "Alice, Network");
+        assert_eq!(
+            shifts[0].start,
+            Utc.with_ymd_and_hms(2025, 1, 6, 8, 0, 0).unwrap()
+        );
+        assert_eq!(shifts[1].person, "Carol");
+    }
+}
@@ -0,0 +1,251 @@
+use crate::alertmanager::RelayStatus;
+use crate::enrichment::AlertEnrichment;
+use crate::trap_db::TrapDb;
+use crate::web::{AlertView, sorted_alert_views};
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use utoipa::ToSchema;
+
+const JOURNAL_SIZE: usize = 10_000;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+pub const WAIT_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+#[derive(Debug)]
+struct Change {
+    revision: u64,
+    hash: u64,
+    kind: ChangeKind,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Delta {
+    added: Vec<u64>,
+    updated: Vec<u64>,
+    removed: Vec<u64>,
+}
+
+// what changed at which revision, only the most recent changes are kept
+#[derive(Debug, Default)]
+struct Journal {
+    revision: u64,
+    forgotten: u64,
+    fingerprints: HashMap<u64, u64>,
+    changes: VecDeque<Change>,
+}
+
+impl Journal {
+    fn observe(&mut self, current: HashMap<u64, u64>) {
+        let mut changed = Vec::new();
+        for (hash, fingerprint) in &current {
+            match self.fingerprints.get(hash) {
+                None => changed.push((*hash, ChangeKind::Added)),
+                Some(known) if known != fingerprint => changed.push((*hash, ChangeKind::Updated)),
+                Some(_) => {}
+            }
+        }
+        for hash in self.fingerprints.keys() {
+            if !current.contains_key(hash) {
+                changed.push((*hash, ChangeKind::Removed));
+            }
+        }
+        self.fingerprints = current;
+        if changed.is_empty() {
+            return;
+        }
+
+        self.revision += 1;
+        for (hash, kind) in changed {
+            self.changes.push_back(Change {
+                revision: self.revision,
+                hash,
+                kind,
+            });
+        }
+        while self.changes.len() > JOURNAL_SIZE {
+            if let Some(change) = self.changes.pop_front() {
+                self.forgotten = change.revision;
+            }
+        }
+    }
+
+    // None when the journal doesn't reach back that far, the caller has to start over
+    fn since(&self, since: u64) -> Option<Delta> {
+        if since < self.forgotten || since > self.revision {
+            return None;
+        }
+
+        // the first change after the cursor tells whether the client knows the alert
+        let mut first: HashMap<u64, ChangeKind> = HashMap::new();
+        for change in self.changes.iter().filter(|c| c.revision > since) {
+            if let Entry::Vacant(entry) = first.entry(change.hash) {
+                entry.insert(change.kind);
+            }
+        }
+
+        let mut delta = Delta::default();
+        for (hash, kind) in first {
+            let present = self.fingerprints.contains_key(&hash);
+            match (kind, present) {
+                (ChangeKind::Added, true) => delta.added.push(hash),
+                (ChangeKind::Added, false) => {}
+                (_, true) => delta.updated.push(hash),
+                (_, false) => delta.removed.push(hash),
+            }
+        }
+        Some(delta)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AlertChangeSet {
+    // pass this as `since` to get the changes after this response
+    pub cursor: String,
+    // the cursor was unknown or too old, `added` holds all alerts and replaces the mirrored state
+    pub reset: bool,
+    pub added: Vec<AlertView>,
+    pub updated: Vec<AlertView>,
+    pub removed: Vec<u64>,
+}
+
+impl AlertChangeSet {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+// cursors name the process they came from, a restart starts a new journal
+pub struct AlertChanges {
+    epoch: String,
+    journal: Mutex<Journal>,
+}
+
+impl Default for AlertChanges {
+    fn default() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            epoch: format!("{:x}", started.as_millis()),
+            journal: Mutex::new(Journal::default()),
+        }
+    }
+}
+
+impl AlertChanges {
+    fn parse_cursor(&self, cursor: &str) -> Option<u64> {
+        let (epoch, revision) = cursor.split_once('-')?;
+        if epoch != self.epoch {
+            return None;
+        }
+        revision.parse().ok()
+    }
+
+    fn collect(&self, since: Option<&str>, views: Vec<AlertView>) -> AlertChangeSet {
+        let fingerprints = views
+            .iter()
+            .map(|view| {
+                let mut hasher = DefaultHasher::new();
+                hasher.write(&serde_json::to_vec(view).unwrap_or_default());
+                (view.hash, hasher.finish())
+            })
+            .collect();
+
+        let mut journal = self.journal.lock().expect("change journal lock poisoned");
+        journal.observe(fingerprints);
+        let cursor = format!("{}-{}", self.epoch, journal.revision);
+        let delta = since
+            .and_then(|since| self.parse_cursor(since))
+            .and_then(|since| journal.since(since));
+        drop(journal);
+
+        let Some(delta) = delta else {
+            return AlertChangeSet {
+                cursor,
+                reset: true,
+                added: views,
+                updated: Vec::new(),
+                removed: Vec::new(),
+            };
+        };
+
+        let mut views: HashMap<u64, AlertView> = views.into_iter().map(|v| (v.hash, v)).collect();
+        let mut take = |hashes: Vec<u64>| {
+            hashes
+                .into_iter()
+                .filter_map(|hash| views.remove(&hash))
+                .collect()
+        };
+        AlertChangeSet {
+            cursor,
+            reset: false,
+            added: take(delta.added),
+            updated: take(delta.updated),
+            removed: delta.removed,
+        }
+    }
+
+    // holds the request until something changes or the wait is over
+    pub async fn wait_for_changes(
+        &self,
+        db: &TrapDb,
+        relay_status: &RelayStatus,
+        enrichment: &AlertEnrichment,
+        since: Option<&str>,
+        wait: Duration,
+    ) -> AlertChangeSet {
+        let deadline = Instant::now() + wait.min(WAIT_MAX);
+        loop {
+            let changes = self.collect(
+                since,
+                sorted_alert_views(db, relay_status, enrichment).await,
+            );
+            if changes.reset || !changes.is_empty() || Instant::now() >= deadline {
+                return changes;
+            }
+            tokio::time::sleep(POLL_INTERVAL.min(deadline - Instant::now())).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::changes::{Delta, Journal};
+    use std::collections::HashMap;
+
+    #[test]
+    fn deltas_since_revision() {
+        let mut journal = Journal::default();
+        journal.observe(HashMap::from([(1, 10), (2, 20)]));
+        assert_eq!(journal.revision, 1);
+
+        journal.observe(HashMap::from([(1, 11), (3, 30)]));
+        journal.observe(HashMap::from([(1, 11), (3, 30), (4, 40)]));
+        journal.observe(HashMap::from([(1, 11), (3, 30)]));
+        assert_eq!(journal.revision, 4);
+
+        let mut delta = journal.since(1).unwrap();
+        delta.removed.sort();
+        assert_eq!(
+            delta,
+            Delta {
+                added: vec![3],
+                updated: vec![1],
+                removed: vec![2],
+            }
+        );
+        assert_eq!(journal.since(4), Some(Delta::default()));
+        assert_eq!(journal.since(5), None);
+    }
+}
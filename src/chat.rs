@@ -0,0 +1,187 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::config::CONFIG;
+use crate::enrichment::build_context;
+use crate::notifier::{IncidentSink, Route, Schedule, alert_description, alert_title, find_route};
+use async_trait::async_trait;
+use log::debug;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tera::Tera;
+
+const MESSAGE_TEMPLATE: &str = "message";
+const THROTTLE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatKind {
+    Slack,
+    Teams,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatSettings {
+    kind: ChatKind,
+    name: Option<String>,
+    #[serde(flatten)]
+    schedule: Schedule,
+    template: Option<String>,
+    #[serde(default = "max_messages_per_minute_default")]
+    max_messages_per_minute: usize,
+    routes: Vec<Route<ChatTarget>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatTarget {
+    url: String,
+}
+
+fn max_messages_per_minute_default() -> usize {
+    20
+}
+
+// incoming webhooks for Slack and Microsoft Teams, posting one card per fired or resolved alert
+pub struct ChatNotifier {
+    settings: ChatSettings,
+    name: String,
+    templates: Option<Tera>,
+    client: Client,
+    window_start: Instant,
+    sent_in_window: usize,
+}
+
+impl ChatNotifier {
+    pub fn new(settings: ChatSettings) -> anyhow::Result<Self> {
+        let templates = match &settings.template {
+            Some(template) => {
+                let mut tera = Tera::default();
+                tera.add_raw_template(MESSAGE_TEMPLATE, template)?;
+                Some(tera)
+            }
+            None => None,
+        };
+        let name = match (&settings.name, settings.kind) {
+            (Some(name), _) => name.clone(),
+            (None, ChatKind::Slack) => "slack".to_string(),
+            (None, ChatKind::Teams) => "teams".to_string(),
+        };
+
+        Ok(Self {
+            settings,
+            name,
+            templates,
+            client: Client::default(),
+            window_start: Instant::now(),
+            sent_in_window: 0,
+        })
+    }
+
+    fn render_message(&self, alert: &AlertmanagerAlert, resolved: bool) -> anyhow::Result<String> {
+        let Some(templates) = &self.templates else {
+            return Ok(alert_description(alert));
+        };
+
+        let mut ctx = build_context(alert, &BTreeMap::new())?;
+        ctx.insert("resolved", &resolved);
+        Ok(templates.render(MESSAGE_TEMPLATE, &ctx)?)
+    }
+
+    async fn send(&self, alert: &AlertmanagerAlert, resolved: bool) -> anyhow::Result<()> {
+        let Some(target) = find_route(&self.settings.routes, alert) else {
+            debug!("No chat route for alert {:?}", alert.name());
+            return Ok(());
+        };
+
+        let title = if resolved {
+            format!("[RESOLVED] {}", alert_title(alert))
+        } else {
+            format!(
+                "[{}] {}",
+                alert.severity().to_uppercase(),
+                alert_title(alert)
+            )
+        };
+        let text = self.render_message(alert, resolved)?;
+        let color = color(alert.severity(), resolved);
+
+        let payload = match self.settings.kind {
+            ChatKind::Slack => json!({
+                "attachments": [{
+                    "color": format!("#{color}"),
+                    "title": title,
+                    "title_link": CONFIG.web_url(),
+                    "text": text,
+                    "footer": alert.community(),
+                }]
+            }),
+            ChatKind::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "themeColor": color,
+                "summary": title,
+                "title": title,
+                "text": text,
+                "potentialAction": [{
+                    "@type": "OpenUri",
+                    "name": "Open alerts",
+                    "targets": [{"os": "default", "uri": CONFIG.web_url()}],
+                }],
+            }),
+        };
+
+        self.client
+            .post(&target.url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IncidentSink for ChatNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.settings.schedule
+    }
+
+    async fn trigger(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, false).await
+    }
+
+    async fn resolve(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, true).await
+    }
+
+    // messages over the limit stay unannounced and are retried in a later cycle
+    fn admit(&mut self) -> bool {
+        if self.window_start.elapsed() >= THROTTLE_WINDOW {
+            self.window_start = Instant::now();
+            self.sent_in_window = 0;
+        }
+        if self.sent_in_window >= self.settings.max_messages_per_minute {
+            debug!("Chat message limit reached, holding back further messages");
+            return false;
+        }
+        self.sent_in_window += 1;
+        true
+    }
+}
+
+// same accents as the web UI
+fn color(severity: &str, resolved: bool) -> &'static str {
+    if resolved {
+        return "22c55e";
+    }
+    match severity {
+        "critical" => "ef4444",
+        "warning" => "ef7744",
+        _ => "44a8ef",
+    }
+}
@@ -1,24 +1,74 @@
-use clap::Parser;
+use crate::access_log::AccessLogSettings;
+use crate::alerts::{Alert, Severity};
+use crate::archive::ArchiveSettings;
+use crate::bench::BenchOptions;
+use crate::bucketing::BucketRule;
+use crate::business_hours::SeverityAdjustment;
+use crate::calendar::OnCallCalendarSettings;
+use crate::chat::ChatSettings;
+use crate::conventions::OutputConventions;
+use crate::correlation::CorrelationRule;
+use crate::decode::ValueNames;
+use crate::enrichment::LabelMatcher;
+use crate::escalation::EscalationPolicy;
+use crate::filter::SourceFilter;
+use crate::inhibition::InhibitRule;
+use crate::librenms::LibreNmsSettings;
+use crate::maintenance::MaintenanceWindow;
+use crate::netbox::NetBoxSettings;
+use crate::oidc::OidcSettings;
+use crate::oncall::OnCallSettings;
+use crate::opsgenie::OpsgenieSettings;
+use crate::pagerduty::PagerDutySettings;
+use crate::pipeline::PipelineSettings;
+use crate::quiet_hours::QuietHours;
+use crate::rate_limit::RateLimitSettings;
+use crate::relay_queue::RelayQueueSettings;
+use crate::rule_pack::RulePack;
+use crate::rules_git::GitRulesSettings;
+use crate::sites::SiteMapping;
+use clap::{Parser, Subcommand};
 use config::Config;
+use ipnet::IpNet;
 use lazy_static::lazy_static;
-use serde::Deserialize;
+use log::warn;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use time::Duration;
+use std::sync::Arc;
 use time::ext::NumericalDuration;
+use time::{Duration, OffsetDateTime};
 
 lazy_static! {
     pub static ref CLI: CLISettings = CLISettings::parse();
 }
 
 lazy_static! {
-    pub static ref CONFIG: Settings = Config::builder()
+    // the sources are kept to tell on a reload what changed since startup
+    static ref CONFIG_SOURCE: Config = read_config().unwrap();
+    pub static ref CONFIG: Arc<Settings> =
+        Arc::new(CONFIG_SOURCE.clone().try_deserialize().unwrap());
+}
+
+// only the alert rule sources are picked up on a reload, everything else needs a restart
+const RELOADABLE_KEYS: &[&str] = &["alert_dir", "rule_packs"];
+
+fn read_config() -> Result<Config, config::ConfigError> {
+    Config::builder()
         .add_source(config::File::with_name(CLI.config_path()))
         .add_source(config::Environment::default())
         .build()
-        .unwrap()
-        .try_deserialize()
-        .unwrap();
+}
+
+fn without_reloadable(source: &Config) -> anyhow::Result<serde_json::Value> {
+    let mut value: serde_json::Value = source.clone().try_deserialize()?;
+    if let Some(keys) = value.as_object_mut() {
+        for key in RELOADABLE_KEYS {
+            keys.remove(*key);
+        }
+    }
+    Ok(value)
 }
 
 #[derive(Debug, Parser)]
@@ -28,17 +78,55 @@ pub struct CLISettings {
     #[arg(
         long,
         short,
-        help = "Socket Address of the web frontend [127.0.0.1:7788]"
+        help = "Socket Address of the web frontend, can be repeated [127.0.0.1:7788]"
     )]
-    listen: Option<SocketAddr>,
+    listen: Vec<SocketAddr>,
     #[arg(
         long,
         help = "The directory containing .yaml files to enrich received alerts"
     )]
     alert_dir: Option<PathBuf>,
 
-    #[arg(long, help = "Only test the validity of alert enrichments inside --alert-dir <dir>", requires = "alert_dir")]
+    #[arg(
+        long,
+        help = "Only test the validity of alert enrichments inside --alert-dir <dir>",
+        requires = "alert_dir"
+    )]
     pub test_alerts: bool,
+
+    #[arg(
+        long,
+        help = "Pass a trap in snmptrapd traphandle format from stdin to the running instance"
+    )]
+    pub traphandle: bool,
+
+    #[arg(
+        long,
+        help = "Pass line-delimited JSON traps from stdin to the running instance"
+    )]
+    pub json_stdin: bool,
+
+    #[arg(
+        long,
+        help = "Print how the current alerts would merge under the identity rules in this YAML file"
+    )]
+    pub preview_identity: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Measure how fast synthetic traps are mapped, deduplicated and enriched")]
+    Bench(BenchOptions),
+    #[command(about = "Print a starter configuration for the communities of an snmptrapd.conf")]
+    ImportSnmptrapd {
+        #[arg(default_value = "/etc/snmp/snmptrapd.conf")]
+        path: PathBuf,
+    },
+    #[command(about = "Print Alertmanager routes and receivers matching the current alerts")]
+    SuggestRoutes,
 }
 
 impl CLISettings {
@@ -50,45 +138,368 @@ impl CLISettings {
     }
 }
 
-fn web_listen_default() -> SocketAddr {
-    SocketAddr::from(([127, 0, 0, 1], 7788))
+fn web_listen_default() -> ListenAddresses {
+    ListenAddresses::One(SocketAddr::from(([127, 0, 0, 1], 7788)))
 }
 
 fn announce_sec_default() -> u32 {
     60
 }
 
+fn cors_allowed_methods_default() -> Vec<String> {
+    vec!["GET".to_string()]
+}
+
 fn community_label_default() -> String {
     "community".to_string()
 }
 
-#[derive(Debug, Deserialize)]
+fn db_query_timeout_sec_default() -> u64 {
+    30
+}
+
+fn leader_lock_id_default() -> i64 {
+    0x736e6d70 // "snmp"
+}
+
+fn drop_columns_default() -> Vec<String> {
+    ["mib", "oid", "source", "version", "sysUpTime.0", "host"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn reboot_alert_name_default() -> String {
+    "DeviceRebooted".to_string()
+}
+
+fn reboot_lookback_sec_default() -> u64 {
+    24 * 60 * 60
+}
+
+fn redis_key_prefix_default() -> String {
+    "snmp-trap-alertmanager".to_string()
+}
+
+fn restricted_label_prefix_default() -> String {
+    "trap_".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     web_url: String,
     #[serde(default = "web_listen_default")]
-    web_listen: SocketAddr,
+    web_listen: ListenAddresses,
     db_connection_url: String,
+    #[serde(default = "db_query_timeout_sec_default")]
+    db_query_timeout_sec: u64,
+    #[serde(default)]
+    sql_aggregation: bool,
     alertmanager_url: String,
-    #[serde(default = "announce_sec_default")]
+    #[serde(default = "announce_sec_default", deserialize_with = "announce_sec")]
     alertmanager_announce_sec: u32,
+    #[serde(default, deserialize_with = "announce_sec_by_severity")]
+    alertmanager_announce_sec_by_severity: HashMap<Severity, u32>,
+    #[serde(default)]
+    alertmanager_announce_jitter_sec: u32,
+    #[serde(default)]
+    alertmanager_announce_splay_sec: u32,
     #[serde(default = "community_label_default")]
     alertmanager_community_label: String,
+    #[serde(default = "restricted_label_prefix_default")]
+    restricted_label_prefix: String,
+    annotation_language: Option<String>,
+    #[serde(default)]
+    community_annotation_languages: HashMap<String, String>,
     alert_dir: Option<PathBuf>,
+    #[serde(default)]
+    rule_packs: Vec<RulePack>,
+    rules_git: Option<GitRulesSettings>,
+    #[serde(default)]
+    api_keys: Vec<String>,
+    #[serde(default)]
+    web_path_prefix: String,
+    static_dir: Option<PathBuf>,
+    access_log: Option<AccessLogSettings>,
+    rate_limit: Option<RateLimitSettings>,
+    #[serde(default)]
+    trusted_proxies: Vec<IpNet>,
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+    #[serde(default = "cors_allowed_methods_default")]
+    cors_allowed_methods: Vec<String>,
+    // traps received here never reach the trap table, without Redis they only live in memory
+    trap_listen: Option<ListenAddresses>,
+    traphandle_socket: Option<PathBuf>,
+    json_socket: Option<PathBuf>,
+    #[serde(default)]
+    trap_forward: Vec<ForwardTarget>,
+    #[serde(default)]
+    source_filter: SourceFilter,
+    label_value_max_length: Option<usize>,
+    #[serde(default)]
+    label_value_oversized: OversizedLabelPolicy,
+    alertmanager_max_alert_bytes: Option<usize>,
+    relay_queue: Option<RelayQueueSettings>,
+    output_conventions: Option<OutputConventions>,
+    oncall: Option<OnCallSettings>,
+    opsgenie: Option<OpsgenieSettings>,
+    pagerduty: Option<PagerDutySettings>,
+    #[serde(default)]
+    chat: Vec<ChatSettings>,
+    #[serde(default)]
+    auto_decode_values: bool,
+    #[serde(default)]
+    value_names: HashMap<String, ValueNames>,
+    #[serde(default)]
+    correlation_rules: Vec<CorrelationRule>,
+    #[serde(default)]
+    inhibit_rules: Vec<InhibitRule>,
+    #[serde(default)]
+    maintenance_windows: Vec<MaintenanceWindow>,
+    #[serde(default)]
+    severity_adjustments: Vec<SeverityAdjustment>,
+    #[serde(default)]
+    sites: Vec<SiteMapping>,
+    netbox: Option<NetBoxSettings>,
+    librenms: Option<LibreNmsSettings>,
+    quiet_hours: Option<QuietHours>,
+    #[serde(default)]
+    escalations: Vec<EscalationPolicy>,
+    escalation_state_file: Option<PathBuf>,
+    #[serde(default)]
+    oncall_calendars: Vec<OnCallCalendarSettings>,
+    archive: Option<ArchiveSettings>,
+    audit_log: Option<PathBuf>,
+    oidc: Option<OidcSettings>,
+    #[serde(default)]
+    wallboard_public: bool,
+    #[serde(default)]
+    leader_election: bool,
+    #[serde(default = "leader_lock_id_default")]
+    leader_lock_id: i64,
+    notify_channel: Option<String>,
+    #[serde(default)]
+    notify_install_trigger: bool,
+    redis_url: Option<String>,
+    #[serde(default = "redis_key_prefix_default")]
+    redis_key_prefix: String,
+    alert_expiry: Option<AlertExpiry>,
+    #[serde(default = "drop_columns_default")]
+    drop_columns: Vec<String>,
+    instance_label: Option<String>,
+    #[serde(default)]
+    label_mapping: HashMap<String, String>,
+    #[serde(default)]
+    reboot_detection: bool,
+    #[serde(default = "reboot_alert_name_default")]
+    reboot_alert_name: String,
+    // resets are recomputed from the whole trap table, older ones don't become alerts again
+    #[serde(default = "reboot_lookback_sec_default")]
+    reboot_lookback_sec: u64,
+    #[serde(default)]
+    pipelines: Vec<PipelineSettings>,
+    // set for the settings of an additional pipeline, never configured directly
+    #[serde(skip)]
+    pipeline: Option<String>,
+    #[serde(default)]
+    communities: HashMap<String, CommunityDisplay>,
+    #[serde(default)]
+    dead_letter: bool,
+    #[serde(default)]
+    name_fallback_columns: Vec<String>,
+    #[serde(default)]
+    identity_exclude_labels: Vec<String>,
+    #[serde(default)]
+    label_buckets: Vec<BucketRule>,
+    #[serde(default)]
+    record_reload_diffs: bool,
+    #[serde(default)]
+    community_fallback: CommunityFallback,
+}
+
+// a single address keeps older configs working, a list allows dual-stack and multi-homed setups
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ListenAddresses {
+    One(SocketAddr),
+    Many(Vec<SocketAddr>),
+}
+
+impl ListenAddresses {
+    pub fn as_slice(&self) -> &[SocketAddr] {
+        match self {
+            ListenAddresses::One(addr) => std::slice::from_ref(addr),
+            ListenAddresses::Many(addrs) => addrs,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedLabelPolicy {
+    #[default]
+    Truncate,
+    Annotate,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryAction {
+    #[default]
+    Hide,
+    Clear,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AlertExpiry {
+    pub max_age_sec: u64,
+    #[serde(default)]
+    pub action: ExpiryAction,
+}
+
+impl AlertExpiry {
+    pub fn is_expired(&self, alert: &Alert) -> bool {
+        OffsetDateTime::now_utc() - alert.latest() > (self.max_age_sec as i64).seconds()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommunityDisplay {
+    name: Option<String>,
+    color: Option<String>,
+}
+
+impl CommunityDisplay {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // ends up in a style attribute, so only hex codes and color names are let through
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref().filter(|color| {
+            let name = color.strip_prefix('#').unwrap_or(color);
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+    }
+}
+
+// v3 traps carry no community, the security name or context usually tells the senders apart
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CommunityFallback {
+    #[serde(default)]
+    columns: Vec<String>,
+    value: Option<String>,
+}
+
+impl CommunityFallback {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref().filter(|v| !v.is_empty())
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.columns.is_empty() || self.value().is_some()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ForwardTarget {
+    pub target: SocketAddr,
+    pub community: Option<String>,
+    #[serde(default, with = "serde_regex")]
+    pub name: Option<regex::Regex>,
+    #[serde(default)]
+    pub labels: Vec<LabelMatcher>,
+}
+
+// intervals drive tokio::time::interval, which panics on a zero period
+fn nonzero_sec<T: Copy + Into<u64>, E: serde::de::Error>(sec: T) -> Result<T, E> {
+    match sec.into() {
+        0 => Err(E::custom("interval has to be at least one second")),
+        _ => Ok(sec),
+    }
+}
+
+pub(crate) fn interval_sec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    nonzero_sec(u64::deserialize(deserializer)?)
+}
+
+// the announce cadence also drives the expiry task
+pub(crate) fn announce_sec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    nonzero_sec(u32::deserialize(deserializer)?)
+}
+
+pub(crate) fn optional_announce_sec<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error> {
+    Option::<u32>::deserialize(deserializer)?
+        .map(nonzero_sec)
+        .transpose()
+}
+
+fn announce_sec_by_severity<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Severity, u32>, D::Error> {
+    let by_severity = HashMap::<Severity, u32>::deserialize(deserializer)?;
+    for sec in by_severity.values() {
+        nonzero_sec::<_, D::Error>(*sec)?;
+    }
+    Ok(by_severity)
 }
 
 impl Settings {
+    // re-reads the configuration on top of the running settings, which came from CONFIG
+    pub fn reload(&self) -> anyhow::Result<Settings> {
+        let source = read_config()?;
+        let reloaded: Settings = source.clone().try_deserialize()?;
+        if without_reloadable(&source)? != without_reloadable(&CONFIG_SOURCE)? {
+            warn!(
+                "The configuration changed beyond the alert rule sources, \
+                 a restart is needed to apply the rest"
+            );
+        }
+
+        let mut settings = self.clone();
+        settings.alert_dir = reloaded.alert_dir;
+        settings.rule_packs = reloaded.rule_packs;
+        Ok(settings)
+    }
+
+    pub fn from_yaml(yaml: &str) -> anyhow::Result<Settings> {
+        Ok(Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()?
+            .try_deserialize()?)
+    }
+
     pub fn web_url(&self) -> &str {
         &self.web_url
     }
 
-    pub fn web_listen(&self) -> SocketAddr {
-        CLI.listen.unwrap_or(self.web_listen)
+    pub fn web_listen(&self) -> &[SocketAddr] {
+        if CLI.listen.is_empty() {
+            self.web_listen.as_slice()
+        } else {
+            &CLI.listen
+        }
     }
 
     pub fn db_url(&self) -> &str {
         &self.db_connection_url
     }
 
+    pub fn db_query_timeout(&self) -> Option<std::time::Duration> {
+        (self.db_query_timeout_sec > 0)
+            .then(|| std::time::Duration::from_secs(self.db_query_timeout_sec))
+    }
+
+    pub fn sql_aggregation(&self) -> bool {
+        self.sql_aggregation
+    }
+
     pub fn alertmanager_url(&self) -> &str {
         &self.alertmanager_url
     }
@@ -97,11 +508,400 @@ impl Settings {
         (self.alertmanager_announce_sec as i64).seconds()
     }
 
+    // severities without a cadence of their own use the global one
+    pub fn alertmanager_announce_duration_for(&self, severity: Severity) -> Duration {
+        match self.alertmanager_announce_sec_by_severity.get(&severity) {
+            Some(sec) => (*sec as i64).seconds(),
+            None => self.alertmanager_announce_duration(),
+        }
+    }
+
+    // the relay has to wake up as often as the most urgent severity wants to be announced
+    pub fn alertmanager_min_announce_duration(&self) -> Duration {
+        let sec = self
+            .alertmanager_announce_sec_by_severity
+            .values()
+            .copied()
+            .chain([self.alertmanager_announce_sec])
+            .min()
+            .unwrap_or(self.alertmanager_announce_sec);
+        (sec as i64).seconds()
+    }
+
+    pub fn alertmanager_announce_jitter(&self) -> Duration {
+        (self.alertmanager_announce_jitter_sec as i64).seconds()
+    }
+
+    pub fn alertmanager_announce_splay(&self) -> Duration {
+        (self.alertmanager_announce_splay_sec as i64).seconds()
+    }
+
     pub fn alertmanager_community_label(&self) -> &str {
         &self.alertmanager_community_label
     }
 
+    pub fn annotation_language(&self, community: &str) -> Option<&str> {
+        self.community_annotation_languages
+            .get(community)
+            .or(self.annotation_language.as_ref())
+            .map(String::as_str)
+    }
+
+    pub fn restricted_label_prefix(&self) -> &str {
+        &self.restricted_label_prefix
+    }
+
     pub fn alert_dir(&self) -> Option<&Path> {
         CLI.alert_dir.as_deref().or(self.alert_dir.as_deref())
     }
+
+    pub fn rule_packs(&self) -> &[RulePack] {
+        &self.rule_packs
+    }
+
+    pub fn rules_git(&self) -> Option<&GitRulesSettings> {
+        self.rules_git.as_ref()
+    }
+
+    pub fn api_keys(&self) -> &[String] {
+        &self.api_keys
+    }
+
+    pub fn web_path_prefix(&self) -> &str {
+        self.web_path_prefix.trim_end_matches('/')
+    }
+
+    pub fn static_dir(&self) -> Option<&Path> {
+        self.static_dir.as_deref()
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLogSettings> {
+        self.access_log.as_ref()
+    }
+
+    pub fn rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.rate_limit.as_ref()
+    }
+
+    pub fn trusted_proxies(&self) -> &[IpNet] {
+        &self.trusted_proxies
+    }
+
+    pub fn cors_allowed_origins(&self) -> &[String] {
+        &self.cors_allowed_origins
+    }
+
+    pub fn cors_allowed_methods(&self) -> &[String] {
+        &self.cors_allowed_methods
+    }
+
+    pub fn trap_listen(&self) -> &[SocketAddr] {
+        self.trap_listen
+            .as_ref()
+            .map(ListenAddresses::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn traphandle_socket(&self) -> Option<&Path> {
+        self.traphandle_socket.as_deref()
+    }
+
+    pub fn json_socket(&self) -> Option<&Path> {
+        self.json_socket.as_deref()
+    }
+
+    pub fn trap_forward(&self) -> &[ForwardTarget] {
+        &self.trap_forward
+    }
+
+    pub fn source_filter(&self) -> &SourceFilter {
+        &self.source_filter
+    }
+
+    pub fn label_value_max_length(&self) -> Option<usize> {
+        self.label_value_max_length
+    }
+
+    pub fn label_value_oversized(&self) -> OversizedLabelPolicy {
+        self.label_value_oversized
+    }
+
+    pub fn alertmanager_max_alert_bytes(&self) -> Option<usize> {
+        self.alertmanager_max_alert_bytes
+    }
+
+    pub fn relay_queue(&self) -> Option<&RelayQueueSettings> {
+        self.relay_queue.as_ref()
+    }
+
+    pub fn output_conventions(&self) -> Option<&OutputConventions> {
+        self.output_conventions.as_ref()
+    }
+
+    pub fn oncall(&self) -> Option<&OnCallSettings> {
+        self.oncall.as_ref()
+    }
+
+    pub fn opsgenie(&self) -> Option<&OpsgenieSettings> {
+        self.opsgenie.as_ref()
+    }
+
+    pub fn pagerduty(&self) -> Option<&PagerDutySettings> {
+        self.pagerduty.as_ref()
+    }
+
+    pub fn chat(&self) -> &[ChatSettings] {
+        &self.chat
+    }
+
+    pub fn auto_decode_values(&self) -> bool {
+        self.auto_decode_values
+    }
+
+    pub fn value_names(&self) -> &HashMap<String, ValueNames> {
+        &self.value_names
+    }
+
+    pub fn correlation_rules(&self) -> &[CorrelationRule] {
+        &self.correlation_rules
+    }
+
+    pub fn inhibit_rules(&self) -> &[InhibitRule] {
+        &self.inhibit_rules
+    }
+
+    pub fn maintenance_windows(&self) -> &[MaintenanceWindow] {
+        &self.maintenance_windows
+    }
+
+    pub fn severity_adjustments(&self) -> &[SeverityAdjustment] {
+        &self.severity_adjustments
+    }
+
+    pub fn sites(&self) -> &[SiteMapping] {
+        &self.sites
+    }
+
+    pub fn netbox(&self) -> Option<&NetBoxSettings> {
+        self.netbox.as_ref()
+    }
+
+    pub fn librenms(&self) -> Option<&LibreNmsSettings> {
+        self.librenms.as_ref()
+    }
+
+    pub fn quiet_hours(&self) -> Option<&QuietHours> {
+        self.quiet_hours.as_ref()
+    }
+
+    pub fn escalations(&self) -> &[EscalationPolicy] {
+        &self.escalations
+    }
+
+    pub fn escalation_state_file(&self) -> Option<&Path> {
+        self.escalation_state_file.as_deref()
+    }
+
+    pub fn oncall_calendars(&self) -> &[OnCallCalendarSettings] {
+        &self.oncall_calendars
+    }
+
+    pub fn archive(&self) -> Option<&ArchiveSettings> {
+        self.archive.as_ref()
+    }
+
+    pub fn audit_log(&self) -> Option<&Path> {
+        self.audit_log.as_deref()
+    }
+
+    pub fn oidc(&self) -> Option<&OidcSettings> {
+        self.oidc.as_ref()
+    }
+
+    pub fn wallboard_public(&self) -> bool {
+        self.wallboard_public
+    }
+
+    pub fn leader_election(&self) -> bool {
+        self.leader_election
+    }
+
+    pub fn leader_lock_id(&self) -> i64 {
+        self.leader_lock_id
+    }
+
+    pub fn notify_channel(&self) -> Option<&str> {
+        self.notify_channel.as_deref()
+    }
+
+    pub fn notify_install_trigger(&self) -> bool {
+        self.notify_install_trigger
+    }
+
+    pub fn redis_url(&self) -> Option<&str> {
+        self.redis_url.as_deref()
+    }
+
+    pub fn redis_key_prefix(&self) -> &str {
+        &self.redis_key_prefix
+    }
+
+    pub fn alert_expiry(&self) -> Option<&AlertExpiry> {
+        self.alert_expiry.as_ref()
+    }
+
+    pub fn drop_columns(&self) -> &[String] {
+        &self.drop_columns
+    }
+
+    pub fn instance_label(&self) -> Option<&str> {
+        self.instance_label.as_deref()
+    }
+
+    pub fn reboot_detection(&self) -> bool {
+        self.reboot_detection
+    }
+
+    pub fn reboot_alert_name(&self) -> &str {
+        &self.reboot_alert_name
+    }
+
+    pub fn reboot_lookback_sec(&self) -> u64 {
+        self.reboot_lookback_sec
+    }
+
+    pub fn pipelines(&self) -> &[PipelineSettings] {
+        &self.pipelines
+    }
+
+    pub fn pipeline(&self) -> Option<&str> {
+        self.pipeline.as_deref()
+    }
+
+    pub fn community_display(&self, community: &str) -> Option<&CommunityDisplay> {
+        self.communities.get(community)
+    }
+
+    pub fn dead_letter(&self) -> bool {
+        self.dead_letter
+    }
+
+    pub fn identity_exclude_labels(&self) -> &[String] {
+        &self.identity_exclude_labels
+    }
+
+    pub fn label_buckets(&self) -> &[BucketRule] {
+        &self.label_buckets
+    }
+
+    pub fn record_reload_diffs(&self) -> bool {
+        self.record_reload_diffs
+    }
+
+    pub fn name_fallback_columns(&self) -> &[String] {
+        &self.name_fallback_columns
+    }
+
+    pub fn community_fallback(&self) -> &CommunityFallback {
+        &self.community_fallback
+    }
+
+    pub fn with_identity_rules(
+        &self,
+        exclude_labels: Option<Vec<String>>,
+        buckets: Option<Vec<BucketRule>>,
+    ) -> Settings {
+        let mut settings = self.clone();
+        if let Some(exclude_labels) = exclude_labels {
+            settings.identity_exclude_labels = exclude_labels;
+        }
+        if let Some(buckets) = buckets {
+            settings.label_buckets = buckets;
+        }
+        settings
+    }
+
+    // everything that would be shared with the main pipeline, like queue files, sockets and
+    // Redis keys, is left out
+    pub fn for_pipeline(&self, pipeline: &PipelineSettings) -> Settings {
+        let mut settings = self.clone();
+        settings.db_connection_url = pipeline.db_connection_url.clone();
+        settings.alert_dir = pipeline.alert_dir.clone();
+        settings.alertmanager_url = pipeline.alertmanager_url.clone();
+        if let Some(announce_sec) = pipeline.alertmanager_announce_sec {
+            settings.alertmanager_announce_sec = announce_sec;
+        }
+        settings.rule_packs.clear();
+        settings.rules_git = None;
+        settings.trap_listen = None;
+        settings.traphandle_socket = None;
+        settings.json_socket = None;
+        settings.relay_queue = None;
+        settings.notify_channel = None;
+        settings.redis_url = None;
+        settings.escalation_state_file = None;
+        settings.pipelines.clear();
+        settings.pipeline = Some(pipeline.name.clone());
+        settings.archive = self
+            .archive
+            .as_ref()
+            .map(|a| a.for_pipeline(&pipeline.name));
+        settings
+    }
+
+    pub fn mapped_label<'a>(&'a self, column: &'a str) -> &'a str {
+        self.label_mapping
+            .get(column)
+            .map(String::as_str)
+            .unwrap_or(column)
+    }
+
+    pub fn mapped_column<'a>(&'a self, label: &'a str) -> &'a str {
+        self.label_mapping
+            .iter()
+            .find(|(_, l)| *l == label)
+            .map(|(c, _)| c.as_str())
+            .unwrap_or(label)
+    }
+}
+
+// the minimal configuration tests start from, `extra` is appended as further top-level keys
+#[cfg(test)]
+pub(crate) fn test_settings(extra: &str) -> Settings {
+    Settings::from_yaml(&format!(
+        "web_url: http://localhost:7788\n\
+         db_connection_url: postgres://localhost/snmp\n\
+         alertmanager_url: http://localhost:9093\n\
+         {extra}"
+    ))
+    .expect("test settings should parse")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::without_reloadable;
+    use config::Config;
+
+    #[test]
+    fn reload_ignores_only_rule_sources() {
+        let source = |yaml: &str| {
+            without_reloadable(
+                &Config::builder()
+                    .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+
+        let running = source("alertmanager_url: http://a\nalert_dir: rules\n");
+        assert_eq!(
+            running,
+            source("alertmanager_url: http://a\nalert_dir: other\nrule_packs: []\n")
+        );
+        assert_ne!(
+            running,
+            source("alertmanager_url: http://b\nalert_dir: rules\n")
+        );
+    }
 }
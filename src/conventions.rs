@@ -0,0 +1,57 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::enrichment::build_context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tera::Tera;
+
+// annotation and label names Karma and Grafana Alerting pick up for links and grouping
+const DASHBOARD_UID_ANNOTATION: &str = "__dashboardUid__";
+const PANEL_ID_ANNOTATION: &str = "__panelId__";
+const DASHBOARD_ANNOTATION: &str = "dashboard";
+const RUNBOOK_ANNOTATION: &str = "runbook_url";
+const FOLDER_LABEL: &str = "grafana_folder";
+
+// every value is a template rendered with the same context as enrichment rules
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputConventions {
+    dashboard_uid: Option<String>,
+    panel_id: Option<String>,
+    dashboard_url: Option<String>,
+    runbook_url: Option<String>,
+    grafana_folder: Option<String>,
+}
+
+impl OutputConventions {
+    // values set by enrichment rules win over the global conventions
+    pub fn apply(&self, alert: &mut AlertmanagerAlert) -> anyhow::Result<()> {
+        let annotations = [
+            (DASHBOARD_UID_ANNOTATION, &self.dashboard_uid),
+            (PANEL_ID_ANNOTATION, &self.panel_id),
+            (DASHBOARD_ANNOTATION, &self.dashboard_url),
+            (RUNBOOK_ANNOTATION, &self.runbook_url),
+        ];
+        for (name, template) in annotations {
+            if let Some(template) = template
+                && !alert.annotations().contains_key(name)
+                && let Some(value) = render(template, alert)?
+            {
+                alert.add_annotation(name, value);
+            }
+        }
+
+        if let Some(template) = &self.grafana_folder
+            && !alert.labels().contains_key(FOLDER_LABEL)
+            && let Some(value) = render(template, alert)?
+        {
+            alert.add_label(FOLDER_LABEL, value);
+        }
+
+        Ok(())
+    }
+}
+
+fn render(template: &str, alert: &AlertmanagerAlert) -> anyhow::Result<Option<String>> {
+    let ctx = build_context(alert, &BTreeMap::new())?;
+    let value = Tera::one_off(template, &ctx, false)?;
+    Ok(Some(value).filter(|v| !v.trim().is_empty()))
+}
@@ -0,0 +1,144 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::enrichment::is_full_match;
+use serde::Deserialize;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+fn equal_default() -> Vec<String> {
+    vec!["instance".to_string()]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CorrelationRule {
+    #[serde(with = "serde_regex")]
+    source: regex::Regex,
+    #[serde(with = "serde_regex")]
+    target: regex::Regex,
+    #[serde(default = "equal_default")]
+    equal: Vec<String>,
+    #[serde(default)]
+    suppress: bool,
+}
+
+impl CorrelationRule {
+    fn causes(&self, source: &AlertmanagerAlert, target: &AlertmanagerAlert) -> bool {
+        is_full_match(&self.source, source.name())
+            && is_full_match(&self.target, target.name())
+            && self.equal.iter().all(|label| {
+                let value = source.labels().get(label);
+                value.is_some() && value == target.labels().get(label)
+            })
+    }
+}
+
+// when two alerts would cause each other, only the one that started first is the cause
+fn precedes(a: &AlertmanagerAlert, b: &AlertmanagerAlert) -> bool {
+    let key = |alert: &AlertmanagerAlert| {
+        (
+            OffsetDateTime::parse(alert.starts_at(), &Rfc3339).ok(),
+            alert.labels().clone(),
+        )
+    };
+    key(a) < key(b)
+}
+
+pub fn correlate(rules: &[CorrelationRule], alerts: &mut [AlertmanagerAlert]) {
+    if rules.is_empty() {
+        return;
+    }
+
+    let causes: Vec<Option<(String, bool)>> = alerts
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            rules.iter().find_map(|rule| {
+                alerts
+                    .iter()
+                    .enumerate()
+                    .find(|(j, source)| {
+                        *j != i
+                            && rule.causes(source, target)
+                            && (!rule.causes(target, source) || precedes(source, target))
+                    })
+                    .map(|(_, source)| (source.name().to_string(), rule.suppress))
+            })
+        })
+        .collect();
+
+    for (alert, cause) in alerts.iter_mut().zip(causes) {
+        let Some((source, suppress)) = cause else {
+            continue;
+        };
+
+        alert.add_annotation("caused_by", &source);
+        if suppress {
+            alert.suppress(format!("caused by {source}"));
+        }
+    }
+}
+
+// how busy the sending device is, counted over everything firing including suppressed alerts
+pub fn count_device_alerts(alerts: &mut [AlertmanagerAlert], instance_label: Option<&str>) {
+    let device = |alert: &AlertmanagerAlert| match alert.source() {
+        Some(source) => Some(source.to_string()),
+        None => alert.labels().get(instance_label?).cloned(),
+    };
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for device in alerts.iter().filter_map(device) {
+        *counts.entry(device).or_default() += 1;
+    }
+
+    for alert in alerts.iter_mut() {
+        if let Some(count) = device(alert).and_then(|d| counts.get(&d)) {
+            alert.add_annotation("device_active_alerts", (count - 1).to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alertmanager::AlertmanagerAlert;
+    use crate::alerts::Severity;
+    use crate::config::test_settings;
+    use crate::correlation::correlate;
+    use std::collections::BTreeMap;
+    use time::OffsetDateTime;
+    use time::ext::NumericalDuration;
+
+    #[test]
+    fn mutual_causes_keep_the_earliest() {
+        let settings = test_settings(
+            r#"
+correlation_rules:
+  - source: "link.*"
+    target: "link.*"
+    suppress: true
+"#,
+        );
+        let now = OffsetDateTime::now_utc();
+        let alert = |name: &str, starts_at: OffsetDateTime| {
+            let labels = BTreeMap::from([("instance".to_string(), "sw1".to_string())]);
+            AlertmanagerAlert::new(
+                &settings,
+                starts_at,
+                now,
+                name,
+                "public",
+                Severity::Warning,
+                Some(labels),
+            )
+        };
+
+        let mut alerts = [
+            alert("linkFlap", now - 10.seconds()),
+            alert("linkDown", now - 60.seconds()),
+        ];
+        correlate(settings.correlation_rules(), &mut alerts);
+
+        assert_eq!(alerts[0].suppressed(), Some("caused by linkDown"));
+        assert_eq!(alerts[1].suppressed(), None);
+        assert!(!alerts[1].annotations().contains_key("caused_by"));
+    }
+}
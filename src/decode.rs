@@ -0,0 +1,212 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoding {
+    Auto,
+    Mac,
+    Ip,
+    DateAndTime,
+}
+
+impl Decoding {
+    pub fn decode(&self, value: &str) -> Option<String> {
+        let bytes = parse_hex(value)?;
+        match self {
+            Decoding::Auto => decode_auto(&bytes),
+            Decoding::Mac => decode_mac(&bytes),
+            Decoding::Ip => decode_ip(&bytes),
+            Decoding::DateAndTime => decode_date_and_time(&bytes),
+        }
+    }
+}
+
+pub fn auto_decode_labels(labels: &mut BTreeMap<String, String>) {
+    for value in labels.values_mut() {
+        if let Some(decoded) = Decoding::Auto.decode(value) {
+            *value = decoded;
+        }
+    }
+}
+
+// a dictionary from raw values to readable names, e.g. ifOperStatus 1 -> up
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(from = "HashMap<String, String>")]
+pub struct ValueNames(HashMap<String, String>);
+
+impl From<HashMap<String, String>> for ValueNames {
+    fn from(names: HashMap<String, String>) -> Self {
+        ValueNames(
+            names
+                .into_iter()
+                .map(|(value, name)| (canonical_value(&value).to_string(), name))
+                .collect(),
+        )
+    }
+}
+
+impl ValueNames {
+    pub fn name(&self, value: &str) -> Option<&str> {
+        self.0.get(canonical_value(value)).map(String::as_str)
+    }
+}
+
+// net-snmp may render the same value as `2`, `INTEGER: 2` or `"2"`, and OIDs with or without a leading dot
+fn canonical_value(value: &str) -> &str {
+    let value = value.trim();
+    let value = value.split_once(": ").map_or(value, |(_, v)| v).trim();
+    value.trim_matches('"').trim_start_matches('.')
+}
+
+pub fn name_label_values(
+    labels: &mut BTreeMap<String, String>,
+    names: &HashMap<String, ValueNames>,
+) {
+    for (label, value) in labels.iter_mut() {
+        if let Some(name) = names.get(label).and_then(|n| n.name(value)) {
+            *value = name.to_string();
+        }
+    }
+}
+
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("Hex-STRING:")
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value)
+        .trim();
+
+    let groups: Vec<&str> = if value.contains([' ', ':', '-']) {
+        value
+            .split([' ', ':', '-'])
+            .filter(|g| !g.is_empty())
+            .collect()
+    } else {
+        return None; // a bare string of hex digits is too ambiguous to decode
+    };
+
+    groups
+        .iter()
+        .map(|g| match g.len() {
+            1 | 2 => u8::from_str_radix(g, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn decode_auto(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        6 => decode_mac(bytes),
+        4 | 16 => decode_ip(bytes),
+        8 | 11 => decode_date_and_time(bytes),
+        _ => None,
+    }
+}
+
+fn decode_mac(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != 6 {
+        return None;
+    }
+
+    Some(
+        bytes
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+fn decode_ip(bytes: &[u8]) -> Option<String> {
+    if let Ok(octets) = <[u8; 4]>::try_from(bytes) {
+        return Some(Ipv4Addr::from(octets).to_string());
+    }
+    if let Ok(octets) = <[u8; 16]>::try_from(bytes) {
+        return Some(Ipv6Addr::from(octets).to_string());
+    }
+    None
+}
+
+// SNMPv2-TC DateAndTime: year(2) month day hour minutes seconds deci-seconds [direction hours minutes]
+fn decode_date_and_time(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != 8 && bytes.len() != 11 {
+        return None;
+    }
+
+    let year = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let [month, day, hour, minute, second, deci] = bytes[2..8] else {
+        return None;
+    };
+
+    let valid = (1..=12).contains(&month)
+        && (1..=31).contains(&day)
+        && hour <= 23
+        && minute <= 59
+        && second <= 60
+        && deci <= 9;
+    if !valid {
+        return None;
+    }
+
+    let mut out = format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{deci}");
+    match bytes.get(8..11) {
+        Some(&[direction @ (b'+' | b'-'), tz_hours, tz_minutes])
+            if tz_hours <= 13 && tz_minutes <= 59 =>
+        {
+            out.push_str(&format!(
+                "{}{tz_hours:02}:{tz_minutes:02}",
+                direction as char
+            ));
+        }
+        Some(_) => return None,
+        None => {}
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::{Decoding, ValueNames};
+    use std::collections::HashMap;
+
+    #[test]
+    fn decodes_common_encodings() {
+        assert_eq!(
+            Decoding::Auto
+                .decode("Hex-STRING: 00 1A 2B 3C 4D 5E")
+                .as_deref(),
+            Some("00:1a:2b:3c:4d:5e")
+        );
+        assert_eq!(
+            Decoding::Auto.decode("0A 00 00 01").as_deref(),
+            Some("10.0.0.1")
+        );
+        assert_eq!(
+            Decoding::Auto
+                .decode("07 E8 03 05 0A 14 1E 05 2B 01 00")
+                .as_deref(),
+            Some("2024-03-05T10:20:30.5+01:00")
+        );
+        assert_eq!(Decoding::Mac.decode("0A 00 00 01"), None);
+        assert_eq!(Decoding::Auto.decode("plain text"), None);
+        assert_eq!(Decoding::Auto.decode("001a2b3c4d5e"), None);
+    }
+
+    #[test]
+    fn value_names() {
+        let names = ValueNames::from(HashMap::from([
+            ("1".to_string(), "up".to_string()),
+            (".1.3.6.1.4.1.9".to_string(), "Cisco".to_string()),
+        ]));
+
+        assert_eq!(names.name("1"), Some("up"));
+        assert_eq!(names.name("INTEGER: 1"), Some("up"));
+        assert_eq!(names.name("1.3.6.1.4.1.9"), Some("Cisco"));
+        assert_eq!(names.name("OID: .1.3.6.1.4.1.9"), Some("Cisco"));
+        assert_eq!(names.name("2"), None);
+    }
+}
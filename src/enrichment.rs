@@ -1,49 +1,275 @@
 use crate::alertmanager::AlertmanagerAlert;
+use crate::alerts::Severity;
+use crate::config::Settings;
+use crate::decode::{Decoding, ValueNames};
+use crate::inventory::DeviceInventory;
+use anyhow::{Context as _, anyhow, bail};
+use indexmap::IndexMap;
 use itertools::Itertools;
-use serde::Deserialize;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
-use tera::{Context, Tera};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tera::{Context, Tera, Value};
 
+const ACTIVE_ALERTS_FUNCTION: &str = "active_alerts";
+const DEFAULT_LANGUAGE: &str = "en";
+
+#[derive(Default)]
 pub struct AlertEnrichment {
-    definitions: Vec<AlertEnrichmentDefinition>,
+    definitions: RwLock<Vec<AlertEnrichmentDefinition>>,
+    active: ActiveAlerts,
+    inventory: DeviceInventory,
 }
 
 impl AlertEnrichment {
     pub fn new() -> Self {
-        AlertEnrichment {
-            definitions: Vec::new(),
+        AlertEnrichment::default()
+    }
+
+    pub async fn load_configured(settings: &Settings) -> anyhow::Result<Self> {
+        let mut enrichment = AlertEnrichment::new();
+        if let Some(alert_dir) = settings.alert_dir() {
+            enrichment
+                .load_directory(alert_dir)
+                .context("alert directory")?;
+        }
+
+        for pack in settings.rule_packs() {
+            let files = pack
+                .load()
+                .await
+                .with_context(|| format!("rule pack {}", pack.source()))?;
+            let amount = enrichment.load_files(files)?;
+            info!(
+                "Loaded {amount} alert enrichments from rule pack {}",
+                pack.source()
+            );
+        }
+
+        if let Some(git) = settings.rules_git() {
+            enrichment
+                .load_rule_directory(&git.rules_dir())
+                .context("git rules checkout")?;
         }
+
+        Ok(enrichment)
     }
 
     pub fn load_directory(&mut self, dir: &Path) -> anyhow::Result<usize> {
-        let amount = self.count();
-        for entry in dir.read_dir()? {
-            let file = AlertEnrichmentFile::load(&entry?.path())?;
-            let alerts: Vec<_> = file
-                .alerts
-                .into_iter()
-                .map(|a| a.try_into())
-                .try_collect()?;
-            self.definitions.extend(alerts);
-        }
-        Ok(self.count() - amount)
+        let files: Vec<_> = dir
+            .read_dir()?
+            .map(|entry| AlertEnrichmentFile::load(&entry?.path()))
+            .try_collect()?;
+        self.load_files(files)
     }
 
+    // a repository checkout also holds .git, READMEs and CI files, only the rule files are loaded
+    pub fn load_rule_directory(&mut self, dir: &Path) -> anyhow::Result<usize> {
+        let files: Vec<_> = dir
+            .read_dir()?
+            .map(|entry| Ok::<_, anyhow::Error>(entry?.path()))
+            .filter_ok(|path| path.is_file() && is_rule_file(path))
+            .map(|path| AlertEnrichmentFile::load(&path?))
+            .try_collect()?;
+        self.load_files(files)
+    }
+
+    // nothing is added unless every definition is valid, so a broken pack can't be half applied
+    pub fn load_files(
+        &mut self,
+        files: impl IntoIterator<Item = AlertEnrichmentFile>,
+    ) -> anyhow::Result<usize> {
+        let definitions: Vec<AlertEnrichmentDefinition> = files
+            .into_iter()
+            .flat_map(|file| file.alerts)
+            .map(|a| a.try_into())
+            .map_ok(|d: AlertEnrichmentDefinition| d.with_active_alerts(&self.active))
+            .try_collect()?;
+        let amount = definitions.len();
+        self.definitions.get_mut().unwrap().extend(definitions);
+        Ok(amount)
+    }
+
+    // the reloaded templates have to query this instance's snapshot, not the one they were loaded with
+    pub fn replace(&self, other: AlertEnrichment) {
+        let definitions = other
+            .definitions
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|d| d.with_active_alerts(&self.active))
+            .collect();
+        *self.definitions.write().unwrap() = definitions;
+    }
+
+    // taken before enriching, so templates can look at everything else that's currently firing
+    pub fn set_active_alerts(&self, alerts: &[AlertmanagerAlert]) {
+        *self.active.0.write().unwrap() = alerts.iter().map(ActiveAlert::from).collect();
+    }
+
+    pub fn inventory(&self) -> &DeviceInventory {
+        &self.inventory
+    }
+
+    // inventory labels come first, so the enrichment templates can build on them
     pub fn apply_all(&self, alert: &mut AlertmanagerAlert) -> anyhow::Result<()> {
-        for definition in &self.definitions {
+        self.inventory.apply(alert);
+        for definition in self.definitions.read().unwrap().iter() {
             definition.apply(alert)?;
         }
         Ok(())
     }
 
     pub fn count(&self) -> usize {
-        self.definitions.len()
+        self.definitions.read().unwrap().len()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ActiveAlert {
+    name: String,
+    community: String,
+    severity: String,
+    labels: BTreeMap<String, String>,
+}
+
+impl From<&AlertmanagerAlert> for ActiveAlert {
+    fn from(alert: &AlertmanagerAlert) -> Self {
+        ActiveAlert {
+            name: alert.name().to_string(),
+            community: alert.community().to_string(),
+            severity: alert.severity().to_string(),
+            labels: alert.labels().clone(),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct ActiveAlerts(Arc<RwLock<Vec<ActiveAlert>>>);
+
+impl ActiveAlerts {
+    // active_alerts(name="upsOnBattery", labels=["host=" ~ labels.host]) lists the matching alerts
+    fn query(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let name = match args.get("name") {
+            None => None,
+            Some(Value::String(name)) => {
+                Some(regex::Regex::new(name).map_err(|e| tera::Error::msg(e.to_string()))?)
+            }
+            Some(other) => {
+                return Err(tera::Error::msg(format!(
+                    "`{ACTIVE_ALERTS_FUNCTION}` expects a name regex, got {other}"
+                )));
+            }
+        };
+        let matchers: Vec<LabelMatcher> = match args.get("labels") {
+            None => Vec::new(),
+            Some(Value::Array(matchers)) => matchers
+                .iter()
+                .map(|m| match m {
+                    Value::String(m) => m.parse(),
+                    other => Err(anyhow!("label matchers are strings, got {other}")),
+                })
+                .try_collect()
+                .map_err(|e| tera::Error::msg(format!("`{ACTIVE_ALERTS_FUNCTION}`: {e}")))?,
+            Some(other) => {
+                return Err(tera::Error::msg(format!(
+                    "`{ACTIVE_ALERTS_FUNCTION}` expects a list of label matchers, got {other}"
+                )));
+            }
+        };
+
+        let active = self.0.read().unwrap();
+        let matching = active
+            .iter()
+            .filter(|alert| {
+                name.as_ref()
+                    .is_none_or(|rgx| is_full_match(rgx, &alert.name))
+            })
+            .filter(|alert| matchers.iter().all(|m| m.matches(&alert.labels)))
+            .collect_vec();
+        Ok(tera::to_value(matching)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum LabelMatch {
+    Equal(String),
+    NotEqual(String),
+    Regex(regex::Regex),
+    NotRegex(regex::Regex),
+}
+
+// the same matcher syntax Alertmanager uses, e.g. `host=sw1` or `ifDescr=~Gi.*`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LabelMatcher {
+    label: String,
+    matching: LabelMatch,
+}
+
+impl FromStr for LabelMatcher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let idx = s
+            .find(['=', '!'])
+            .ok_or_else(|| anyhow!("no operator in label matcher {s:?}"))?;
+        let label = s[..idx].trim();
+        if label.is_empty() {
+            bail!("missing label name in label matcher {s:?}");
+        }
+
+        let rest = &s[idx..];
+        let matching = if let Some(value) = rest.strip_prefix("=~") {
+            LabelMatch::Regex(regex::Regex::new(value)?)
+        } else if let Some(value) = rest.strip_prefix("!~") {
+            LabelMatch::NotRegex(regex::Regex::new(value)?)
+        } else if let Some(value) = rest.strip_prefix("!=") {
+            LabelMatch::NotEqual(value.to_string())
+        } else if let Some(value) = rest.strip_prefix('=') {
+            LabelMatch::Equal(value.to_string())
+        } else {
+            bail!("unknown operator in label matcher {s:?}");
+        };
+
+        Ok(LabelMatcher {
+            label: label.to_string(),
+            matching,
+        })
     }
 }
 
+impl TryFrom<String> for LabelMatcher {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl LabelMatcher {
+    // like in Alertmanager, a missing label matches as if it was empty
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let value = labels.get(&self.label).map(String::as_str).unwrap_or("");
+        match &self.matching {
+            LabelMatch::Equal(expected) => value == expected,
+            LabelMatch::NotEqual(expected) => value != expected,
+            LabelMatch::Regex(rgx) => is_full_match(rgx, value),
+            LabelMatch::NotRegex(rgx) => !is_full_match(rgx, value),
+        }
+    }
+}
+
+pub fn is_rule_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AlertEnrichmentFile {
     alerts: Vec<RawAlertEnrichmentDefinition>,
@@ -51,8 +277,11 @@ pub struct AlertEnrichmentFile {
 
 impl AlertEnrichmentFile {
     pub fn load(file: &Path) -> anyhow::Result<AlertEnrichmentFile> {
-        let content = fs::read_to_string(file)?;
-        Ok(serde_norway::from_str(&content)?)
+        Self::parse(&fs::read_to_string(file)?)
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<AlertEnrichmentFile> {
+        Ok(serde_norway::from_str(content)?)
     }
 }
 
@@ -60,32 +289,149 @@ impl AlertEnrichmentFile {
 pub struct RawAlertEnrichmentDefinition {
     #[serde(with = "serde_regex")]
     name: regex::Regex,
-    labels: Option<HashMap<String, String>>,
-    annotations: Option<HashMap<String, String>>,
+    #[serde(default, with = "serde_regex")]
+    name_exclude: Option<regex::Regex>,
+    #[serde(default)]
+    unless_labels: HashMap<String, String>,
+    labels: Option<IndexMap<String, String>>,
+    annotations: Option<IndexMap<String, String>>,
     #[serde(with = "serde_regex")]
     drop_labels: Option<Vec<regex::Regex>>,
+    decode: Option<HashMap<String, Decoding>>,
+    value_names: Option<HashMap<String, ValueNames>>,
+    thresholds: Option<Vec<ThresholdRule>>,
+    #[serde(default)]
+    capture_labels: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    label: String,
+    comparison: Comparison,
+    value: f64,
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const OPERATORS: &[(&str, Comparison)] = &[
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            (">=", Comparison::Ge),
+            ("<=", Comparison::Le),
+            (">", Comparison::Gt),
+            ("<", Comparison::Lt),
+        ];
+
+        let (idx, op, comparison) = OPERATORS
+            .iter()
+            .filter_map(|(op, cmp)| s.find(op).map(|idx| (idx, *op, *cmp)))
+            .min_by_key(|(idx, op, _)| (*idx, usize::MAX - op.len()))
+            .ok_or_else(|| anyhow!("no comparison operator in condition {s:?}"))?;
+
+        let label = s[..idx].trim();
+        if label.is_empty() {
+            bail!("missing label name in condition {s:?}");
+        }
+        let value = s[idx + op.len()..].trim().parse()?;
+
+        Ok(Condition {
+            label: label.to_string(),
+            comparison,
+            value,
+        })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let Some(value) = labels.get(&self.label).and_then(|v| parse_numeric(v)) else {
+            return false;
+        };
+
+        match self.comparison {
+            Comparison::Eq => value == self.value,
+            Comparison::Ne => value != self.value,
+            Comparison::Gt => value > self.value,
+            Comparison::Ge => value >= self.value,
+            Comparison::Lt => value < self.value,
+            Comparison::Le => value <= self.value,
+        }
+    }
+}
+
+// accepts plain numbers as well as net-snmp enum renderings like "down(2)"
+fn parse_numeric(value: &str) -> Option<f64> {
+    let value = value.trim();
+    if let Ok(n) = value.parse() {
+        return Some(n);
+    }
+
+    let inner = value.strip_suffix(')')?;
+    inner[inner.rfind('(')? + 1..].parse().ok()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ThresholdRule {
+    condition: Condition,
+    severity: Option<Severity>,
+    #[serde(default)]
+    drop: bool,
 }
 
 pub struct AlertEnrichmentDefinition {
     name: regex::Regex,
-    label_templates: Tera,
-    annotation_templates: Tera,
+    name_exclude: Option<regex::Regex>,
+    unless_labels: HashMap<String, regex::Regex>,
+    label_templates: OrderedTemplates,
+    annotation_templates: OrderedTemplates,
     drop_labels: Vec<regex::Regex>,
+    decode: Vec<(regex::Regex, Decoding)>,
+    value_names: Vec<(regex::Regex, ValueNames)>,
+    thresholds: Vec<ThresholdRule>,
+    capture_labels: bool,
 }
 
 impl TryFrom<RawAlertEnrichmentDefinition> for AlertEnrichmentDefinition {
     type Error = anyhow::Error;
 
     fn try_from(raw: RawAlertEnrichmentDefinition) -> Result<Self, Self::Error> {
-        Self::new(raw.name, raw.labels, raw.annotations, raw.drop_labels)
+        Ok(
+            Self::new(raw.name, raw.labels, raw.annotations, raw.drop_labels)?
+                .with_exclusions(raw.name_exclude, raw.unless_labels)?
+                .with_decode(raw.decode.unwrap_or_default())?
+                .with_value_names(raw.value_names.unwrap_or_default())?
+                .with_thresholds(raw.thresholds.unwrap_or_default())
+                .with_capture_labels(raw.capture_labels),
+        )
     }
 }
 
 impl AlertEnrichmentDefinition {
     pub fn new(
         name: regex::Regex,
-        labels: Option<HashMap<String, String>>,
-        annotations: Option<HashMap<String, String>>,
+        labels: Option<IndexMap<String, String>>,
+        annotations: Option<IndexMap<String, String>>,
         drop_labels: Option<Vec<regex::Regex>>,
     ) -> anyhow::Result<Self> {
         let annotations = annotations.unwrap_or_default();
@@ -97,16 +443,103 @@ impl AlertEnrichmentDefinition {
 
         Ok(AlertEnrichmentDefinition {
             name,
+            name_exclude: None,
+            unless_labels: HashMap::new(),
             label_templates,
             annotation_templates,
             drop_labels,
+            decode: Vec::new(),
+            value_names: Vec::new(),
+            thresholds: Vec::new(),
+            capture_labels: false,
         })
     }
 
+    pub fn with_exclusions(
+        mut self,
+        name_exclude: Option<regex::Regex>,
+        unless_labels: HashMap<String, String>,
+    ) -> anyhow::Result<Self> {
+        self.name_exclude = name_exclude;
+        for (label, pattern) in unless_labels {
+            self.unless_labels
+                .insert(label, regex::Regex::new(&pattern)?);
+        }
+        Ok(self)
+    }
+
+    pub fn with_decode(mut self, decode: HashMap<String, Decoding>) -> anyhow::Result<Self> {
+        for (label, decoding) in decode {
+            self.decode.push((regex::Regex::new(&label)?, decoding));
+        }
+        Ok(self)
+    }
+
+    pub fn with_value_names(
+        mut self,
+        value_names: HashMap<String, ValueNames>,
+    ) -> anyhow::Result<Self> {
+        for (label, names) in value_names {
+            self.value_names.push((regex::Regex::new(&label)?, names));
+        }
+        Ok(self)
+    }
+
+    pub fn with_thresholds(mut self, thresholds: Vec<ThresholdRule>) -> Self {
+        self.thresholds.extend(thresholds);
+        self
+    }
+
+    pub fn with_capture_labels(mut self, capture_labels: bool) -> Self {
+        self.capture_labels = capture_labels;
+        self
+    }
+
+    fn with_active_alerts(mut self, active: &ActiveAlerts) -> Self {
+        for templates in [&mut self.label_templates, &mut self.annotation_templates] {
+            let active = active.clone();
+            templates.tera.register_function(
+                ACTIVE_ALERTS_FUNCTION,
+                move |args: &HashMap<String, Value>| active.query(args),
+            );
+        }
+        self
+    }
+
     pub fn applies_to(&self, alert: &AlertmanagerAlert) -> bool {
+        is_full_match(&self.name, alert.name()) && !self.is_excluded(alert.name(), alert.labels())
+    }
+
+    // unless_labels only carves out alerts where every listed label matches
+    fn is_excluded(&self, name: &str, labels: &BTreeMap<String, String>) -> bool {
+        let name_excluded = self
+            .name_exclude
+            .as_ref()
+            .is_some_and(|rgx| is_full_match(rgx, name));
+        let labels_excluded = !self.unless_labels.is_empty()
+            && self
+                .unless_labels
+                .iter()
+                .all(|(label, rgx)| labels.get(label).is_some_and(|v| is_full_match(rgx, v)));
+
+        name_excluded || labels_excluded
+    }
+
+    pub fn name_captures(&self, name: &str) -> BTreeMap<String, String> {
+        let Some(captures) = self.name.captures(name) else {
+            return BTreeMap::new();
+        };
+
         self.name
-            .find_at(alert.name(), 0)
-            .is_some_and(|m| m.len() == alert.name().len())
+            .capture_names()
+            .flatten()
+            .filter_map(|group| {
+                Some((
+                    group.to_string(),
+                    captures.name(group)?.as_str().to_string(),
+                ))
+            })
+            .collect()
     }
 
     pub fn apply(&self, alert: &mut AlertmanagerAlert) -> anyhow::Result<bool> {
@@ -114,13 +547,66 @@ impl AlertEnrichmentDefinition {
             return Ok(false);
         }
 
-        alert.add_labels(&generate_labels(&self.label_templates, alert)?);
-        alert.add_annotations(&generate_labels(&self.annotation_templates, alert)?);
+        let captures = self.name_captures(alert.name());
+        if self.capture_labels {
+            alert.add_labels(&captures);
+        }
+
+        let decoded = alert
+            .labels()
+            .iter()
+            .filter_map(|(name, value)| {
+                let (_, decoding) = self
+                    .decode
+                    .iter()
+                    .find(|(rgx, _)| is_full_match(rgx, name))?;
+                Some((name.clone(), decoding.decode(value)?))
+            })
+            .collect_vec();
+        alert.add_labels(decoded);
+
+        for rule in &self.thresholds {
+            if !rule.condition.matches(alert.labels()) {
+                continue;
+            }
+            if let Some(severity) = rule.severity {
+                alert.set_severity(severity);
+            }
+            if rule.drop {
+                alert.suppress(format!("threshold {:?}", rule.condition.label));
+            }
+        }
+
+        // named only after the thresholds, those compare the numeric values
+        let named = alert
+            .labels()
+            .iter()
+            .filter_map(|(name, value)| {
+                let (_, names) = self
+                    .value_names
+                    .iter()
+                    .find(|(rgx, _)| is_full_match(rgx, name))?;
+                Some((name.clone(), names.name(value)?.to_string()))
+            })
+            .collect_vec();
+        alert.add_labels(named);
+
+        self.label_templates
+            .render_in_order(alert, &captures, None, |alert, name, value| {
+                alert.add_label(name, value)
+            })?;
+        let language = alert.language().map(str::to_string);
+        self.annotation_templates.render_in_order(
+            alert,
+            &captures,
+            language.as_deref(),
+            |alert, name, value| alert.add_annotation(name, value),
+        )?;
 
         let label_names = alert.labels().keys().cloned().collect_vec();
         for rgx in &self.drop_labels {
             for name in &label_names {
-                if rgx.find_at(name, 0).is_some_and(|m| m.len() == name.len()) {
+                if is_full_match(rgx, name) {
                     alert.remove_label(name);
                 }
             }
@@ -130,7 +616,87 @@ impl AlertEnrichmentDefinition {
     }
 }
 
-fn build_templates<I, S, S2>(values: I) -> tera::Result<Tera>
+pub fn is_full_match(rgx: &regex::Regex, haystack: &str) -> bool {
+    rgx.find_at(haystack, 0)
+        .is_some_and(|m| m.len() == haystack.len())
+}
+
+struct OrderedTemplates {
+    tera: Tera,
+    order: Vec<String>,
+}
+
+impl OrderedTemplates {
+    // each template sees the values rendered before it, so later ones can build on earlier results.
+    // blank results are skipped, which lets `{% if %}` templates leave optional values out entirely
+    fn render_in_order(
+        &self,
+        alert: &mut AlertmanagerAlert,
+        captures: &BTreeMap<String, String>,
+        language: Option<&str>,
+        mut set: impl FnMut(&mut AlertmanagerAlert, &str, String),
+    ) -> tera::Result<()> {
+        for (name, output) in self.localized_order(language) {
+            let value = self.tera.render(name, &build_context(alert, captures)?)?;
+            if value.trim().is_empty() {
+                debug!(
+                    "Template {name:?} rendered empty for {:?}, skipping it",
+                    alert.name()
+                );
+                continue;
+            }
+            set(alert, output, value);
+        }
+        Ok(())
+    }
+
+    // `description.de` replaces `description` for German alerts. Without a variant in the
+    // alert's language the plain template is used, and English when there's only localized ones
+    fn localized_order(&self, language: Option<&str>) -> Vec<(&str, &str)> {
+        let mut chosen: IndexMap<&str, (usize, usize)> = IndexMap::new();
+        for (position, name) in self.order.iter().enumerate() {
+            let (output, variant) = split_language(name);
+            let rank = match variant {
+                Some(variant) if Some(variant) == language => 0,
+                None => 1,
+                Some(DEFAULT_LANGUAGE) => 2,
+                Some(_) => continue,
+            };
+            let best = chosen.entry(output).or_insert((rank, position));
+            if rank < best.0 {
+                *best = (rank, position);
+            }
+        }
+
+        chosen
+            .into_iter()
+            .sorted_by_key(|(_, (_, position))| *position)
+            .map(|(output, (_, position))| (self.order[position].as_str(), output))
+            .collect()
+    }
+}
+
+// a trailing ISO 639-1 code, optionally with a region like `pt-BR`
+fn split_language(name: &str) -> (&str, Option<&str>) {
+    let Some((base, suffix)) = name.rsplit_once('.') else {
+        return (name, None);
+    };
+    let (language, region) = match suffix.split_once(['-', '_']) {
+        Some((language, region)) => (language, Some(region)),
+        None => (suffix, None),
+    };
+
+    let is_language = language.len() == 2
+        && language.bytes().all(|b| b.is_ascii_lowercase())
+        && region.is_none_or(|r| r.len() == 2 && r.bytes().all(|b| b.is_ascii_uppercase()));
+    if is_language && !base.is_empty() {
+        (base, Some(suffix))
+    } else {
+        (name, None)
+    }
+}
+
+fn build_templates<I, S, S2>(values: I) -> tera::Result<OrderedTemplates>
 where
     I: IntoIterator<Item = (S, S2)>,
     S: AsRef<str>,
@@ -138,54 +704,206 @@ where
 {
     let mut tera = Tera::default();
     tera.set_strict(false);
+    let mut order = Vec::new();
     for (k, v) in values {
         tera.add_raw_template(k.as_ref(), v.as_ref())?;
+        order.push(k.as_ref().to_string());
     }
-    Ok(tera)
+    Ok(OrderedTemplates { tera, order })
 }
 
-fn build_context(alert: &AlertmanagerAlert) -> tera::Result<Context> {
-    let labels = alert.labels();
+pub fn build_context(
+    alert: &AlertmanagerAlert,
+    captures: &BTreeMap<String, String>,
+) -> tera::Result<Context> {
     Context::from_value(json!({
-        "labels": labels,
+        "name": alert.name(),
+        "community": alert.community(),
+        "severity": alert.severity(),
+        "starts_at": alert.starts_at(),
+        "ends_at": alert.ends_at(),
+        "count": alert.count(),
+        "labels": alert.labels(),
+        "annotations": alert.annotations(),
+        "captures": captures,
     }))
 }
 
-pub fn generate_labels(
-    templates: &Tera,
-    alert: &AlertmanagerAlert,
-) -> tera::Result<HashMap<String, String>> {
-    let mut labels = HashMap::new();
-    let ctx = build_context(alert)?;
-    for name in templates.get_template_names() {
-        let value = templates.render(name, &ctx)?;
-        labels.insert(name.to_string(), value);
-    }
-    Ok(labels)
-}
-
 #[cfg(test)]
 mod tests {
     use crate::alertmanager::AlertmanagerAlert;
     use crate::alerts::Severity;
-    use crate::enrichment::AlertEnrichmentDefinition;
+    use crate::config::test_settings;
+    use crate::enrichment::{
+        AlertEnrichment, AlertEnrichmentDefinition, AlertEnrichmentFile, Condition,
+    };
+    use indexmap::IndexMap;
     use regex::Regex;
+    use std::collections::{BTreeMap, HashMap};
     use time::OffsetDateTime;
 
     #[test]
     fn enrichment_applies() {
         let def = AlertEnrichmentDefinition::new(Regex::new(r"test.*").unwrap(), None, None, None)
             .unwrap();
+        let settings = test_settings("alertmanager_community_label: job\n");
         let alert = AlertmanagerAlert::new(
+            &settings,
             OffsetDateTime::now_utc(),
             OffsetDateTime::now_utc(),
             "testAlert",
             "somejob",
             Severity::Info,
             None,
-            None,
         );
 
         assert!(def.applies_to(&alert));
+        assert_eq!(
+            alert.labels().get("job").map(String::as_str),
+            Some("somejob")
+        );
+    }
+
+    #[test]
+    fn exclusions() {
+        let def =
+            AlertEnrichmentDefinition::new(Regex::new(r".*Power.*").unwrap(), None, None, None)
+                .unwrap()
+                .with_exclusions(
+                    Some(Regex::new(r"upsPowerTest.*").unwrap()),
+                    HashMap::from([("ifDescr".to_string(), "Lab.*".to_string())]),
+                )
+                .unwrap();
+        let labels = BTreeMap::from([("ifDescr".to_string(), "Lab PDU".to_string())]);
+
+        assert!(def.is_excluded("upsPowerTestStarted", &BTreeMap::new()));
+        assert!(def.is_excluded("pduPowerLost", &labels));
+        assert!(!def.is_excluded("pduPowerLost", &BTreeMap::new()));
+    }
+
+    #[test]
+    fn name_captures() {
+        let def = AlertEnrichmentDefinition::new(
+            Regex::new(r"psuModule(?<module>\d+)(Failed|Restored)").unwrap(),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let captures = def.name_captures("psuModule3Failed");
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures["module"], "3");
+    }
+
+    #[test]
+    fn threshold_conditions() {
+        let labels = BTreeMap::from([
+            ("temperature".to_string(), "72".to_string()),
+            ("ifOperStatus".to_string(), "down(2)".to_string()),
+        ]);
+
+        let hot: Condition = "temperature > 70".parse().unwrap();
+        let down: Condition = "ifOperStatus == 2".parse().unwrap();
+        let cold: Condition = "temperature<=70".parse().unwrap();
+        let missing: Condition = "fanSpeed >= 1".parse().unwrap();
+
+        assert!(hot.matches(&labels));
+        assert!(down.matches(&labels));
+        assert!(!cold.matches(&labels));
+        assert!(!missing.matches(&labels));
+        assert!("temperature 70".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn active_alert_query() {
+        let settings = test_settings("");
+        let alert = |name: &str, host: &str| {
+            AlertmanagerAlert::new(
+                &settings,
+                OffsetDateTime::now_utc(),
+                OffsetDateTime::now_utc(),
+                name,
+                "public",
+                Severity::Warning,
+                Some(BTreeMap::from([("host".to_string(), host.to_string())])),
+            )
+        };
+        let file = AlertEnrichmentFile::parse(
+            r#"
+alerts:
+  - name: linkDown
+    drop_labels: []
+    annotations:
+      context: >-
+        {% for other in active_alerts(name="ups.*", labels=["host=" ~ labels.host]) -%}
+        {{ other.labels.host }} also reports {{ other.name }}
+        {%- endfor %}
+"#,
+        )
+        .unwrap();
+        let mut enrichment = AlertEnrichment::new();
+        enrichment.load_files([file]).unwrap();
+
+        let mut alerts = vec![
+            alert("linkDown", "sw1"),
+            alert("linkDown", "sw2"),
+            alert("upsOnBattery", "sw1"),
+        ];
+        enrichment.set_active_alerts(&alerts);
+        for alert in &mut alerts {
+            enrichment.apply_all(alert).unwrap();
+        }
+
+        assert_eq!(
+            alerts[0].annotations().get("context").map(String::as_str),
+            Some("sw1 also reports upsOnBattery")
+        );
+        assert_eq!(alerts[1].annotations().get("context"), None);
+    }
+
+    #[test]
+    fn localized_annotations() {
+        let settings = test_settings(
+            "community_annotation_languages:\n  \
+               nocde: de\n",
+        );
+        let annotations = IndexMap::from([
+            ("summary.en", "Link down"),
+            ("summary.de", "Link ausgefallen"),
+            ("runbook.url", "https://wiki/link"),
+        ]);
+        let def = AlertEnrichmentDefinition::new(
+            Regex::new("linkDown").unwrap(),
+            None,
+            Some(
+                annotations
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            None,
+        )
+        .unwrap();
+
+        for (community, summary) in [("nocde", "Link ausgefallen"), ("public", "Link down")] {
+            let mut alert = AlertmanagerAlert::new(
+                &settings,
+                OffsetDateTime::now_utc(),
+                OffsetDateTime::now_utc(),
+                "linkDown",
+                community,
+                Severity::Warning,
+                None,
+            );
+            def.apply(&mut alert).unwrap();
+
+            assert_eq!(
+                alert.annotations().get("summary").map(String::as_str),
+                Some(summary)
+            );
+            assert!(alert.annotations().contains_key("runbook.url"));
+            assert!(!alert.annotations().contains_key("summary.de"));
+        }
     }
 }
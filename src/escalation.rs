@@ -0,0 +1,270 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::alerts::Severity;
+use crate::chat::ChatSettings;
+use crate::enrichment::is_full_match;
+use crate::notifier::{Notifier, Schedule};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use time::{Duration, OffsetDateTime};
+
+pub const ESCALATED_LABEL: &str = "escalated";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EscalationPolicy {
+    name: String,
+    after_min: u32,
+    severity: Option<String>,
+    community: Option<String>,
+    #[serde(default, with = "serde_regex")]
+    alert_name: Option<regex::Regex>,
+    // the severity and the label are part of the Alertmanager fingerprint, so with either set
+    // the original alert resolves and the escalated one fires as a new alert. The policy is
+    // always named in the "escalated" annotation, which keeps the fingerprint
+    set_severity: Option<Severity>,
+    #[serde(default)]
+    label: bool,
+    chat: Option<ChatSettings>,
+}
+
+impl EscalationPolicy {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn chat(&self) -> Option<&ChatSettings> {
+        self.chat.as_ref()
+    }
+
+    fn after(&self) -> Duration {
+        Duration::minutes(self.after_min as i64)
+    }
+
+    fn matches(&self, name: &str, severity: &str, community: &str) -> bool {
+        self.severity.as_ref().is_none_or(|s| s == severity)
+            && self.community.as_ref().is_none_or(|c| c == community)
+            && self
+                .alert_name
+                .as_ref()
+                .is_none_or(|rgx| is_full_match(rgx, name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Unacknowledged {
+    #[serde(with = "time::serde::rfc3339")]
+    since: OffsetDateTime,
+}
+
+// an alert counts as acknowledged once it drops out of the relayed set, by being cleared,
+// snoozed or suppressed. The timers are kept on disk so a restart doesn't start them over
+pub struct Escalations {
+    policies: Vec<EscalationPolicy>,
+    state_file: Option<PathBuf>,
+    unacknowledged: Mutex<HashMap<u64, Unacknowledged>>,
+    // generation of the state taken for writing and of the one last written
+    generation: Mutex<u64>,
+    written: Arc<Mutex<u64>>,
+}
+
+impl Escalations {
+    pub fn load(policies: &[EscalationPolicy], state_file: Option<&Path>) -> Escalations {
+        let unacknowledged = state_file
+            .filter(|path| path.exists())
+            .and_then(|path| match read_state(path) {
+                Ok(state) => Some(state),
+                Err(e) => {
+                    warn!("Failed to read escalation state from {path:?}, timers start over: {e}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Escalations {
+            policies: policies.to_vec(),
+            state_file: state_file.map(Path::to_path_buf),
+            unacknowledged: Mutex::new(unacknowledged),
+            generation: Mutex::default(),
+            written: Arc::default(),
+        }
+    }
+
+    pub fn apply(&self, alerts: &mut [AlertmanagerAlert]) {
+        if self.policies.is_empty() {
+            return;
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let mut unacknowledged = self
+            .unacknowledged
+            .lock()
+            .expect("escalation state lock poisoned");
+        let before = unacknowledged.len();
+        unacknowledged.retain(|hash, _| alerts.iter().any(|a| a.source_hash() == Some(*hash)));
+        let mut changed = unacknowledged.len() != before;
+
+        for alert in alerts.iter_mut() {
+            let Some(hash) = alert.source_hash() else {
+                continue;
+            };
+            let since = unacknowledged
+                .entry(hash)
+                .or_insert_with(|| {
+                    changed = true;
+                    Unacknowledged { since: now }
+                })
+                .since;
+            self.escalate(alert, now - since);
+        }
+
+        if changed && let Some(path) = &self.state_file {
+            self.write_state_logged(path.clone(), unacknowledged.clone());
+        }
+    }
+
+    // the file IO blocks, so it's moved off the async runtime. A write that got overtaken
+    // by a newer one is skipped instead of putting back the older state
+    fn write_state_logged(&self, path: PathBuf, state: HashMap<u64, Unacknowledged>) {
+        let generation = {
+            let mut generation = self
+                .generation
+                .lock()
+                .expect("escalation state lock poisoned");
+            *generation += 1;
+            *generation
+        };
+        let written = self.written.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap_or_else(|e| e.into_inner());
+            if *written > generation {
+                return;
+            }
+            match write_state(&path, &state) {
+                Ok(()) => *written = generation,
+                Err(e) => warn!("Failed to write escalation state to {path:?}: {e}"),
+            }
+        });
+    }
+
+    // policies are matched against the alert as it was, so raising the severity in one
+    // doesn't pull the alert into another
+    fn escalate(&self, alert: &mut AlertmanagerAlert, unacknowledged: Duration) {
+        let name = alert.name().to_string();
+        let severity = alert.severity().to_string();
+        let community = alert.community().to_string();
+
+        for policy in &self.policies {
+            if unacknowledged < policy.after() || !policy.matches(&name, &severity, &community) {
+                continue;
+            }
+
+            if let Some(severity) = policy.set_severity {
+                alert.set_severity(severity);
+            }
+            if policy.label {
+                alert.add_label(ESCALATED_LABEL, &policy.name);
+            }
+            alert.add_annotation(ESCALATED_LABEL, &policy.name);
+            alert.mark_escalated(&policy.name);
+        }
+    }
+}
+
+fn read_state(path: &Path) -> anyhow::Result<HashMap<u64, Unacknowledged>> {
+    let state = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    info!("Loaded escalation state from {path:?}");
+    Ok(state)
+}
+
+fn write_state(path: &Path, state: &HashMap<u64, Unacknowledged>) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string(state)?)?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+// an extra sink that only ever hears about the alerts one policy escalated
+pub struct EscalationNotifier<N> {
+    policy: String,
+    notifier: N,
+}
+
+impl<N: Notifier> EscalationNotifier<N> {
+    pub fn new(policy: impl Into<String>, notifier: N) -> Self {
+        Self {
+            policy: policy.into(),
+            notifier,
+        }
+    }
+}
+
+#[async_trait]
+impl<N: Notifier> Notifier for EscalationNotifier<N> {
+    fn name(&self) -> &str {
+        self.notifier.name()
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.notifier.schedule()
+    }
+
+    async fn notify(&mut self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        let escalated: Vec<AlertmanagerAlert> = alerts
+            .iter()
+            .filter(|a| a.escalations().contains(&self.policy))
+            .cloned()
+            .collect();
+        self.notifier.notify(&escalated).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alertmanager::AlertmanagerAlert;
+    use crate::alerts::Severity;
+    use crate::config::test_settings;
+    use crate::escalation::Escalations;
+    use time::{Duration, OffsetDateTime};
+
+    #[test]
+    fn escalates_unacknowledged_alerts() {
+        let settings = test_settings(
+            r#"
+escalations:
+  - name: now
+    after_min: 0
+    severity: warning
+    set_severity: critical
+    label: true
+  - name: later
+    after_min: 60
+"#,
+        );
+        let mut alert = AlertmanagerAlert::new(
+            &settings,
+            OffsetDateTime::now_utc(),
+            OffsetDateTime::now_utc(),
+            "linkDown",
+            "public",
+            Severity::Warning,
+            None,
+        );
+
+        let escalations = Escalations::load(settings.escalations(), None);
+        escalations.escalate(&mut alert, Duration::minutes(5));
+
+        assert_eq!(alert.severity(), "critical");
+        assert_eq!(
+            alert.labels().get("escalated").map(String::as_str),
+            Some("now")
+        );
+        assert_eq!(
+            alert.annotations().get("escalated").map(String::as_str),
+            Some("now")
+        );
+        assert_eq!(alert.escalations(), ["now"]);
+    }
+}
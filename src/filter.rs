@@ -0,0 +1,97 @@
+use crate::alerts::Alert;
+use ipnet::IpNet;
+use log::debug;
+use serde::Deserialize;
+use std::net::IpAddr;
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    #[default]
+    Drop,
+    Tag,
+}
+
+fn tag_label_default() -> String {
+    "untrusted_source".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceFilter {
+    #[serde(default)]
+    allow_sources: Vec<IpNet>,
+    #[serde(default)]
+    deny_sources: Vec<IpNet>,
+    #[serde(default)]
+    allow_communities: Vec<String>,
+    #[serde(default)]
+    deny_communities: Vec<String>,
+    #[serde(default)]
+    action: FilterAction,
+    #[serde(default = "tag_label_default")]
+    tag_label: String,
+}
+
+impl Default for SourceFilter {
+    fn default() -> Self {
+        SourceFilter {
+            allow_sources: Vec::new(),
+            deny_sources: Vec::new(),
+            allow_communities: Vec::new(),
+            deny_communities: Vec::new(),
+            action: FilterAction::default(),
+            tag_label: tag_label_default(),
+        }
+    }
+}
+
+impl SourceFilter {
+    pub fn rejection_reason(&self, source: Option<IpAddr>, community: &str) -> Option<String> {
+        let contains = |nets: &[IpNet], ip: IpAddr| nets.iter().any(|net| net.contains(&ip));
+
+        match source {
+            Some(ip) if contains(&self.deny_sources, ip) => {
+                return Some(format!("source {ip} is denied"));
+            }
+            Some(ip) if !self.allow_sources.is_empty() && !contains(&self.allow_sources, ip) => {
+                return Some(format!("source {ip} is not allowed"));
+            }
+            None if !self.allow_sources.is_empty() => {
+                return Some("unknown source address".to_string());
+            }
+            _ => {}
+        }
+
+        if self.deny_communities.iter().any(|c| c == community) {
+            return Some(format!("community {community:?} is denied"));
+        }
+        if !self.allow_communities.is_empty()
+            && !self.allow_communities.iter().any(|c| c == community)
+        {
+            return Some(format!("community {community:?} is not allowed"));
+        }
+
+        None
+    }
+
+    pub fn tag_label(&self) -> &str {
+        &self.tag_label
+    }
+
+    pub fn apply(&self, mut alert: Alert) -> Option<Alert> {
+        let Some(reason) = self.rejection_reason(alert.source(), alert.community()) else {
+            return Some(alert);
+        };
+
+        match self.action {
+            FilterAction::Drop => {
+                debug!("Dropping trap {}: {reason}", alert.raw_name());
+                None
+            }
+            FilterAction::Tag => {
+                alert.add_label(self.tag_label.clone(), reason);
+                Some(alert)
+            }
+        }
+    }
+}
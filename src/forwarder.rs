@@ -0,0 +1,63 @@
+use crate::alerts::Alert;
+use crate::config::ForwardTarget;
+use crate::enrichment::is_full_match;
+use crate::snmp::{Message, PduType};
+use log::{info, warn};
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+pub struct TrapForwarder {
+    socket_v4: UdpSocket,
+    socket_v6: Option<UdpSocket>,
+    targets: Vec<ForwardTarget>,
+}
+
+impl TrapForwarder {
+    pub async fn new(targets: Vec<ForwardTarget>) -> anyhow::Result<Self> {
+        let socket_v4 = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0))).await?;
+        let socket_v6 = match targets.iter().any(|t| t.target.is_ipv6()) {
+            true => Some(UdpSocket::bind(SocketAddr::from(([0u16; 8], 0))).await?),
+            false => None,
+        };
+
+        info!("Forwarding SNMP traps to {} destinations", targets.len());
+
+        Ok(TrapForwarder {
+            socket_v4,
+            socket_v6,
+            targets,
+        })
+    }
+
+    // matched like enrichment rules are, against the name and labels of the received alert
+    pub async fn forward(&self, msg: &Message, alert: &Alert) {
+        let name = alert.raw_name();
+        for target in &self.targets {
+            if target
+                .name
+                .as_ref()
+                .is_some_and(|rgx| !is_full_match(rgx, name))
+                || !target.labels.iter().all(|m| m.matches(alert.raw_labels()))
+            {
+                continue;
+            }
+
+            let mut forwarded = msg.clone();
+            if forwarded.pdu.pdu_type == PduType::InformRequest {
+                forwarded.pdu.pdu_type = PduType::TrapV2;
+            }
+            if let Some(community) = &target.community {
+                forwarded.community = community.clone();
+            }
+
+            let socket = match (target.target.is_ipv6(), &self.socket_v6) {
+                (true, Some(socket_v6)) => socket_v6,
+                _ => &self.socket_v4,
+            };
+
+            if let Err(e) = socket.send_to(&forwarded.encode(), target.target).await {
+                warn!("Failed to forward trap {name} to {}: {e}", target.target);
+            }
+        }
+    }
+}
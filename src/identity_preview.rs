@@ -0,0 +1,153 @@
+use crate::alerts::Alert;
+use crate::bucketing::BucketRule;
+use crate::config::Settings;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use utoipa::ToSchema;
+
+const GROUPS_SHOWN: usize = 50;
+
+// proposed identity rules, whatever is left out stays as configured
+#[derive(Debug, Default, Clone, Deserialize, ToSchema)]
+pub struct IdentityRules {
+    identity_exclude_labels: Option<Vec<String>>,
+    #[schema(value_type = Option<Vec<Object>>)]
+    label_buckets: Option<Vec<BucketRule>>,
+}
+
+impl IdentityRules {
+    pub fn load(file: &Path) -> anyhow::Result<IdentityRules> {
+        Ok(serde_norway::from_str(&fs::read_to_string(file)?)?)
+    }
+
+    pub fn apply(&self, settings: &Settings) -> Settings {
+        settings.with_identity_rules(
+            self.identity_exclude_labels.clone(),
+            self.label_buckets.clone(),
+        )
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlertGroup {
+    pub name: String,
+    pub community: String,
+    pub labels: BTreeMap<String, String>,
+    // how many alerts end up in this one, or this one ends up in
+    pub alerts: usize,
+    pub occurrences: usize,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct MergePreview {
+    pub current_alerts: usize,
+    pub proposed_alerts: usize,
+    // proposed alerts that several current ones collapse into, the largest first
+    pub merges: Vec<AlertGroup>,
+    // current alerts that fall apart into several proposed ones
+    pub splits: Vec<AlertGroup>,
+}
+
+#[derive(Default)]
+struct Grouping<'a> {
+    sample: Option<&'a Alert>,
+    others: HashSet<u64>,
+    occurrences: usize,
+}
+
+fn largest_groups(groups: HashMap<u64, Grouping>) -> Vec<AlertGroup> {
+    let mut groups: Vec<AlertGroup> = groups
+        .into_values()
+        .filter(|g| g.others.len() > 1)
+        .filter_map(|g| {
+            let sample = g.sample?;
+            Some(AlertGroup {
+                name: sample.pretty_name(),
+                community: sample.community().to_string(),
+                labels: sample.raw_labels().clone(),
+                alerts: g.others.len(),
+                occurrences: g.occurrences,
+            })
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.alerts
+            .cmp(&a.alerts)
+            .then(b.occurrences.cmp(&a.occurrences))
+    });
+    groups.truncate(GROUPS_SHOWN);
+    groups
+}
+
+// maps every row twice, so each proposed alert knows which current alerts it is made of
+pub fn preview(
+    rows: &[PgRow],
+    map: impl Fn(&PgRow, &Settings) -> anyhow::Result<Alert>,
+    current: &Settings,
+    proposed: &Settings,
+) -> MergePreview {
+    let pairs: Vec<(Alert, Alert)> = rows
+        .iter()
+        .filter_map(|row| Some((map(row, current).ok()?, map(row, proposed).ok()?)))
+        .collect();
+    preview_pairs(&pairs)
+}
+
+fn preview_pairs(pairs: &[(Alert, Alert)]) -> MergePreview {
+    let mut merges: HashMap<u64, Grouping> = HashMap::new();
+    let mut splits: HashMap<u64, Grouping> = HashMap::new();
+    for (current, proposed) in pairs {
+        let merge = merges.entry(proposed.hash()).or_default();
+        merge.sample = Some(proposed);
+        merge.others.insert(current.hash());
+        merge.occurrences += current.count();
+
+        let split = splits.entry(current.hash()).or_default();
+        split.sample = Some(current);
+        split.others.insert(proposed.hash());
+        split.occurrences += current.count();
+    }
+
+    MergePreview {
+        current_alerts: splits.len(),
+        proposed_alerts: merges.len(),
+        merges: largest_groups(merges),
+        splits: largest_groups(splits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::Alert;
+    use crate::identity_preview::preview_pairs;
+    use std::collections::BTreeMap;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn counts_merges() {
+        let alert = |uptime: &str, exclude: &[String]| {
+            Alert::from_occurrence(
+                "coldStart".to_string(),
+                "public".to_string(),
+                OffsetDateTime::now_utc(),
+                BTreeMap::from([("sysUpTime".to_string(), uptime.to_string())]),
+            )
+            .exclude_from_identity(exclude)
+        };
+        let excluded = ["sysUpTime".to_string()];
+        let pairs: Vec<(Alert, Alert)> = ["1", "2", "3"]
+            .into_iter()
+            .map(|uptime| (alert(uptime, &[]), alert(uptime, &excluded)))
+            .collect();
+
+        let preview = preview_pairs(&pairs);
+        assert_eq!(preview.current_alerts, 3);
+        assert_eq!(preview.proposed_alerts, 1);
+        assert_eq!(preview.merges.len(), 1);
+        assert_eq!(preview.merges[0].alerts, 3);
+        assert!(preview.splits.is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::enrichment::LabelMatcher;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InhibitRule {
+    #[serde(default)]
+    source_matchers: Vec<LabelMatcher>,
+    #[serde(default)]
+    target_matchers: Vec<LabelMatcher>,
+    #[serde(default)]
+    equal: Vec<String>,
+}
+
+impl InhibitRule {
+    // same semantics as Alertmanager: labels listed in `equal` have to match, even when both are missing
+    fn inhibits(&self, source: &AlertmanagerAlert, target: &AlertmanagerAlert) -> bool {
+        self.source_matchers
+            .iter()
+            .all(|m| m.matches(source.labels()))
+            && self
+                .target_matchers
+                .iter()
+                .all(|m| m.matches(target.labels()))
+            && self
+                .equal
+                .iter()
+                .all(|label| source.labels().get(label) == target.labels().get(label))
+    }
+}
+
+// only alerts that would be relayed themselves can inhibit others, and never themselves
+pub fn inhibit(rules: &[InhibitRule], alerts: &mut [AlertmanagerAlert]) {
+    if rules.is_empty() {
+        return;
+    }
+
+    let inhibitors: Vec<Option<String>> = alerts
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            alerts
+                .iter()
+                .enumerate()
+                .filter(|(j, source)| *j != i && source.suppressed().is_none())
+                .find(|(_, source)| rules.iter().any(|rule| rule.inhibits(source, target)))
+                .map(|(_, source)| source.name().to_string())
+        })
+        .collect();
+
+    for (alert, inhibitor) in alerts.iter_mut().zip(inhibitors) {
+        if let Some(source) = inhibitor {
+            alert.suppress(format!("inhibited by {source}"));
+        }
+    }
+}
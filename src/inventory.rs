@@ -0,0 +1,36 @@
+use crate::alertmanager::AlertmanagerAlert;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+pub type DeviceLabels = BTreeMap<String, String>;
+
+// device facts synced from inventory systems, keyed by the management address traps come from.
+// Every system keeps its own snapshot, so one failing sync doesn't wipe the others
+#[derive(Debug, Default, Clone)]
+pub struct DeviceInventory(Arc<RwLock<BTreeMap<String, HashMap<IpAddr, DeviceLabels>>>>);
+
+impl DeviceInventory {
+    pub fn update(&self, system: &str, devices: HashMap<IpAddr, DeviceLabels>) {
+        self.0
+            .write()
+            .expect("inventory lock poisoned")
+            .insert(system.to_string(), devices);
+    }
+
+    // labels from the trap itself stay, they describe the event more precisely than the inventory
+    pub fn apply(&self, alert: &mut AlertmanagerAlert) {
+        let Some(source) = alert.source() else {
+            return;
+        };
+
+        let systems = self.0.read().expect("inventory lock poisoned");
+        for labels in systems.values().filter_map(|devices| devices.get(&source)) {
+            for (name, value) in labels {
+                if !alert.labels().contains_key(name) {
+                    alert.add_label(name, value);
+                }
+            }
+        }
+    }
+}
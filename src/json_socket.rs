@@ -0,0 +1,101 @@
+use crate::config::CONFIG;
+use crate::trap_db::TrapDb;
+use crate::webhook::IncomingAlerts;
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+// a sender that never ends its line would otherwise grow the buffer without bound
+const MAX_LINE_SIZE: usize = 1024 * 1024;
+
+pub fn forward_stdin(path: &Path) -> anyhow::Result<()> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .with_context(|| format!("connecting to {}", path.display()))?;
+    std::io::copy(&mut std::io::stdin().lock(), &mut stream)?;
+    Ok(())
+}
+
+pub struct JsonSocketListener {
+    listener: UnixListener,
+    db: Arc<TrapDb>,
+}
+
+impl JsonSocketListener {
+    pub fn bind(path: PathBuf, db: Arc<TrapDb>) -> anyhow::Result<Self> {
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        info!("Listening for JSON traps on {}", path.display());
+
+        Ok(JsonSocketListener { listener, db })
+    }
+
+    pub async fn run_listener_blocking(&self) {
+        loop {
+            match self.listener.accept().await {
+                // collectors keep their connection open, so each one gets its own task
+                Ok((stream, _)) => {
+                    let db = self.db.clone();
+                    tokio::spawn(async move {
+                        handle_connection(stream, &db).await;
+                    });
+                }
+                Err(e) => warn!("Failed to accept JSON socket connection: {e}"),
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream, db: &TrapDb) {
+    let mut reader = BufReader::new(stream);
+    let mut accepted = 0;
+    loop {
+        let mut line = Vec::new();
+        match (&mut reader)
+            .take(MAX_LINE_SIZE as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await
+        {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to read from JSON socket: {e}");
+                break;
+            }
+        }
+        if line.len() > MAX_LINE_SIZE && line.last() != Some(&b'\n') {
+            warn!("Dropping JSON socket connection, a line exceeded {MAX_LINE_SIZE} bytes");
+            break;
+        }
+        let Ok(line) = String::from_utf8(line) else {
+            warn!("Rejected JSON trap line: not valid UTF-8");
+            continue;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match ingest_line(&line, db).await {
+            Ok(count) => accepted += count,
+            Err(e) => warn!("Rejected JSON trap line: {e}"),
+        }
+    }
+    debug!("JSON socket connection closed after {accepted} accepted alerts");
+}
+
+async fn ingest_line(line: &str, db: &TrapDb) -> anyhow::Result<usize> {
+    let alerts = serde_json::from_str::<IncomingAlerts>(line)?.into_alerts()?;
+
+    let mut accepted = 0;
+    for alert in alerts {
+        if let Some(alert) = CONFIG.source_filter().apply(alert) {
+            db.ingest(alert).await;
+            accepted += 1;
+        }
+    }
+    Ok(accepted)
+}
@@ -0,0 +1,77 @@
+use crate::config::CONFIG;
+use log::{info, warn};
+use sqlx::{Connection, PgConnection};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const ELECTION_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct LeaderElection {
+    enabled: bool,
+    leader: AtomicBool,
+    // advisory locks belong to the session, so the connection holding it must stay open
+    lock_connection: Mutex<Option<PgConnection>>,
+}
+
+impl LeaderElection {
+    pub fn new(enabled: bool) -> Self {
+        LeaderElection {
+            enabled,
+            leader: AtomicBool::new(!enabled),
+            lock_connection: Mutex::default(),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+
+    pub async fn run_election_blocking(&self) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut interval = tokio::time::interval(ELECTION_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let leader = match self.campaign().await {
+                Ok(leader) => leader,
+                Err(e) => {
+                    warn!("Leader election failed: {e}");
+                    *self.lock_connection.lock().await = None;
+                    false
+                }
+            };
+
+            if leader != self.leader.swap(leader, Ordering::Relaxed) {
+                if leader {
+                    info!("Became leader, relaying alerts and serving clears");
+                } else {
+                    info!("Lost leadership, standing by");
+                }
+            }
+        }
+    }
+
+    async fn campaign(&self) -> anyhow::Result<bool> {
+        let mut connection = self.lock_connection.lock().await;
+
+        if let Some(conn) = connection.as_mut() {
+            conn.ping().await?;
+            return Ok(true);
+        }
+
+        let mut conn = PgConnection::connect(CONFIG.db_url()).await?;
+        let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(CONFIG.leader_lock_id())
+            .fetch_one(&mut conn)
+            .await?;
+        if acquired {
+            *connection = Some(conn);
+        }
+
+        Ok(acquired)
+    }
+}
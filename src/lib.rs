@@ -0,0 +1,61 @@
+pub mod access_log;
+pub mod alertmanager;
+pub mod alerts;
+pub mod api;
+pub mod archive;
+pub mod assets;
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod bucketing;
+pub mod business_hours;
+pub mod calendar;
+pub mod changes;
+pub mod chat;
+pub mod config;
+pub mod conventions;
+pub mod correlation;
+pub mod decode;
+pub mod enrichment;
+pub mod escalation;
+pub mod filter;
+pub mod forwarder;
+pub mod identity_preview;
+pub mod inhibition;
+pub mod inventory;
+pub mod json_socket;
+pub mod leader;
+pub mod librenms;
+pub mod listener;
+pub mod maintenance;
+pub mod metrics;
+pub mod netbox;
+pub mod notifier;
+pub mod oidc;
+pub mod oncall;
+pub mod opsgenie;
+pub mod pagerduty;
+pub mod pipeline;
+pub mod quiet_hours;
+pub mod rate_limit;
+pub mod reboot;
+pub mod redis_store;
+pub mod relay_queue;
+pub mod reload_diff;
+pub mod route_suggestions;
+pub mod row_errors;
+pub mod rule_pack;
+pub mod rules_git;
+pub mod sanitize;
+pub mod sites;
+pub mod snmp;
+pub mod snmptrapd_import;
+pub mod snooze;
+pub mod status;
+pub mod summary;
+pub mod systemd;
+pub mod trap_db;
+pub mod trap_search;
+pub mod traphandle;
+pub mod web;
+pub mod webhook;
@@ -0,0 +1,176 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::inventory::{DeviceInventory, DeviceLabels};
+use log::{debug, warn};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const INVENTORY_NAME: &str = "librenms";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownAction {
+    #[default]
+    Tag,
+    Suppress,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibreNmsSettings {
+    url: String,
+    token: String,
+    #[serde(
+        default = "interval_sec_default",
+        deserialize_with = "crate::config::interval_sec"
+    )]
+    interval_sec: u64,
+    #[serde(default)]
+    action: DownAction,
+    #[serde(default = "tag_label_default")]
+    tag_label: String,
+}
+
+fn interval_sec_default() -> u64 {
+    60
+}
+
+fn tag_label_default() -> String {
+    "nms_device_down".to_string()
+}
+
+impl LibreNmsSettings {
+    // the NMS already pages for a device that is down, the traps it still manages to send don't
+    pub fn suppress_if_down(&self, alert: &mut AlertmanagerAlert) {
+        if self.action != DownAction::Suppress {
+            return;
+        }
+        if let Some(reason) = alert.labels().get(&self.tag_label) {
+            let reason = format!("device is down in LibreNMS ({reason})");
+            alert.suppress(reason);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DevicesResponse {
+    #[serde(default)]
+    devices: Vec<Device>,
+}
+
+#[derive(Deserialize)]
+struct Device {
+    hostname: Option<String>,
+    ip: Option<String>,
+    overwrite_ip: Option<String>,
+    status_reason: Option<String>,
+}
+
+pub struct LibreNmsPoller {
+    settings: LibreNmsSettings,
+    client: Client,
+}
+
+impl LibreNmsPoller {
+    pub fn new(settings: LibreNmsSettings) -> Self {
+        Self {
+            settings,
+            client: Client::default(),
+        }
+    }
+
+    pub async fn run_poll_blocking(&self, inventory: DeviceInventory) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.interval_sec));
+        loop {
+            interval.tick().await;
+
+            match self.fetch_down_devices().await {
+                Ok(devices) => {
+                    debug!("LibreNMS reports {} down device addresses", devices.len());
+                    inventory.update(INVENTORY_NAME, devices);
+                }
+                Err(e) => warn!("Couldn't poll down devices from LibreNMS: {e:#}"),
+            }
+        }
+    }
+
+    async fn fetch_down_devices(&self) -> anyhow::Result<HashMap<IpAddr, DeviceLabels>> {
+        let response: DevicesResponse = self
+            .client
+            .get(format!(
+                "{}/api/v0/devices?type=down",
+                self.settings.url.trim_end_matches('/')
+            ))
+            .header("X-Auth-Token", &self.settings.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(down_devices(response, &self.settings.tag_label))
+    }
+}
+
+// devices are often added by address, so the hostname is worth a try as well
+fn down_devices(response: DevicesResponse, label: &str) -> HashMap<IpAddr, DeviceLabels> {
+    response
+        .devices
+        .into_iter()
+        .flat_map(|device| {
+            let reason = match device.status_reason.as_deref() {
+                Some("") | None => "down".to_string(),
+                Some(reason) => reason.to_string(),
+            };
+            let labels = DeviceLabels::from([(label.to_string(), reason)]);
+            [device.ip, device.overwrite_ip, device.hostname]
+                .into_iter()
+                .flatten()
+                .filter_map(|address| address.parse().ok())
+                .map(move |ip| (ip, labels.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::librenms::{DevicesResponse, LibreNmsSettings, down_devices};
+    use std::net::IpAddr;
+
+    #[test]
+    fn down_device_addresses() {
+        let response: DevicesResponse = serde_json::from_str(
+            r#"{
+                "status": "ok",
+                "count": 2,
+                "devices": [
+                    {"device_id": 1, "hostname": "sw1.example.net", "ip": "10.0.0.1", "status": 0, "status_reason": "icmp", "overwrite_ip": null},
+                    {"device_id": 2, "hostname": "10.0.0.2", "ip": "", "status": 0, "status_reason": "", "overwrite_ip": null}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let devices = down_devices(response, "nms_device_down");
+        let reason = |ip: &str| {
+            devices
+                .get(&ip.parse::<IpAddr>().unwrap())
+                .map(|labels| labels["nms_device_down"].as_str())
+        };
+        assert_eq!(devices.len(), 2);
+        assert_eq!(reason("10.0.0.1"), Some("icmp"));
+        assert_eq!(reason("10.0.0.2"), Some("down"));
+    }
+
+    #[test]
+    fn zero_interval_is_rejected() {
+        let settings = |interval: u64| {
+            serde_json::from_str::<LibreNmsSettings>(&format!(
+                r#"{{"url": "http://librenms", "token": "t", "interval_sec": {interval}}}"#
+            ))
+        };
+
+        assert!(settings(0).is_err());
+        assert!(settings(30).is_ok());
+    }
+}
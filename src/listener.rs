@@ -0,0 +1,146 @@
+use crate::alerts::Alert;
+use crate::config::CONFIG;
+use crate::forwarder::TrapForwarder;
+use crate::reboot::{RebootDetector, message_uptime};
+use crate::snmp::{Message, SNMP_TRAP_OID, SYS_UPTIME_OID};
+use crate::trap_db::TrapDb;
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+// every trap source ingests the same way, reboots are noticed from the uptime a trap carries
+pub struct TrapIngest {
+    db: Arc<TrapDb>,
+    reboots: Mutex<RebootDetector>,
+}
+
+impl TrapIngest {
+    pub fn new(db: Arc<TrapDb>) -> Self {
+        Self {
+            db,
+            reboots: Mutex::default(),
+        }
+    }
+
+    pub async fn ingest(&self, alert: Alert, source: Option<IpAddr>, uptime: Option<u32>) {
+        let community = alert.community().to_string();
+        self.db.ingest(alert).await;
+
+        let settings = self.db.settings();
+        if settings.reboot_detection()
+            && let (Some(source), Some(uptime)) = (source, uptime)
+            && let Some(reboot) = self.reboots.lock().await.observe(
+                settings,
+                source,
+                &community,
+                OffsetDateTime::now_utc(),
+                uptime,
+            )
+        {
+            self.db.ingest(reboot).await;
+        }
+    }
+}
+
+pub struct TrapListener {
+    socket: UdpSocket,
+    ingest: TrapIngest,
+    forwarder: Option<TrapForwarder>,
+}
+
+impl TrapListener {
+    pub async fn bind(
+        addr: SocketAddr,
+        db: Arc<TrapDb>,
+        forwarder: Option<TrapForwarder>,
+    ) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind(addr).await?;
+        info!("Listening for SNMP traps on {addr}");
+
+        Ok(TrapListener {
+            socket,
+            ingest: TrapIngest::new(db),
+            forwarder,
+        })
+    }
+
+    pub async fn run_listener_blocking(&self) {
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, source) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Failed to receive SNMP datagram: {e}");
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.handle_datagram(&buf[..len], source).await {
+                warn!("Dropping SNMP datagram from {source}: {e}");
+            }
+        }
+    }
+
+    async fn handle_datagram(&self, data: &[u8], source: SocketAddr) -> anyhow::Result<()> {
+        let msg = Message::decode(data)?;
+        if !msg.is_notification() {
+            debug!("Ignoring {:?} PDU from {source}", msg.pdu.pdu_type);
+            return Ok(());
+        }
+
+        // unacknowledged informs are retried, possibly towards a receiver that accepts them
+        let Some(alert) = CONFIG.source_filter().apply(
+            message_to_alert(&msg)?.with_agent_address(Some(source.ip()), CONFIG.instance_label()),
+        ) else {
+            return Ok(());
+        };
+
+        if let Some(response) = msg.inform_response() {
+            self.socket.send_to(&response.encode(), source).await?;
+        }
+
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.forward(&msg, &alert).await;
+        }
+        self.ingest
+            .ingest(alert, Some(source.ip()), message_uptime(&msg))
+            .await;
+
+        Ok(())
+    }
+}
+
+fn message_to_alert(msg: &Message) -> anyhow::Result<Alert> {
+    let name = msg
+        .trap_oid()
+        .ok_or_else(|| anyhow!("notification without snmpTrapOID.0"))?;
+
+    let mut labels = BTreeMap::new();
+    for varbind in &msg.pdu.varbinds {
+        let oid = varbind.oid.to_string();
+        if oid == SYS_UPTIME_OID || oid == SNMP_TRAP_OID {
+            continue;
+        }
+
+        let value = varbind.value.to_string();
+        if value.is_empty() {
+            continue;
+        }
+
+        labels.insert(CONFIG.mapped_label(&oid).to_string(), value);
+    }
+
+    Ok(Alert::from_occurrence(
+        name.to_string(),
+        msg.community.clone(),
+        OffsetDateTime::now_utc(),
+        labels,
+    ))
+}
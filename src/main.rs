@@ -1,26 +1,63 @@
-mod alertmanager;
-pub mod alerts;
-pub mod config;
-mod enrichment;
-pub mod sanitize;
-pub mod trap_db;
-pub mod web;
-
-use crate::alertmanager::AlertmanagerRelay;
-use crate::config::{CLI, CONFIG};
-use crate::enrichment::AlertEnrichment;
-use crate::trap_db::TrapDb;
-use crate::web::{alerts_view, clear_alert};
-use actix_web::web::Data;
+use actix_cors::Cors;
+use actix_web::http::header;
+use actix_web::middleware::{Compress, from_fn};
+use actix_web::web::{Data, scope};
 use actix_web::{App, HttpServer};
-use log::{error, info};
+use log::{error, info, warn};
+use snmp_trap_alertmanager::access_log::access_log;
+use snmp_trap_alertmanager::alertmanager::{AlertmanagerRelay, RelayStatus};
+use snmp_trap_alertmanager::api::{
+    alert_changes_api, alerts_api, dead_letters_api, identity_preview_api, ingest_alerts_api,
+    label_stages_api, openapi, reload_diff_api, row_errors_api, schema_api, status_api,
+    summary_api, trap_search_api, wallboard_api,
+};
+use snmp_trap_alertmanager::archive::S3Uploader;
+use snmp_trap_alertmanager::assets::{embedded_asset, static_asset};
+use snmp_trap_alertmanager::auth::{API_KEY_HEADER, CSRF_HEADER, mutation_guard};
+use snmp_trap_alertmanager::calendar::OnCallCalendars;
+use snmp_trap_alertmanager::changes::AlertChanges;
+use snmp_trap_alertmanager::chat::ChatNotifier;
+use snmp_trap_alertmanager::config::{CLI, CONFIG, Command};
+use snmp_trap_alertmanager::enrichment::AlertEnrichment;
+use snmp_trap_alertmanager::escalation::{EscalationNotifier, Escalations};
+use snmp_trap_alertmanager::forwarder::TrapForwarder;
+use snmp_trap_alertmanager::identity_preview::IdentityRules;
+use snmp_trap_alertmanager::inventory::DeviceInventory;
+use snmp_trap_alertmanager::json_socket::JsonSocketListener;
+use snmp_trap_alertmanager::leader::LeaderElection;
+use snmp_trap_alertmanager::librenms::LibreNmsPoller;
+use snmp_trap_alertmanager::listener::TrapListener;
+use snmp_trap_alertmanager::netbox::NetBoxSync;
+use snmp_trap_alertmanager::notifier::{
+    LifecycleNotifier, NotifierDispatcher, NotifierStats, prepared_alerts,
+};
+use snmp_trap_alertmanager::oidc::{OidcAuth, session_guard};
+use snmp_trap_alertmanager::oncall::OnCallNotifier;
+use snmp_trap_alertmanager::opsgenie::OpsgenieNotifier;
+use snmp_trap_alertmanager::pagerduty::PagerDutyNotifier;
+use snmp_trap_alertmanager::pipeline::start_pipeline;
+use snmp_trap_alertmanager::rate_limit::{RateLimiter, rate_limit};
+use snmp_trap_alertmanager::redis_store::RedisStore;
+use snmp_trap_alertmanager::reload_diff::EnrichmentReloader;
+use snmp_trap_alertmanager::route_suggestions::RouteSuggestions;
+use snmp_trap_alertmanager::rules_git::GitRuleSync;
+use snmp_trap_alertmanager::snmptrapd_import::SnmptrapdConf;
+use snmp_trap_alertmanager::trap_db::TrapDb;
+use snmp_trap_alertmanager::traphandle::TraphandleListener;
+use snmp_trap_alertmanager::web::{
+    alerts_view, clear_alert, label_stages_view, snooze_alert, traps_csv, traps_view,
+};
+use snmp_trap_alertmanager::{bench, json_socket, metrics, oidc, status, systemd, traphandle};
+use std::path::Path;
 use std::sync::Arc;
 use tera::Tera;
+use utoipa_swagger_ui::{self as swagger_ui, SwaggerUi};
 
 #[tokio::main]
 async fn main() {
     _ = dotenvy::dotenv();
     env_logger::init();
+    status::mark_started();
 
     if CLI.test_alerts {
         let mut enrichment = AlertEnrichment::new();
@@ -31,41 +68,495 @@ async fn main() {
         return;
     }
 
-    let db = TrapDb::new(CONFIG.db_url()).unwrap();
+    if CLI.traphandle {
+        let Some(path) = CONFIG.traphandle_socket() else {
+            error!("traphandle_socket has to be configured to pass on traps");
+            std::process::exit(1);
+        };
+        if let Err(e) = traphandle::forward_stdin(path).await {
+            error!("Error when passing on trap: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if CLI.json_stdin {
+        let Some(path) = CONFIG.json_socket() else {
+            error!("json_socket has to be configured to pass on traps");
+            std::process::exit(1);
+        };
+        if let Err(e) = json_socket::forward_stdin(path) {
+            error!("Error when passing on traps: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    match &CLI.command {
+        Some(Command::Bench(options)) => {
+            if let Err(e) = bench::run(options, &CONFIG) {
+                error!("Error when benchmarking the pipeline: {e:#}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::ImportSnmptrapd { path }) => {
+            match SnmptrapdConf::load(path) {
+                Ok(conf) => print!("{}", conf.starter_config(&path.display().to_string())),
+                Err(e) => {
+                    error!("Error when reading {path:?}: {e:#}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::SuggestRoutes) => {
+            if let Err(e) = suggest_routes().await {
+                error!("Error when suggesting Alertmanager routes: {e:#}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    if let Some(path) = &CLI.preview_identity {
+        if let Err(e) = preview_identity(path).await {
+            error!("Error when previewing identity rules: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut db = TrapDb::new(CONFIG.clone()).unwrap();
+    if let Some(url) = CONFIG.redis_url() {
+        match RedisStore::connect(url, CONFIG.redis_key_prefix()).await {
+            Ok(redis) => db = db.with_redis(redis),
+            Err(e) => {
+                error!("Error when connecting to Redis: {e}");
+                return;
+            }
+        }
+    }
+    if let Err(e) = db.check_schema().await {
+        warn!("Couldn't check the trap table schema: {e}");
+    }
 
     let mut tera = Tera::default();
     tera.add_raw_template("alerts_view", include_str!("../templates/alerts.html"))
         .expect("Failed to add built-in alert template");
+    tera.add_raw_template("labels_view", include_str!("../templates/labels.html"))
+        .expect("Failed to add built-in labels template");
+    tera.add_raw_template("traps_view", include_str!("../templates/traps.html"))
+        .expect("Failed to add built-in traps template");
 
     let shared_db = Arc::new(db);
     let shared_tera = Arc::new(tera);
+    let shared_relay_status = Arc::new(RelayStatus::default());
+    let shared_leader = Arc::new(LeaderElection::new(CONFIG.leader_election()));
+    start_leader_election_thread(shared_leader.clone());
+
+    let rules_sync = CONFIG
+        .rules_git()
+        .map(|settings| GitRuleSync::new(settings.clone()));
+    if let Some(sync) = &rules_sync
+        && let Err(e) = sync.initial_sync().await
+    {
+        error!("Error when fetching enrichment rules from Git: {e:#}");
+        return;
+    }
 
-    if let Err(e) = start_relay_thread(shared_db.clone()) {
-        error!("Error when configuring alertmanager relay: {e}");
+    let enrichment = match AlertEnrichment::load_configured(&CONFIG).await {
+        Ok(enrichment) => enrichment,
+        Err(e) => {
+            error!("Error loading alert enrichments: {e:#}");
+            return;
+        }
+    };
+    info!("Loaded {} alert enrichments", enrichment.count());
+    let shared_enrichment = Arc::new(enrichment);
+    let shared_reloader = Arc::new(EnrichmentReloader::new(
+        shared_enrichment.clone(),
+        shared_db.clone(),
+    ));
+    if let Some(sync) = rules_sync {
+        start_rules_sync_thread(sync, shared_reloader.clone());
+    }
+    start_inventory_threads(shared_enrichment.inventory());
+    if let Err(e) = systemd::start_reload_on_hangup(shared_reloader.clone()) {
+        error!("Error when installing the SIGHUP handler: {e}");
+        return;
+    }
+
+    let shared_calendars = Arc::new(OnCallCalendars::new(CONFIG.oncall_calendars()));
+    start_calendar_threads(&shared_calendars);
+
+    let shared_notifier_stats = Arc::new(NotifierStats::default());
+    let dispatcher = NotifierDispatcher::new(
+        shared_db.clone(),
+        shared_enrichment.clone(),
+        shared_leader.clone(),
+        shared_notifier_stats.clone(),
+        shared_calendars,
+    );
+    if let Err(e) = start_notifier_threads(&dispatcher, shared_relay_status.clone()) {
+        error!("Error when configuring notifiers: {e}");
+        return;
+    }
+    for pipeline in CONFIG.pipelines() {
+        if let Err(e) = start_pipeline(
+            pipeline,
+            shared_leader.clone(),
+            shared_notifier_stats.clone(),
+        )
+        .await
+        {
+            error!("Error when starting pipeline {:?}: {e:#}", pipeline.name);
+            return;
+        }
+    }
+    start_expiry_thread(shared_db.clone(), shared_leader.clone());
+    if let Err(e) = start_notify_listener_thread(shared_db.clone()).await {
+        error!("Error when configuring trap notifications: {e}");
+        return;
+    }
+    if let Err(e) = start_archive_upload_thread() {
+        error!("Error when configuring S3 archive upload: {e}");
+        return;
+    }
+    if let Err(e) = start_listener_thread(shared_db.clone()).await {
+        error!("Error when starting SNMP trap listener: {e}");
         return;
     }
-    run_web_frontend(shared_db.into(), shared_tera.into()).await;
+    if let Err(e) = start_traphandle_thread(shared_db.clone()) {
+        error!("Error when starting traphandle listener: {e}");
+        return;
+    }
+    if let Err(e) = start_json_socket_thread(shared_db.clone()) {
+        error!("Error when starting JSON socket listener: {e}");
+        return;
+    }
+    let shared_oidc = match CONFIG.oidc() {
+        None => None,
+        Some(settings) => match OidcAuth::discover(settings.clone()).await {
+            Ok(oidc) => Some(Data::new(oidc)),
+            Err(e) => {
+                error!("Error when configuring OIDC login: {e:#}");
+                return;
+            }
+        },
+    };
+    run_web_frontend(
+        shared_db.into(),
+        shared_tera.into(),
+        shared_relay_status.into(),
+        shared_reloader.into(),
+        shared_leader.into(),
+        shared_notifier_stats.into(),
+        shared_oidc,
+    )
+    .await;
 }
 
-async fn run_web_frontend(shared_db: Data<TrapDb>, shared_tera: Data<Tera>) {
-    HttpServer::new(move || {
+async fn run_web_frontend(
+    shared_db: Data<TrapDb>,
+    shared_tera: Data<Tera>,
+    shared_relay_status: Data<RelayStatus>,
+    shared_reloader: Data<EnrichmentReloader>,
+    shared_leader: Data<LeaderElection>,
+    shared_notifier_stats: Data<NotifierStats>,
+    shared_oidc: Option<Data<OidcAuth>>,
+) {
+    // shared by all workers, otherwise every worker would allow the full rate
+    let shared_rate_limiter = CONFIG
+        .rate_limit()
+        .cloned()
+        .map(|settings| Data::new(RateLimiter::new(settings)));
+    let shared_changes = Data::new(AlertChanges::default());
+    let shared_enrichment = Data::from(shared_reloader.enrichment().clone());
+    let mut server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(mutation_guard))
+            .wrap(from_fn(session_guard))
+            .wrap(from_fn(rate_limit))
+            .wrap(build_cors())
+            .wrap(Compress::default())
+            .wrap(from_fn(access_log))
             .app_data(shared_db.clone())
             .app_data(shared_tera.clone())
-            .service(alerts_view)
-            .service(clear_alert)
-    })
-    .bind(CONFIG.web_listen())
-    .unwrap()
-    .run()
-    .await
-    .unwrap();
-}
-
-fn start_relay_thread(db: Arc<TrapDb>) -> anyhow::Result<()> {
-    let mut relay = AlertmanagerRelay::new(CONFIG.alertmanager_url().to_string(), db)?;
+            .app_data(shared_relay_status.clone())
+            .app_data(shared_enrichment.clone())
+            .app_data(shared_reloader.clone())
+            .app_data(shared_leader.clone())
+            .app_data(shared_notifier_stats.clone())
+            .app_data(shared_changes.clone())
+            .configure(|cfg| {
+                if let Some(oidc) = &shared_oidc {
+                    cfg.app_data(oidc.clone());
+                }
+                if let Some(limiter) = &shared_rate_limiter {
+                    cfg.app_data(limiter.clone());
+                }
+            })
+            .service(
+                scope(CONFIG.web_path_prefix())
+                    .service(alerts_view)
+                    .service(clear_alert)
+                    .service(snooze_alert)
+                    .service(label_stages_view)
+                    .service(traps_view)
+                    .service(traps_csv)
+                    .service(static_asset)
+                    .service(embedded_asset)
+                    .service(label_stages_api)
+                    .service(metrics::metrics)
+                    .configure(|cfg| {
+                        if shared_oidc.is_some() {
+                            cfg.service(oidc::login)
+                                .service(oidc::callback)
+                                .service(oidc::logout);
+                        }
+                    })
+                    .service(summary_api)
+                    .service(status_api)
+                    .service(row_errors_api)
+                    .service(dead_letters_api)
+                    .service(wallboard_api)
+                    .service(alerts_api)
+                    .service(alert_changes_api)
+                    .service(trap_search_api)
+                    .service(schema_api)
+                    .service(identity_preview_api)
+                    .service(reload_diff_api)
+                    .service(ingest_alerts_api)
+                    .service(
+                        SwaggerUi::new("/api/docs/{_:.*}")
+                            .url("/api/openapi.json", openapi())
+                            .config(swagger_ui::Config::from(format!(
+                                "{}/api/openapi.json",
+                                CONFIG.web_path_prefix()
+                            ))),
+                    ),
+            )
+    });
+    let activated = systemd::listen_fds();
+    if activated.is_empty() {
+        for addr in CONFIG.web_listen() {
+            server = server.bind(addr).unwrap();
+        }
+    }
+    for listener in activated {
+        server = server.listen(listener).unwrap();
+    }
+
+    let server = server.run();
+    systemd::notify("READY=1");
+    systemd::start_watchdog();
+    server.await.unwrap();
+}
+
+async fn preview_identity(path: &Path) -> anyhow::Result<()> {
+    let rules = IdentityRules::load(path)?;
+    let preview = TrapDb::new(CONFIG.clone())?
+        .preview_identity(&rules)
+        .await?;
+    println!("{}", serde_json::to_string_pretty(&preview)?);
+    Ok(())
+}
+
+// suppressed alerts are left out, Alertmanager never gets to route them
+async fn suggest_routes() -> anyhow::Result<()> {
+    let db = TrapDb::new(CONFIG.clone())?;
+    let enrichment = AlertEnrichment::load_configured(&CONFIG).await?;
+    let escalations = Escalations::load(&[], None);
+    let alerts = prepared_alerts(&db, &enrichment, &escalations, &OnCallCalendars::default()).await;
+    print!(
+        "{}",
+        RouteSuggestions::from_alerts(&alerts, &CONFIG).to_yaml()
+    );
+    Ok(())
+}
+
+fn build_cors() -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(CONFIG.cors_allowed_methods().iter().map(String::as_str))
+        .allowed_headers([header::CONTENT_TYPE, header::ACCEPT, header::IF_NONE_MATCH])
+        .expose_headers([header::ETAG])
+        .allowed_header(API_KEY_HEADER)
+        .allowed_header(CSRF_HEADER)
+        .max_age(3600);
+
+    for origin in CONFIG.cors_allowed_origins() {
+        cors = match origin.as_str() {
+            "*" => cors.allow_any_origin(),
+            origin => cors.allowed_origin(origin),
+        };
+    }
+
+    cors
+}
+
+fn start_notifier_threads(
+    dispatcher: &NotifierDispatcher,
+    relay_status: Arc<RelayStatus>,
+) -> anyhow::Result<()> {
+    dispatcher.spawn(Box::new(AlertmanagerRelay::new(
+        CONFIG.clone(),
+        relay_status,
+    )));
+    if let Some(settings) = CONFIG.oncall() {
+        dispatcher.spawn(Box::new(LifecycleNotifier::new(OnCallNotifier::new(
+            settings.clone(),
+        ))));
+    }
+    if let Some(settings) = CONFIG.opsgenie() {
+        dispatcher.spawn(Box::new(LifecycleNotifier::new(OpsgenieNotifier::new(
+            settings.clone(),
+        ))));
+    }
+    if let Some(settings) = CONFIG.pagerduty() {
+        dispatcher.spawn(Box::new(LifecycleNotifier::new(PagerDutyNotifier::new(
+            settings.clone(),
+        ))));
+    }
+    for settings in CONFIG.chat() {
+        dispatcher.spawn(Box::new(LifecycleNotifier::new(ChatNotifier::new(
+            settings.clone(),
+        )?)));
+    }
+    for policy in CONFIG.escalations() {
+        if let Some(settings) = policy.chat() {
+            dispatcher.spawn(Box::new(EscalationNotifier::new(
+                policy.name(),
+                LifecycleNotifier::new(ChatNotifier::new(settings.clone())?),
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn start_expiry_thread(db: Arc<TrapDb>, leader: Arc<LeaderElection>) {
+    if CONFIG.alert_expiry().is_none() {
+        return;
+    }
+
+    tokio::spawn(async move { db.run_expiry_blocking(&leader).await });
+}
+
+async fn start_notify_listener_thread(db: Arc<TrapDb>) -> anyhow::Result<()> {
+    let Some(channel) = CONFIG.notify_channel() else {
+        return Ok(());
+    };
+    if channel.is_empty()
+        || !channel
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        anyhow::bail!("notify channel {channel:?} may only contain a-z, 0-9 and _");
+    }
+
+    if CONFIG.notify_install_trigger() {
+        db.install_notify_trigger(channel).await?;
+    }
+    tokio::spawn(async move {
+        db.run_notify_listener_blocking(channel).await;
+    });
+
+    Ok(())
+}
+
+fn start_leader_election_thread(leader: Arc<LeaderElection>) {
+    tokio::spawn(async move {
+        leader.run_election_blocking().await;
+    });
+}
+
+fn start_rules_sync_thread(sync: GitRuleSync, reloader: Arc<EnrichmentReloader>) {
+    tokio::spawn(async move {
+        sync.run_sync_blocking(reloader).await;
+    });
+}
+
+fn start_inventory_threads(inventory: &DeviceInventory) {
+    if let Some(settings) = CONFIG.netbox() {
+        let sync = NetBoxSync::new(settings.clone());
+        let inventory = inventory.clone();
+        tokio::spawn(async move {
+            sync.run_sync_blocking(inventory).await;
+        });
+    }
+    if let Some(settings) = CONFIG.librenms() {
+        let poller = LibreNmsPoller::new(settings.clone());
+        let inventory = inventory.clone();
+        tokio::spawn(async move {
+            poller.run_poll_blocking(inventory).await;
+        });
+    }
+}
+
+fn start_calendar_threads(calendars: &OnCallCalendars) {
+    for calendar in calendars.calendars() {
+        let calendar = calendar.clone();
+        tokio::spawn(async move {
+            calendar.run_refresh_blocking().await;
+        });
+    }
+}
+
+fn start_archive_upload_thread() -> anyhow::Result<()> {
+    let Some((archive, s3)) = CONFIG.archive().and_then(|a| Some((a, a.s3()?))) else {
+        return Ok(());
+    };
+
+    let uploader = S3Uploader::new(archive.clone(), s3.clone())?;
+    tokio::spawn(async move {
+        uploader.run_uploader_blocking().await;
+    });
+
+    Ok(())
+}
+
+async fn start_listener_thread(db: Arc<TrapDb>) -> anyhow::Result<()> {
+    if !CONFIG.trap_listen().is_empty() && CONFIG.redis_url().is_none() {
+        warn!("Received traps are only kept in memory without redis_url, a restart loses them");
+    }
+    for addr in CONFIG.trap_listen() {
+        let forwarder = match CONFIG.trap_forward() {
+            [] => None,
+            targets => Some(TrapForwarder::new(targets.to_vec()).await?),
+        };
+
+        let listener = TrapListener::bind(*addr, db.clone(), forwarder).await?;
+        tokio::spawn(async move {
+            listener.run_listener_blocking().await;
+        });
+    }
+
+    Ok(())
+}
+
+fn start_traphandle_thread(db: Arc<TrapDb>) -> anyhow::Result<()> {
+    let Some(path) = CONFIG.traphandle_socket() else {
+        return Ok(());
+    };
+
+    let listener = TraphandleListener::bind(path.to_path_buf(), db)?;
+    tokio::spawn(async move {
+        listener.run_listener_blocking().await;
+    });
+
+    Ok(())
+}
+
+fn start_json_socket_thread(db: Arc<TrapDb>) -> anyhow::Result<()> {
+    let Some(path) = CONFIG.json_socket() else {
+        return Ok(());
+    };
+
+    let listener = JsonSocketListener::bind(path.to_path_buf(), db)?;
     tokio::spawn(async move {
-        relay.run_relay_blocking().await;
+        listener.run_listener_blocking().await;
     });
 
     Ok(())
@@ -0,0 +1,62 @@
+use crate::enrichment::is_full_match;
+use chrono::{Local, TimeDelta};
+use croner::Cron;
+use croner::errors::CronError;
+use log::warn;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MaintenanceWindow {
+    name: String,
+    schedule: Cron,
+    duration_min: u32,
+    community: Option<String>,
+    #[serde(default, with = "serde_regex")]
+    alert_name: Option<regex::Regex>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+impl MaintenanceWindow {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_active(&self) -> bool {
+        match is_within_window(&self.schedule, self.duration_min) {
+            Ok(active) => active,
+            Err(e) => {
+                warn!("Failed to evaluate maintenance window {:?}: {e}", self.name);
+                false
+            }
+        }
+    }
+
+    pub fn matches(&self, name: &str, community: &str, labels: &BTreeMap<String, String>) -> bool {
+        self.community.as_ref().is_none_or(|c| c == community)
+            && self
+                .alert_name
+                .as_ref()
+                .is_none_or(|rgx| is_full_match(rgx, name))
+            && self.labels.iter().all(|(k, v)| labels.get(k) == Some(v))
+    }
+}
+
+// a window opens at every occurrence of the schedule and stays open for duration_min
+pub fn is_within_window(schedule: &Cron, duration_min: u32) -> Result<bool, CronError> {
+    let now = Local::now();
+    let start = schedule.find_previous_occurrence(&now, true)?;
+    Ok(now < start + TimeDelta::minutes(duration_min as i64))
+}
+
+pub fn active_window<'a>(
+    windows: &'a [MaintenanceWindow],
+    name: &str,
+    community: &str,
+    labels: &BTreeMap<String, String>,
+) -> Option<&'a MaintenanceWindow> {
+    windows
+        .iter()
+        .find(|w| w.matches(name, community, labels) && w.is_active())
+}
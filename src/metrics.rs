@@ -0,0 +1,137 @@
+use crate::alerts::Alert;
+use crate::config::Settings;
+use crate::notifier::{NotifierStats, SinkStats};
+use crate::trap_db::TrapDb;
+use actix_web::web::Data;
+use actix_web::{HttpResponse, get};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+fn metric_label_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if out.starts_with(|c: char| c.is_ascii_digit()) || out.starts_with("__") || out.is_empty() {
+        out.insert_str(0, "label_");
+    }
+    out
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn metric_labels(alert: &Alert, settings: &Settings) -> String {
+    let mut labels = vec![
+        ("alertname".to_string(), alert.pretty_name()),
+        ("community".to_string(), alert.community().to_string()),
+        ("severity".to_string(), alert.severity().to_string()),
+    ];
+    for (name, value) in alert.pretty_labels(settings) {
+        let name = metric_label_name(&name);
+        // differently punctuated names can collapse into one, keep the first
+        if !labels.iter().any(|(k, _)| *k == name) {
+            labels.push((name, value));
+        }
+    }
+
+    labels
+        .iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn render_metrics<'a>(
+    alerts: impl IntoIterator<Item = &'a Alert>,
+    settings: &Settings,
+) -> String {
+    let mut active = String::from(
+        "# HELP snmp_trap_alert_active Alert derived from SNMP traps is currently active.\n\
+         # TYPE snmp_trap_alert_active gauge\n",
+    );
+    let mut occurrences = String::from(
+        "# HELP snmp_trap_alert_occurrences Number of traps merged into the active alert.\n\
+         # TYPE snmp_trap_alert_occurrences gauge\n",
+    );
+    let mut last_seen = String::from(
+        "# HELP snmp_trap_alert_last_seen_timestamp_seconds Time the alert was last received.\n\
+         # TYPE snmp_trap_alert_last_seen_timestamp_seconds gauge\n",
+    );
+
+    for alert in alerts {
+        let labels = metric_labels(alert, settings);
+        _ = writeln!(active, "snmp_trap_alert_active{{{labels}}} 1");
+        _ = writeln!(
+            occurrences,
+            "snmp_trap_alert_occurrences{{{labels}}} {}",
+            alert.count()
+        );
+        _ = writeln!(
+            last_seen,
+            "snmp_trap_alert_last_seen_timestamp_seconds{{{labels}}} {}",
+            alert.latest().unix_timestamp()
+        );
+    }
+
+    active + &occurrences + &last_seen
+}
+
+pub fn render_notifier_metrics(sinks: &BTreeMap<String, SinkStats>) -> String {
+    let mut deliveries = String::from(
+        "# HELP snmp_trap_notifier_deliveries_total Announce cycles per notifier and outcome.\n\
+         # TYPE snmp_trap_notifier_deliveries_total counter\n",
+    );
+    let mut last_success = String::from(
+        "# HELP snmp_trap_notifier_last_success_timestamp_seconds Time of the last successful announce.\n\
+         # TYPE snmp_trap_notifier_last_success_timestamp_seconds gauge\n",
+    );
+
+    for (name, stats) in sinks {
+        let name = escape_label_value(name);
+        _ = writeln!(
+            deliveries,
+            "snmp_trap_notifier_deliveries_total{{notifier=\"{name}\",outcome=\"success\"}} {}",
+            stats.successes
+        );
+        _ = writeln!(
+            deliveries,
+            "snmp_trap_notifier_deliveries_total{{notifier=\"{name}\",outcome=\"failure\"}} {}",
+            stats.failures
+        );
+        if let Some(time) = stats.last_success {
+            _ = writeln!(
+                last_success,
+                "snmp_trap_notifier_last_success_timestamp_seconds{{notifier=\"{name}\"}} {}",
+                time.unix_timestamp()
+            );
+        }
+    }
+
+    deliveries + &last_success
+}
+
+#[get("/metrics")]
+async fn metrics(db: Data<TrapDb>, notifier_stats: Data<NotifierStats>) -> HttpResponse {
+    let body = render_metrics(db.cached_alerts().await.iter(), db.settings())
+        + &render_notifier_metrics(&*notifier_stats.sinks().await);
+    HttpResponse::Ok().content_type(CONTENT_TYPE).body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{escape_label_value, metric_label_name};
+
+    #[test]
+    fn label_names_and_values_are_sanitized() {
+        assert_eq!(metric_label_name("IF-MIB::ifIndex.3"), "IF_MIB__ifIndex_3");
+        assert_eq!(metric_label_name("1.3.6.1"), "label_1_3_6_1");
+        assert_eq!(escape_label_value("a \"b\"\n\\"), r#"a \"b\"\n\\"#);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::inventory::{DeviceInventory, DeviceLabels};
+use anyhow::Context;
+use log::{debug, warn};
+use reqwest::Client;
+use reqwest::header::{ACCEPT, AUTHORIZATION};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const INVENTORY_NAME: &str = "netbox";
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetBoxSettings {
+    url: String,
+    token: String,
+    #[serde(
+        default = "cache_ttl_sec_default",
+        deserialize_with = "crate::config::interval_sec"
+    )]
+    cache_ttl_sec: u64,
+}
+
+fn cache_ttl_sec_default() -> u64 {
+    600
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    next: Option<String>,
+    results: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Address {
+    address: String,
+}
+
+// NetBox 4 renamed `device_role` to `role`
+#[derive(Deserialize)]
+struct Device {
+    id: u64,
+    name: Option<String>,
+    #[serde(alias = "device_role")]
+    role: Option<Named>,
+    tenant: Option<Named>,
+    site: Option<Named>,
+    primary_ip4: Option<Address>,
+    primary_ip6: Option<Address>,
+}
+
+// NetBox 4 renamed `content_type` to `object_type`
+#[derive(Deserialize)]
+struct ContactAssignment {
+    #[serde(alias = "content_type")]
+    object_type: String,
+    object_id: u64,
+    contact: Named,
+}
+
+pub struct NetBoxSync {
+    settings: NetBoxSettings,
+    client: Client,
+}
+
+impl NetBoxSync {
+    pub fn new(settings: NetBoxSettings) -> Self {
+        Self {
+            settings,
+            client: Client::default(),
+        }
+    }
+
+    pub async fn run_sync_blocking(&self, inventory: DeviceInventory) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.cache_ttl_sec));
+        loop {
+            interval.tick().await;
+
+            match self.fetch_devices().await {
+                Ok(devices) => {
+                    debug!("Synced {} devices from NetBox", devices.len());
+                    inventory.update(INVENTORY_NAME, devices);
+                }
+                Err(e) => warn!("Couldn't sync devices from NetBox, keeping the last sync: {e:#}"),
+            }
+        }
+    }
+
+    async fn fetch_devices(&self) -> anyhow::Result<HashMap<IpAddr, DeviceLabels>> {
+        let devices: Vec<Device> = self
+            .fetch_all("dcim/devices/?has_primary_ip=true")
+            .await
+            .context("devices")?;
+        let contacts: Vec<ContactAssignment> = self
+            .fetch_all("tenancy/contact-assignments/?priority=primary")
+            .await
+            .context("contact assignments")?;
+
+        let contacts: HashMap<u64, String> = contacts
+            .into_iter()
+            .filter(|a| a.object_type == "dcim.device")
+            .map(|a| (a.object_id, a.contact.name))
+            .collect();
+
+        Ok(devices
+            .into_iter()
+            .flat_map(|device| {
+                let labels = device_labels(&device, contacts.get(&device.id));
+                [device.primary_ip4, device.primary_ip6]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|ip| parse_interface_address(&ip.address))
+                    .map(move |ip| (ip, labels.clone()))
+            })
+            .collect())
+    }
+
+    async fn fetch_all<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Vec<T>> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut next = Some(format!(
+            "{}/api/{path}{separator}limit={PAGE_SIZE}",
+            self.settings.url.trim_end_matches('/')
+        ));
+        let mut results = Vec::new();
+        while let Some(url) = next {
+            let page: Page<T> = self
+                .client
+                .get(&url)
+                .header(AUTHORIZATION, format!("Token {}", self.settings.token))
+                .header(ACCEPT, "application/json")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            results.extend(page.results);
+            next = page.next;
+        }
+        Ok(results)
+    }
+}
+
+fn device_labels(device: &Device, contact: Option<&String>) -> DeviceLabels {
+    [
+        ("device", device.name.as_ref()),
+        ("device_role", device.role.as_ref().map(|r| &r.name)),
+        ("tenant", device.tenant.as_ref().map(|t| &t.name)),
+        ("site", device.site.as_ref().map(|s| &s.name)),
+        ("contact", contact),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| Some((label.to_string(), value?.clone())))
+    .collect()
+}
+
+// addresses come with their prefix length, like 10.0.0.1/24
+fn parse_interface_address(address: &str) -> Option<IpAddr> {
+    address.split('/').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::netbox::{Device, device_labels, parse_interface_address};
+
+    #[test]
+    fn device_labels_from_api() {
+        let device: Device = serde_json::from_str(
+            r#"{
+                "id": 7,
+                "name": "sw1",
+                "device_role": {"id": 1, "name": "Access Switch", "slug": "access"},
+                "tenant": null,
+                "site": {"id": 2, "name": "FRA1", "slug": "fra1"},
+                "primary_ip4": {"id": 9, "address": "10.0.0.1/24"},
+                "primary_ip6": null
+            }"#,
+        )
+        .unwrap();
+
+        let labels = device_labels(&device, Some(&"Alice".to_string()));
+        assert_eq!(labels["device_role"], "Access Switch");
+        assert_eq!(labels["site"], "FRA1");
+        assert_eq!(labels["contact"], "Alice");
+        assert!(!labels.contains_key("tenant"));
+        assert_eq!(
+            parse_interface_address(&device.primary_ip4.unwrap().address),
+            "10.0.0.1".parse().ok()
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use crate::alertmanager::{AlertmanagerAlert, prepare_alert};
+use crate::calendar::OnCallCalendars;
+use crate::correlation::{correlate, count_device_alerts};
+use crate::enrichment::AlertEnrichment;
+use crate::escalation::Escalations;
+use crate::inhibition::inhibit;
+use crate::leader::LeaderElection;
+use crate::trap_db::TrapDb;
+use async_trait::async_trait;
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+const RETRY_BACKOFF: Duration = Duration::seconds(2);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Schedule {
+    #[serde(default = "interval_sec_default")]
+    interval_sec: u64,
+    #[serde(default)]
+    jitter_sec: u64,
+    #[serde(default)]
+    splay_sec: u64,
+    #[serde(default = "retries_default")]
+    retries: u32,
+}
+
+fn interval_sec_default() -> u64 {
+    60
+}
+
+fn retries_default() -> u32 {
+    2
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            interval_sec: interval_sec_default(),
+            jitter_sec: 0,
+            splay_sec: 0,
+            retries: retries_default(),
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new(interval: Duration, jitter: Duration, splay: Duration) -> Self {
+        Schedule {
+            interval_sec: interval.whole_seconds().max(0) as u64,
+            jitter_sec: jitter.whole_seconds().max(0) as u64,
+            splay_sec: splay.whole_seconds().max(0) as u64,
+            ..Default::default()
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::seconds(self.interval_sec as i64)
+    }
+
+    fn jitter(&self) -> Duration {
+        Duration::seconds(self.jitter_sec as i64)
+    }
+
+    fn splay(&self) -> Duration {
+        Duration::seconds(self.splay_sec as i64)
+    }
+}
+
+// a sink the dispatcher hands the prepared alerts to once per announce cycle
+#[async_trait]
+pub trait Notifier: Send {
+    fn name(&self) -> &str;
+
+    fn schedule(&self) -> Schedule;
+
+    async fn notify(&mut self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()>;
+}
+
+// sinks that open and close incidents instead of receiving the full alert set every cycle
+#[async_trait]
+pub trait IncidentSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn schedule(&self) -> Schedule;
+
+    async fn trigger(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()>;
+
+    async fn resolve(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()>;
+
+    // lets throttled sinks hold back messages, they are retried in a later cycle
+    fn admit(&mut self) -> bool {
+        true
+    }
+}
+
+pub struct LifecycleNotifier<S> {
+    sink: S,
+    lifecycle: AlertLifecycle,
+}
+
+impl<S: IncidentSink> LifecycleNotifier<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            lifecycle: AlertLifecycle::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: IncidentSink> Notifier for LifecycleNotifier<S> {
+    fn name(&self) -> &str {
+        self.sink.name()
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.sink.schedule()
+    }
+
+    async fn notify(&mut self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        let mut failed = 0;
+        for alert in self.lifecycle.new_alerts(alerts) {
+            if !self.sink.admit() {
+                break;
+            }
+            match self.sink.trigger(alert).await {
+                Ok(()) => self.lifecycle.mark_announced(alert),
+                Err(e) => {
+                    warn!(
+                        "{}: couldn't send alert {:?}: {e}",
+                        self.sink.name(),
+                        alert.name()
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        for alert in self.lifecycle.resolved_alerts(alerts) {
+            if !self.sink.admit() {
+                break;
+            }
+            match self.sink.resolve(&alert).await {
+                Ok(()) => self.lifecycle.mark_resolved(&alert),
+                Err(e) => {
+                    warn!(
+                        "{}: couldn't resolve alert {:?}: {e}",
+                        self.sink.name(),
+                        alert.name()
+                    );
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed > 0 {
+            anyhow::bail!("{failed} alerts could not be delivered");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SinkStats {
+    pub successes: u64,
+    pub failures: u64,
+    pub last_success: Option<OffsetDateTime>,
+}
+
+#[derive(Default)]
+pub struct NotifierStats {
+    sinks: RwLock<BTreeMap<String, SinkStats>>,
+}
+
+impl NotifierStats {
+    pub async fn sinks(&self) -> RwLockReadGuard<'_, BTreeMap<String, SinkStats>> {
+        self.sinks.read().await
+    }
+
+    async fn record(&self, name: &str, success: bool) {
+        let mut sinks = self.sinks.write().await;
+        let stats = sinks.entry(name.to_string()).or_default();
+        if success {
+            stats.successes += 1;
+            stats.last_success = Some(OffsetDateTime::now_utc());
+        } else {
+            stats.failures += 1;
+        }
+    }
+}
+
+// runs every configured sink on its own schedule, only the leader notifies
+#[derive(Clone)]
+pub struct NotifierDispatcher {
+    db: Arc<TrapDb>,
+    enrichment: Arc<AlertEnrichment>,
+    leader: Arc<LeaderElection>,
+    stats: Arc<NotifierStats>,
+    escalations: Arc<Escalations>,
+    calendars: Arc<OnCallCalendars>,
+}
+
+impl NotifierDispatcher {
+    pub fn new(
+        db: Arc<TrapDb>,
+        enrichment: Arc<AlertEnrichment>,
+        leader: Arc<LeaderElection>,
+        stats: Arc<NotifierStats>,
+        calendars: Arc<OnCallCalendars>,
+    ) -> Self {
+        let settings = db.settings();
+        let escalations =
+            Escalations::load(settings.escalations(), settings.escalation_state_file());
+        Self {
+            db,
+            enrichment,
+            leader,
+            stats,
+            escalations: Arc::new(escalations),
+            calendars,
+        }
+    }
+
+    pub fn spawn(&self, notifier: Box<dyn Notifier>) {
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            dispatcher.run_notifier_blocking(notifier).await;
+        });
+    }
+
+    async fn run_notifier_blocking(&self, mut notifier: Box<dyn Notifier>) {
+        let schedule = notifier.schedule();
+        let splay = random_delay(schedule.splay());
+        if !splay.is_zero() {
+            debug!("Delaying first {} announce by {splay}", notifier.name());
+            tokio::time::sleep(splay.unsigned_abs()).await;
+        }
+
+        let mut next_announce = Instant::now();
+        loop {
+            tokio::time::sleep_until(next_announce.into()).await;
+            next_announce = Instant::now() + schedule.interval() + random_delay(schedule.jitter());
+
+            if !self.leader.is_leader() {
+                debug!("Not the leader, skipping {} announce", notifier.name());
+                continue;
+            }
+
+            let alerts = prepared_alerts(
+                &self.db,
+                &self.enrichment,
+                &self.escalations,
+                &self.calendars,
+            )
+            .await;
+            for attempt in 0..=schedule.retries {
+                match notifier.notify(&alerts).await {
+                    Ok(()) => {
+                        debug!("SNMP Trap alerts successfully sent to {}", notifier.name());
+                        self.stats.record(notifier.name(), true).await;
+                        break;
+                    }
+                    Err(e) if attempt < schedule.retries => {
+                        debug!("Retrying {} after error: {e:?}", notifier.name());
+                        tokio::time::sleep((RETRY_BACKOFF * 2_i32.pow(attempt)).unsigned_abs())
+                            .await;
+                    }
+                    Err(e) => {
+                        warn!("Couldn't send alerts to {}: {e:?}", notifier.name());
+                        self.stats.record(notifier.name(), false).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// spreads announces of several instances so they don't hit the sink in lockstep
+fn random_delay(max: Duration) -> Duration {
+    if max <= Duration::ZERO {
+        return Duration::ZERO;
+    }
+    Duration::milliseconds(rand::random_range(0..=max.whole_milliseconds() as i64))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route<T> {
+    severity: Option<String>,
+    community: Option<String>,
+    #[serde(flatten)]
+    target: T,
+}
+
+impl<T> Route<T> {
+    fn matches(&self, alert: &AlertmanagerAlert) -> bool {
+        self.severity.as_ref().is_none_or(|s| s == alert.severity())
+            && self
+                .community
+                .as_ref()
+                .is_none_or(|c| c == alert.community())
+    }
+}
+
+// the first matching route wins, so a catch-all route belongs at the end
+pub fn find_route<'a, T>(routes: &'a [Route<T>], alert: &AlertmanagerAlert) -> Option<&'a T> {
+    routes
+        .iter()
+        .find(|route| route.matches(alert))
+        .map(|route| &route.target)
+}
+
+// the enriched and correlated alerts every sink gets to see
+pub async fn prepared_alerts(
+    db: &TrapDb,
+    enrichment: &AlertEnrichment,
+    escalations: &Escalations,
+    calendars: &OnCallCalendars,
+) -> Vec<AlertmanagerAlert> {
+    let settings = db.settings();
+    let snoozed = db.snoozed_alerts().await;
+    let cleared = db.cleared_alerts().await;
+    let mut alerts = db
+        .cached_alerts()
+        .await
+        .iter()
+        .filter(|alert| !snoozed.contains_key(&alert.hash()))
+        .map(|alert| {
+            let mut am_alert = AlertmanagerAlert::from_alert(alert, settings);
+            // the alert came back after being cleared, keep the operator's reasoning visible
+            if let Some(entry) = cleared.get(&alert.hash()) {
+                am_alert.add_annotation("cleared_reason", &entry.reason);
+                am_alert.add_annotation(
+                    "cleared_at",
+                    entry.time.format(&Rfc3339).unwrap_or_default(),
+                );
+            }
+            am_alert
+        })
+        .collect_vec();
+    drop(cleared);
+    enrichment.set_active_alerts(&alerts);
+
+    alerts.retain_mut(|alert| match prepare_alert(alert, enrichment, settings) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!(
+                "Failed to prepare alert {:?} for notification: {e}",
+                alert.name()
+            );
+            false
+        }
+    });
+    correlate(settings.correlation_rules(), &mut alerts);
+    inhibit(settings.inhibit_rules(), &mut alerts);
+    count_device_alerts(&mut alerts, settings.instance_label());
+    alerts.retain(|alert| match alert.suppressed() {
+        Some(reason) => {
+            debug!("Not notifying about alert {:?}: {reason}", alert.name());
+            false
+        }
+        None => true,
+    });
+    escalations.apply(&mut alerts);
+    calendars.annotate(&mut alerts);
+    alerts
+}
+
+pub fn alert_title(alert: &AlertmanagerAlert) -> String {
+    match alert.annotations().get("summary") {
+        Some(summary) => summary.clone(),
+        None => alert.name().to_string(),
+    }
+}
+
+pub fn alert_description(alert: &AlertmanagerAlert) -> String {
+    match alert.annotations().get("description") {
+        Some(description) => description.clone(),
+        None => alert
+            .labels()
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .join("\n"),
+    }
+}
+
+// remembers which alerts a sink was told about, so only changes are sent
+#[derive(Default)]
+pub struct AlertLifecycle {
+    announced: HashMap<u64, AlertmanagerAlert>,
+}
+
+impl AlertLifecycle {
+    pub fn new_alerts<'a>(&self, current: &'a [AlertmanagerAlert]) -> Vec<&'a AlertmanagerAlert> {
+        current
+            .iter()
+            .filter(|a| {
+                a.source_hash()
+                    .is_some_and(|h| !self.announced.contains_key(&h))
+            })
+            .collect()
+    }
+
+    pub fn resolved_alerts(&self, current: &[AlertmanagerAlert]) -> Vec<AlertmanagerAlert> {
+        self.announced
+            .iter()
+            .filter(|(hash, _)| !current.iter().any(|a| a.source_hash() == Some(**hash)))
+            .map(|(_, alert)| alert.clone())
+            .collect()
+    }
+
+    pub fn mark_announced(&mut self, alert: &AlertmanagerAlert) {
+        if let Some(hash) = alert.source_hash() {
+            self.announced.insert(hash, alert.clone());
+        }
+    }
+
+    pub fn mark_resolved(&mut self, alert: &AlertmanagerAlert) {
+        if let Some(hash) = alert.source_hash() {
+            self.announced.remove(&hash);
+        }
+    }
+}
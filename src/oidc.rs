@@ -0,0 +1,400 @@
+use crate::auth::{
+    API_KEY_HEADER, client_ip, cookie_path, generate_token, is_mutating, is_valid_api_key,
+};
+use crate::config::CONFIG;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorForbidden, ErrorUnauthorized};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, get};
+use anyhow::{Context, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use log::{info, warn};
+use openidconnect::core::{CoreClient, CoreProviderMetadata, CoreResponseType};
+use openidconnect::{
+    AuthenticationFlow, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet,
+    EndpointNotSet, EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, Scope, TokenResponse, reqwest,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::ext::NumericalDuration;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::RwLock;
+
+pub const SESSION_COOKIE: &str = "session";
+const PENDING_LOGIN_MINUTES: i64 = 10;
+
+type OidcClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcSettings {
+    issuer_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_url: Option<String>,
+    #[serde(default = "scopes_default")]
+    scopes: Vec<String>,
+    #[serde(default = "groups_claim_default")]
+    groups_claim: String,
+    #[serde(default)]
+    role_mappings: HashMap<String, Role>,
+    default_role: Option<Role>,
+    #[serde(default = "session_hours_default")]
+    session_hours: i64,
+}
+
+fn scopes_default() -> Vec<String> {
+    vec!["profile".to_string(), "email".to_string()]
+}
+
+fn groups_claim_default() -> String {
+    "groups".to_string()
+}
+
+fn session_hours_default() -> i64 {
+    8
+}
+
+impl OidcSettings {
+    fn redirect_url(&self) -> String {
+        self.redirect_url.clone().unwrap_or_else(|| {
+            format!(
+                "{}{}/auth/callback",
+                CONFIG.web_url().trim_end_matches('/'),
+                CONFIG.web_path_prefix()
+            )
+        })
+    }
+
+    fn role_for(&self, groups: &[String]) -> Option<Role> {
+        groups
+            .iter()
+            .filter_map(|g| self.role_mappings.get(g).copied())
+            .max()
+            .or(self.default_role)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub user: String,
+    pub role: Role,
+    #[serde(skip)]
+    expires: OffsetDateTime,
+}
+
+impl Session {
+    pub(crate) fn new(user: String, role: Role, lifetime: Duration) -> Self {
+        Self {
+            user,
+            role,
+            expires: OffsetDateTime::now_utc() + lifetime,
+        }
+    }
+}
+
+struct PendingLogin {
+    nonce: Nonce,
+    pkce_verifier: PkceCodeVerifier,
+    created: OffsetDateTime,
+}
+
+pub struct OidcAuth {
+    settings: OidcSettings,
+    client: OidcClient,
+    http: reqwest::Client,
+    pending: RwLock<HashMap<String, PendingLogin>>,
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl OidcAuth {
+    pub async fn discover(settings: OidcSettings) -> anyhow::Result<Self> {
+        // following redirects would open the token exchange up to SSRF
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        let issuer = IssuerUrl::new(settings.issuer_url.clone())?;
+        let metadata = CoreProviderMetadata::discover_async(issuer, &http)
+            .await
+            .context("OIDC discovery failed")?;
+        let client = CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(settings.client_id.clone()),
+            settings.client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(RedirectUrl::new(settings.redirect_url())?);
+
+        info!("OIDC login enabled with issuer {}", settings.issuer_url);
+
+        Ok(OidcAuth {
+            settings,
+            client,
+            http,
+            pending: RwLock::default(),
+            sessions: RwLock::default(),
+        })
+    }
+
+    async fn begin_login(&self) -> String {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let mut request = self
+            .client
+            .authorize_url(
+                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .set_pkce_challenge(pkce_challenge);
+        for scope in &self.settings.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (url, state, nonce) = request.url();
+
+        let now = OffsetDateTime::now_utc();
+        let mut pending = self.pending.write().await;
+        pending.retain(|_, p| now - p.created < PENDING_LOGIN_MINUTES.minutes());
+        pending.insert(
+            state.secret().clone(),
+            PendingLogin {
+                nonce,
+                pkce_verifier,
+                created: now,
+            },
+        );
+
+        url.to_string()
+    }
+
+    async fn complete_login(&self, code: String, state: &str) -> anyhow::Result<(String, Session)> {
+        let pending = self
+            .pending
+            .write()
+            .await
+            .remove(state)
+            .ok_or_else(|| anyhow!("unknown or expired login state"))?;
+
+        let token_response = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))?
+            .set_pkce_verifier(pending.pkce_verifier)
+            .request_async(&self.http)
+            .await
+            .context("token exchange failed")?;
+
+        let id_token = token_response
+            .id_token()
+            .ok_or_else(|| anyhow!("provider did not return an ID token"))?;
+        let claims = id_token.claims(&self.client.id_token_verifier(), &pending.nonce)?;
+
+        let user = claims
+            .preferred_username()
+            .map(|u| u.to_string())
+            .or_else(|| claims.email().map(|e| e.to_string()))
+            .unwrap_or_else(|| claims.subject().to_string());
+        let groups = token_groups(&id_token.to_string(), &self.settings.groups_claim)?;
+        let role = self
+            .settings
+            .role_for(&groups)
+            .ok_or_else(|| anyhow!("user {user:?} is not in any group mapped to a role"))?;
+
+        info!("OIDC login of {user:?} as {role:?}");
+
+        let session = Session::new(user, role, self.settings.session_hours.hours());
+        let id = generate_token();
+
+        let now = OffsetDateTime::now_utc();
+        let mut sessions = self.sessions.write().await;
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(id.clone(), session.clone());
+
+        Ok((id, session))
+    }
+
+    async fn session(&self, id: &str) -> Option<Session> {
+        self.sessions
+            .read()
+            .await
+            .get(id)
+            .filter(|s| s.expires > OffsetDateTime::now_utc())
+            .cloned()
+    }
+
+    async fn end_session(&self, id: &str) {
+        self.sessions.write().await.remove(id);
+    }
+}
+
+// the signature was verified by `IdToken::claims` already, so reading the payload directly is safe
+fn token_groups(jwt: &str, claim: &str) -> anyhow::Result<Vec<String>> {
+    let payload = jwt
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow!("malformed ID token"))?;
+    let mut claims: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload)?)?;
+
+    Ok(match claims.remove(claim) {
+        Some(serde_json::Value::Array(groups)) => groups
+            .into_iter()
+            .filter_map(|g| g.as_str().map(str::to_string))
+            .collect(),
+        Some(serde_json::Value::String(group)) => vec![group],
+        _ => Vec::new(),
+    })
+}
+
+fn login_path() -> String {
+    format!("{}/auth/login", CONFIG.web_path_prefix())
+}
+
+#[get("/auth/login")]
+async fn login(oidc: Data<OidcAuth>) -> HttpResponse {
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, oidc.begin_login().await))
+        .finish()
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: String,
+    state: String,
+}
+
+#[get("/auth/callback")]
+async fn callback(oidc: Data<OidcAuth>, Query(params): Query<CallbackParams>) -> HttpResponse {
+    let (id, _) = match oidc.complete_login(params.code, &params.state).await {
+        Ok(session) => session,
+        Err(e) => {
+            warn!("OIDC login failed: {e:#}");
+            return HttpResponse::Forbidden().body("Login failed");
+        }
+    };
+
+    let cookie = Cookie::build(SESSION_COOKIE, id)
+        .path(cookie_path())
+        .http_only(true)
+        // Lax so the cookie survives the redirect back from the identity provider
+        .same_site(SameSite::Lax)
+        .finish();
+
+    HttpResponse::Found()
+        .cookie(cookie)
+        .insert_header((header::LOCATION, format!("{}/", CONFIG.web_path_prefix())))
+        .finish()
+}
+
+#[get("/auth/logout")]
+async fn logout(oidc: Data<OidcAuth>, req: HttpRequest) -> HttpResponse {
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        oidc.end_session(cookie.value()).await;
+    }
+
+    let mut removal = Cookie::build(SESSION_COOKIE, "")
+        .path(cookie_path())
+        .finish();
+    removal.make_removal();
+
+    HttpResponse::Found()
+        .cookie(removal)
+        .insert_header((header::LOCATION, login_path()))
+        .finish()
+}
+
+pub async fn session_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(oidc) = req.app_data::<Data<OidcAuth>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let auth_prefix = format!("{}/auth/", CONFIG.web_path_prefix());
+    if req.path().starts_with(&auth_prefix) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    // wallboards are usually kiosk browsers that can't log in
+    let wallboard_path = format!("{}/api/wallboard", CONFIG.web_path_prefix());
+    if CONFIG.wallboard_public() && req.path() == wallboard_path && !is_mutating(req.method()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    if let Some(key) = req.headers().get(API_KEY_HEADER) {
+        if !is_valid_api_key(key.as_bytes()) {
+            warn!(
+                "Rejected {} {} from {:?}: invalid API key",
+                req.method(),
+                req.path(),
+                client_ip(req.peer_addr(), req.headers())
+            );
+            return Err(ErrorUnauthorized("Invalid API key"));
+        }
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let session = match req.cookie(SESSION_COOKIE) {
+        Some(cookie) => oidc.session(cookie.value()).await,
+        None => None,
+    };
+
+    let Some(session) = session else {
+        let api_prefix = format!("{}/api/", CONFIG.web_path_prefix());
+        if is_mutating(req.method()) || req.path().starts_with(&api_prefix) {
+            return Err(ErrorUnauthorized("Login required"));
+        }
+        let res = HttpResponse::Found()
+            .insert_header((header::LOCATION, login_path()))
+            .finish();
+        return Ok(req.into_response(res).map_into_right_body());
+    };
+
+    if is_mutating(req.method()) && session.role < Role::Operator {
+        warn!(
+            "Rejected {} {} by {:?}: insufficient role",
+            req.method(),
+            req.path(),
+            session.user
+        );
+        return Err(ErrorForbidden("Insufficient role"));
+    }
+
+    req.extensions_mut().insert(session);
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oidc::token_groups;
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    #[test]
+    fn groups_from_token_payload() {
+        let payload =
+            URL_SAFE_NO_PAD.encode(r#"{"sub":"1","groups":["noc","ops"],"role":"admin"}"#);
+        let jwt = format!("e30.{payload}.sig");
+
+        assert_eq!(token_groups(&jwt, "groups").unwrap(), ["noc", "ops"]);
+        assert_eq!(token_groups(&jwt, "role").unwrap(), ["admin"]);
+        assert!(token_groups(&jwt, "missing").unwrap().is_empty());
+        assert!(token_groups("garbage", "groups").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::config::CONFIG;
+use crate::notifier::{IncidentSink, Route, Schedule, alert_description, alert_title, find_route};
+use async_trait::async_trait;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OnCallSettings {
+    #[serde(flatten)]
+    schedule: Schedule,
+    routes: Vec<Route<OnCallTarget>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OnCallTarget {
+    url: String,
+}
+
+#[derive(Serialize)]
+struct FormattedWebhook<'a> {
+    alert_uid: String,
+    title: String,
+    message: String,
+    state: &'a str,
+    link_to_upstream_details: &'a str,
+}
+
+// speaks the "Formatted Webhook" integration of Grafana OnCall
+pub struct OnCallNotifier {
+    settings: OnCallSettings,
+    client: Client,
+}
+
+impl OnCallNotifier {
+    pub fn new(settings: OnCallSettings) -> Self {
+        Self {
+            settings,
+            client: Client::default(),
+        }
+    }
+
+    async fn send(&self, alert: &AlertmanagerAlert, state: &str) -> anyhow::Result<()> {
+        let Some(target) = find_route(&self.settings.routes, alert) else {
+            debug!("No Grafana OnCall route for alert {:?}", alert.name());
+            return Ok(());
+        };
+
+        let payload = FormattedWebhook {
+            alert_uid: format!("{:x}", alert.source_hash().unwrap_or_default()),
+            title: alert_title(alert),
+            message: alert_description(alert),
+            state,
+            link_to_upstream_details: CONFIG.web_url(),
+        };
+        self.client
+            .post(&target.url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IncidentSink for OnCallNotifier {
+    fn name(&self) -> &str {
+        "oncall"
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.settings.schedule
+    }
+
+    async fn trigger(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, "alerting").await
+    }
+
+    async fn resolve(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, "ok").await
+    }
+}
@@ -0,0 +1,139 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::notifier::{IncidentSink, Route, Schedule, alert_description, alert_title, find_route};
+use async_trait::async_trait;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+const SOURCE: &str = "snmp-trap-alertmanager";
+// Opsgenie rejects longer messages
+const MESSAGE_MAX_CHARS: usize = 130;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpsgenieSettings {
+    #[serde(default = "api_url_default")]
+    api_url: String,
+    #[serde(flatten)]
+    schedule: Schedule,
+    routes: Vec<Route<OpsgenieTarget>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpsgenieTarget {
+    api_key: String,
+    team: Option<String>,
+}
+
+fn api_url_default() -> String {
+    "https://api.opsgenie.com".to_string()
+}
+
+#[derive(Serialize)]
+struct CreateAlert<'a> {
+    message: String,
+    alias: String,
+    description: String,
+    priority: &'a str,
+    source: &'a str,
+    tags: Vec<&'a str>,
+    details: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    responders: Vec<serde_json::Value>,
+}
+
+pub struct OpsgenieNotifier {
+    settings: OpsgenieSettings,
+    client: Client,
+}
+
+impl OpsgenieNotifier {
+    pub fn new(settings: OpsgenieSettings) -> Self {
+        Self {
+            settings,
+            client: Client::default(),
+        }
+    }
+
+    async fn create(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        let Some(target) = find_route(&self.settings.routes, alert) else {
+            debug!("No Opsgenie route for alert {:?}", alert.name());
+            return Ok(());
+        };
+
+        let payload = CreateAlert {
+            message: alert_title(alert).chars().take(MESSAGE_MAX_CHARS).collect(),
+            alias: alias(alert),
+            description: alert_description(alert),
+            priority: priority(alert.severity()),
+            source: SOURCE,
+            tags: vec![alert.severity(), alert.community()],
+            details: alert.labels(),
+            responders: target
+                .team
+                .iter()
+                .map(|team| json!({"type": "team", "name": team}))
+                .collect(),
+        };
+        self.client
+            .post(format!("{}/v2/alerts", self.settings.api_url))
+            .header("Authorization", format!("GenieKey {}", target.api_key))
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn close(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        let Some(target) = find_route(&self.settings.routes, alert) else {
+            return Ok(());
+        };
+
+        self.client
+            .post(format!(
+                "{}/v2/alerts/{}/close",
+                self.settings.api_url,
+                alias(alert)
+            ))
+            .query(&[("identifierType", "alias")])
+            .header("Authorization", format!("GenieKey {}", target.api_key))
+            .json(&json!({"source": SOURCE, "note": "Trap alert resolved"}))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IncidentSink for OpsgenieNotifier {
+    fn name(&self) -> &str {
+        "opsgenie"
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.settings.schedule
+    }
+
+    async fn trigger(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.create(alert).await
+    }
+
+    async fn resolve(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.close(alert).await
+    }
+}
+
+fn alias(alert: &AlertmanagerAlert) -> String {
+    format!("{:x}", alert.source_hash().unwrap_or_default())
+}
+
+fn priority(severity: &str) -> &'static str {
+    match severity {
+        "critical" => "P1",
+        "warning" => "P3",
+        _ => "P5",
+    }
+}
@@ -0,0 +1,129 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::config::CONFIG;
+use crate::notifier::{IncidentSink, Route, Schedule, alert_title, find_route};
+use async_trait::async_trait;
+use log::debug;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const CLIENT: &str = "snmp-trap-alertmanager";
+// PagerDuty truncates summaries beyond this
+const SUMMARY_MAX_CHARS: usize = 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PagerDutySettings {
+    #[serde(default = "events_url_default")]
+    events_url: String,
+    #[serde(flatten)]
+    schedule: Schedule,
+    routes: Vec<Route<PagerDutyTarget>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PagerDutyTarget {
+    routing_key: String,
+}
+
+fn events_url_default() -> String {
+    "https://events.pagerduty.com/v2/enqueue".to_string()
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    routing_key: &'a str,
+    event_action: &'a str,
+    dedup_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<EventPayload<'a>>,
+    client: &'a str,
+    client_url: &'a str,
+}
+
+#[derive(Serialize)]
+struct EventPayload<'a> {
+    summary: String,
+    source: &'a str,
+    severity: &'a str,
+    group: &'a str,
+    class: &'a str,
+    custom_details: &'a BTreeMap<String, String>,
+}
+
+pub struct PagerDutyNotifier {
+    settings: PagerDutySettings,
+    client: Client,
+}
+
+impl PagerDutyNotifier {
+    pub fn new(settings: PagerDutySettings) -> Self {
+        Self {
+            settings,
+            client: Client::default(),
+        }
+    }
+
+    async fn send(&self, alert: &AlertmanagerAlert, action: &str) -> anyhow::Result<()> {
+        let Some(target) = find_route(&self.settings.routes, alert) else {
+            debug!("No PagerDuty route for alert {:?}", alert.name());
+            return Ok(());
+        };
+
+        // resolve events only need the dedup key
+        let payload = (action == "trigger").then(|| EventPayload {
+            summary: alert_title(alert).chars().take(SUMMARY_MAX_CHARS).collect(),
+            source: alert
+                .labels()
+                .get(CONFIG.instance_label().unwrap_or("instance"))
+                .map(String::as_str)
+                .unwrap_or(alert.community()),
+            severity: severity(alert.severity()),
+            group: alert.community(),
+            class: alert.name(),
+            custom_details: alert.labels(),
+        });
+        let event = Event {
+            routing_key: &target.routing_key,
+            event_action: action,
+            dedup_key: format!("{:x}", alert.source_hash().unwrap_or_default()),
+            payload,
+            client: CLIENT,
+            client_url: CONFIG.web_url(),
+        };
+
+        self.client
+            .post(&self.settings.events_url)
+            .json(&event)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IncidentSink for PagerDutyNotifier {
+    fn name(&self) -> &str {
+        "pagerduty"
+    }
+
+    fn schedule(&self) -> Schedule {
+        self.settings.schedule
+    }
+
+    async fn trigger(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, "trigger").await
+    }
+
+    async fn resolve(&self, alert: &AlertmanagerAlert) -> anyhow::Result<()> {
+        self.send(alert, "resolve").await
+    }
+}
+
+fn severity(severity: &str) -> &'static str {
+    match severity {
+        "critical" => "critical",
+        "warning" => "warning",
+        _ => "info",
+    }
+}
@@ -0,0 +1,63 @@
+use crate::alertmanager::{AlertmanagerRelay, RelayStatus};
+use crate::config::CONFIG;
+use crate::enrichment::AlertEnrichment;
+use crate::leader::LeaderElection;
+use crate::notifier::{NotifierDispatcher, NotifierStats};
+use crate::trap_db::TrapDb;
+use log::{info, warn};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineSettings {
+    pub name: String,
+    pub db_connection_url: String,
+    pub alert_dir: Option<PathBuf>,
+    pub alertmanager_url: String,
+    #[serde(default, deserialize_with = "crate::config::optional_announce_sec")]
+    pub alertmanager_announce_sec: Option<u32>,
+}
+
+// additional pipelines only relay to their own Alertmanager, the web frontend and the other
+// sinks stay with the main pipeline
+pub async fn start_pipeline(
+    pipeline: &PipelineSettings,
+    leader: Arc<LeaderElection>,
+    stats: Arc<NotifierStats>,
+) -> anyhow::Result<()> {
+    let settings = Arc::new(CONFIG.for_pipeline(pipeline));
+    let db = Arc::new(TrapDb::new(settings.clone())?);
+    if let Err(e) = db.check_schema().await {
+        warn!(
+            "Couldn't check the trap table schema of pipeline {:?}: {e}",
+            pipeline.name
+        );
+    }
+
+    let mut enrichment = AlertEnrichment::new();
+    if let Some(dir) = &pipeline.alert_dir {
+        enrichment.load_directory(dir)?;
+    }
+    info!(
+        "Starting pipeline {:?} with {} alert enrichments",
+        pipeline.name,
+        enrichment.count()
+    );
+
+    // expired alerts of the pipeline's database are cleared like those of the main one
+    if settings.alert_expiry().is_some() {
+        let db = db.clone();
+        let leader = leader.clone();
+        tokio::spawn(async move { db.run_expiry_blocking(&leader).await });
+    }
+
+    let dispatcher =
+        NotifierDispatcher::new(db, Arc::new(enrichment), leader, stats, Arc::default());
+    dispatcher.spawn(Box::new(
+        AlertmanagerRelay::new(settings, Arc::new(RelayStatus::default()))
+            .with_name(format!("alertmanager:{}", pipeline.name)),
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use crate::maintenance::is_within_window;
+use croner::Cron;
+use log::warn;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AfterQuietHours {
+    #[default]
+    CatchUp,
+    Discard,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietWindow {
+    schedule: Cron,
+    duration_min: u32,
+}
+
+impl QuietWindow {
+    fn is_active(&self) -> bool {
+        match is_within_window(&self.schedule, self.duration_min) {
+            Ok(active) => active,
+            Err(e) => {
+                warn!("Failed to evaluate quiet hours: {e}");
+                false
+            }
+        }
+    }
+}
+
+// info alerts are held back from Alertmanager while a window is active, the UI still shows them
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHours {
+    windows: Vec<QuietWindow>,
+    #[serde(default)]
+    after: AfterQuietHours,
+}
+
+impl QuietHours {
+    pub fn is_active(&self) -> bool {
+        self.windows.iter().any(QuietWindow::is_active)
+    }
+
+    pub fn after(&self) -> AfterQuietHours {
+        self.after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::test_settings;
+    use crate::quiet_hours::AfterQuietHours;
+
+    #[test]
+    fn always_quiet() {
+        let settings = test_settings(
+            r#"
+quiet_hours:
+  after: discard
+  windows:
+    - schedule: "* * * * *"
+      duration_min: 5
+"#,
+        );
+        let quiet_hours = settings.quiet_hours().unwrap();
+
+        assert!(quiet_hours.is_active());
+        assert_eq!(quiet_hours.after(), AfterQuietHours::Discard);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::auth::{API_KEY_HEADER, client_ip, is_mutating, is_valid_api_key};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse};
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitSettings {
+    per_minute: u32,
+    // how many requests may arrive at once, the per-minute rate when left out
+    burst: Option<u32>,
+}
+
+impl RateLimitSettings {
+    fn burst(&self) -> f64 {
+        self.burst.unwrap_or(self.per_minute).max(1) as f64
+    }
+
+    fn per_sec(&self) -> f64 {
+        self.per_minute.max(1) as f64 / 60.0
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// a token bucket per API key, or per client address for browser sessions
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // how long to wait when the client is out of requests
+    fn take(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let burst = self.settings.burst();
+        let per_sec = self.settings.per_sec();
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+
+        // buckets that refilled completely are the same as new ones
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * per_sec < burst
+            });
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * per_sec;
+        bucket.tokens = (bucket.tokens + refilled).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+// keys are only kept as a hash, the limiter shouldn't be another place holding them.
+// It runs before the key is checked, so made up keys would each get a fresh bucket
+fn client_key(req: &ServiceRequest) -> String {
+    if let Some(key) = req.headers().get(API_KEY_HEADER)
+        && is_valid_api_key(key.as_bytes())
+    {
+        let mut hasher = DefaultHasher::new();
+        key.as_bytes().hash(&mut hasher);
+        return format!("key:{:x}", hasher.finish());
+    }
+    match client_ip(req.peer_addr(), req.headers()) {
+        Some(ip) => format!("ip:{ip}"),
+        None => "ip:-".to_string(),
+    }
+}
+
+// reads are cheap and cached, only requests changing the database count
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(limiter) = req.app_data::<Data<RateLimiter>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if !is_mutating(req.method()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let client = client_key(&req);
+    if let Err(wait) = limiter.take(&client, Instant::now()) {
+        warn!("Rate limited {} {} from {client}", req.method(), req.path());
+        let res = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, wait.as_secs().max(1).to_string()))
+            .body("Too many requests");
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::{RateLimitSettings, RateLimiter};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new(RateLimitSettings {
+            per_minute: 6,
+            burst: Some(2),
+        });
+        let start = Instant::now();
+
+        assert!(limiter.take("ip:10.0.0.1", start).is_ok());
+        assert!(limiter.take("ip:10.0.0.1", start).is_ok());
+        let wait = limiter.take("ip:10.0.0.1", start).unwrap_err();
+        assert_eq!(wait.as_secs(), 10);
+        assert!(limiter.take("ip:10.0.0.2", start).is_ok());
+
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.take("ip:10.0.0.1", later).is_ok());
+        assert!(limiter.take("ip:10.0.0.1", later).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::alerts::{Alert, parse_source_address};
+use crate::audit::AuditEntry;
+use crate::config::Settings;
+use crate::snmp::{Message, SYS_UPTIME_OID, Value};
+use itertools::Itertools;
+use sqlx::postgres::PgRow;
+use sqlx::{Column, Row};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use time::ext::NumericalDuration;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+const UPTIME_COLUMN: &str = "sysUpTime.0";
+const DEFAULT_DEVICE_LABEL: &str = "instance";
+// a 32 bit TimeTicks counter wraps after ~497 days, values this close to the limit wrap instead of reset
+const WRAP_MARGIN: u32 = 100 * 60 * 60 * 24;
+
+#[derive(Default)]
+pub struct RebootDetector {
+    uptimes: HashMap<IpAddr, u32>,
+}
+
+impl RebootDetector {
+    pub fn observe(
+        &mut self,
+        settings: &Settings,
+        device: IpAddr,
+        community: &str,
+        time: OffsetDateTime,
+        uptime: u32,
+    ) -> Option<Alert> {
+        let previous = self.uptimes.insert(device, uptime)?;
+        if uptime >= previous || previous > u32::MAX - WRAP_MARGIN {
+            return None;
+        }
+
+        let labels = BTreeMap::from([
+            (
+                settings
+                    .instance_label()
+                    .unwrap_or(DEFAULT_DEVICE_LABEL)
+                    .to_string(),
+                device.to_string(),
+            ),
+            ("severity".to_string(), "warning".to_string()),
+        ]);
+        Some(
+            Alert::from_occurrence(
+                settings.reboot_alert_name().to_string(),
+                community.to_string(),
+                time,
+                labels,
+            )
+            .with_source(Some(device)),
+        )
+    }
+}
+
+// accepts net-snmp renderings like "Timeticks: (12345) 0:02:03.45", plain ticks, "0:02:03.45"
+// and the "0:0:02:03.45" days prefixed form snmptrapd passes to traphandles
+pub fn parse_uptime(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if let Some(start) = value.find('(') {
+        let end = start + value[start..].find(')')?;
+        return value[start + 1..end].trim().parse().ok();
+    }
+    if let Ok(ticks) = value.parse() {
+        return Some(ticks);
+    }
+
+    let (days, clock) = match value.split_once(',') {
+        Some((days, clock)) => (days.split_whitespace().next()?.parse::<u32>().ok()?, clock),
+        None => (0, value),
+    };
+    let (hms, centis) = clock.trim().split_once('.').unwrap_or((clock.trim(), "0"));
+    let parts = hms
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let (days, [h, m, s]) = match parts[..] {
+        [h, m, s] => (days, [h, m, s]),
+        [d, h, m, s] => (days + d, [h, m, s]),
+        _ => return None,
+    };
+
+    Some((((days * 24 + h) * 60 + m) * 60 + s) * 100 + centis.parse::<u32>().ok()?)
+}
+
+pub fn message_uptime(msg: &Message) -> Option<u32> {
+    if let Some(trap) = &msg.pdu.trap_v1 {
+        return Some(trap.time_stamp);
+    }
+
+    msg.pdu.varbinds.iter().find_map(|vb| match &vb.value {
+        Value::TimeTicks(ticks) if vb.oid.to_string() == SYS_UPTIME_OID => Some(*ticks),
+        _ => None,
+    })
+}
+
+// reboot alerts have no rows of their own, they can only be cleared as a whole
+pub fn is_reboot_alert(settings: &Settings, alert: &Alert) -> bool {
+    settings.reboot_detection() && alert.raw_name() == settings.reboot_alert_name()
+}
+
+// the resets are recomputed on every fetch, so a clear hides the ones it covered
+pub fn drop_cleared_reboots(
+    settings: &Settings,
+    alerts: HashSet<Alert>,
+    cleared: &HashMap<u64, AuditEntry>,
+) -> HashSet<Alert> {
+    alerts
+        .into_iter()
+        .filter_map(|alert| match cleared.get(&alert.hash()) {
+            Some(entry) if is_reboot_alert(settings, &alert) => {
+                alert.retain_times(|t| t > entry.time)
+            }
+            _ => Some(alert),
+        })
+        .collect()
+}
+
+// the trap table holds the whole history, so resets are recomputed from scratch on every fetch.
+// Only those within the look-back window become alerts
+pub fn reboot_alerts(traps: &[PgRow], settings: &Settings) -> Vec<Alert> {
+    let observations = traps
+        .iter()
+        .filter_map(|row| {
+            let mut time = None;
+            let mut community = None;
+            let mut source = None;
+            let mut uptime = None;
+
+            for col in row.columns() {
+                let value = || {
+                    row.try_get::<'_, Option<String>, _>(col.ordinal())
+                        .ok()
+                        .flatten()
+                };
+                match col.name() {
+                    "time" => time = row.try_get::<'_, PrimitiveDateTime, _>(col.ordinal()).ok(),
+                    "community" => community = value(),
+                    "source" | "host" if source.is_none() => {
+                        source = value().and_then(|s| parse_source_address(&s))
+                    }
+                    UPTIME_COLUMN => uptime = value().and_then(|v| parse_uptime(&v)),
+                    _ => {}
+                }
+            }
+
+            Some((time?.assume_utc(), community?, source?, uptime?))
+        })
+        .sorted_by_key(|(time, ..)| *time);
+
+    let since = OffsetDateTime::now_utc() - (settings.reboot_lookback_sec() as i64).seconds();
+    let mut detector = RebootDetector::default();
+    observations
+        .filter_map(|(time, community, source, uptime)| {
+            detector.observe(settings, source, &community, time, uptime)
+        })
+        .filter(|alert| alert.latest() >= since)
+        .filter_map(|alert| settings.source_filter().apply(alert))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::AuditEntry;
+    use crate::config::test_settings;
+    use crate::reboot::{RebootDetector, drop_cleared_reboots, parse_uptime};
+    use std::collections::{HashMap, HashSet};
+    use std::net::{IpAddr, Ipv4Addr};
+    use time::OffsetDateTime;
+    use time::ext::NumericalDuration;
+
+    #[test]
+    fn uptime_formats() {
+        assert_eq!(parse_uptime("Timeticks: (12345) 0:02:03.45"), Some(12345));
+        assert_eq!(parse_uptime("12345"), Some(12345));
+        assert_eq!(parse_uptime("0:02:03.45"), Some(12345));
+        assert_eq!(parse_uptime("1 day, 0:00:00.00"), Some(8_640_000));
+        assert_eq!(parse_uptime("1:0:00:00.00"), Some(8_640_000));
+        assert_eq!(parse_uptime("soon"), None);
+    }
+
+    #[test]
+    fn cleared_reboots_stay_cleared() {
+        let settings = test_settings("reboot_detection: true\n");
+        let device = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let now = OffsetDateTime::now_utc();
+        let mut detector = RebootDetector::default();
+        let mut observe =
+            |time, uptime| detector.observe(&settings, device, "public", time, uptime);
+
+        assert!(observe(now - 3.minutes(), 5000).is_none());
+        let reboot = observe(now - 2.minutes(), 100).unwrap();
+        let cleared = HashMap::from([(
+            reboot.hash(),
+            AuditEntry::new("clear", reboot.hash(), reboot.raw_name(), "", "test"),
+        )]);
+        assert!(drop_cleared_reboots(&settings, HashSet::from([reboot]), &cleared).is_empty());
+
+        // a reset after the clear is a new reboot
+        assert!(observe(now - 1.minutes(), 5000).is_none());
+        let again = observe(now + 1.minutes(), 100).unwrap();
+        let alerts = drop_cleared_reboots(&settings, HashSet::from([again]), &cleared);
+        assert_eq!(alerts.len(), 1);
+    }
+}
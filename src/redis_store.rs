@@ -0,0 +1,163 @@
+use crate::alerts::{Alert, merge_received_alert};
+use crate::audit::AuditEntry;
+use crate::snooze::Snooze;
+use anyhow::bail;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+// replicas ingest into the same field, it's only replaced if nobody changed it since it was read.
+// An empty expected value stands for a missing field
+const COMPARE_AND_SET: &str = r#"
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if (current or '') ~= ARGV[2] then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+return 1
+"#;
+const INGEST_ATTEMPTS: usize = 10;
+
+#[derive(Clone)]
+pub struct RedisStore {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str, prefix: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = client.get_connection_manager().await?;
+
+        Ok(RedisStore {
+            conn,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}:{name}", self.prefix)
+    }
+
+    pub async fn cached_alerts(&self) -> anyhow::Result<Option<HashSet<Alert>>> {
+        let json: Option<String> = self.conn.clone().get(self.key("cache")).await?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+
+    pub async fn store_cached_alerts(
+        &self,
+        alerts: &HashSet<Alert>,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let json = serde_json::to_string(alerts)?;
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(self.key("cache"), json, ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn invalidate_cache(&self) -> anyhow::Result<()> {
+        let _: () = self.conn.clone().del(self.key("cache")).await?;
+        Ok(())
+    }
+
+    pub async fn received_alerts(&self) -> anyhow::Result<HashSet<Alert>> {
+        let entries: HashMap<u64, String> = self.conn.clone().hgetall(self.key("received")).await?;
+        Ok(entries
+            .values()
+            .map(|j| serde_json::from_str(j))
+            .collect::<Result<_, _>>()?)
+    }
+
+    pub async fn ingest(&self, alert: Alert) -> anyhow::Result<()> {
+        let key = self.key("received");
+        let mut conn = self.conn.clone();
+        let script = redis::Script::new(COMPARE_AND_SET);
+
+        for _ in 0..INGEST_ATTEMPTS {
+            let existing: Option<String> = conn.hget(&key, alert.hash()).await?;
+            let mut merged = HashSet::new();
+            if let Some(existing) = &existing {
+                merged.insert(serde_json::from_str(existing)?);
+            }
+            merge_received_alert(&mut merged, alert.clone());
+            let Some(merged) = merged.take(&alert) else {
+                bail!("merged alert {} went missing", alert.hash());
+            };
+
+            let stored: bool = script
+                .key(&key)
+                .arg(alert.hash())
+                .arg(existing.unwrap_or_default())
+                .arg(serde_json::to_string(&merged)?)
+                .invoke_async(&mut conn)
+                .await?;
+            if stored {
+                return Ok(());
+            }
+        }
+        bail!("received alert kept changing, gave up after {INGEST_ATTEMPTS} attempts")
+    }
+
+    pub async fn remove_received(&self, alert: &Alert) -> anyhow::Result<()> {
+        let _: () = self
+            .conn
+            .clone()
+            .hdel(self.key("received"), alert.hash())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn cleared_alerts(&self) -> anyhow::Result<HashMap<u64, AuditEntry>> {
+        let entries: HashMap<u64, String> = self.conn.clone().hgetall(self.key("cleared")).await?;
+        entries
+            .into_iter()
+            .map(|(hash, j)| Ok((hash, serde_json::from_str(&j)?)))
+            .collect()
+    }
+
+    pub async fn store_cleared(
+        &self,
+        hash: u64,
+        entry: &AuditEntry,
+        expired: &[u64],
+    ) -> anyhow::Result<()> {
+        let key = self.key("cleared");
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset(&key, hash, serde_json::to_string(entry)?);
+        if !expired.is_empty() {
+            pipe.hdel(&key, expired);
+        }
+        let _: () = pipe.query_async(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+
+    pub async fn snoozed_alerts(&self) -> anyhow::Result<HashMap<u64, Snooze>> {
+        let entries: HashMap<u64, String> = self.conn.clone().hgetall(self.key("snoozed")).await?;
+        entries
+            .into_iter()
+            .map(|(hash, j)| Ok((hash, serde_json::from_str(&j)?)))
+            .collect()
+    }
+
+    pub async fn store_snoozed(
+        &self,
+        hash: u64,
+        snooze: &Snooze,
+        expired: &[u64],
+    ) -> anyhow::Result<()> {
+        let key = self.key("snoozed");
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset(&key, hash, serde_json::to_string(snooze)?);
+        if !expired.is_empty() {
+            pipe.hdel(&key, expired);
+        }
+        let _: () = pipe.query_async(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use crate::alertmanager::AlertmanagerAlert;
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelayQueueSettings {
+    path: PathBuf,
+    #[serde(default = "max_alerts_default")]
+    max_alerts: usize,
+}
+
+fn max_alerts_default() -> usize {
+    10000
+}
+
+// alerts from announce cycles Alertmanager never received, so their history survives an outage
+#[derive(Clone)]
+pub struct RelayQueue {
+    settings: RelayQueueSettings,
+}
+
+impl RelayQueue {
+    pub fn new(settings: RelayQueueSettings) -> Self {
+        Self { settings }
+    }
+
+    // the file IO blocks, so it's moved off the async runtime
+    pub async fn load(&self) -> anyhow::Result<Vec<AlertmanagerAlert>> {
+        let queue = self.clone();
+        tokio::task::spawn_blocking(move || queue.load_blocking()).await?
+    }
+
+    pub async fn enqueue(&self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        let queue = self.clone();
+        let alerts = alerts.to_vec();
+        tokio::task::spawn_blocking(move || queue.enqueue_blocking(&alerts)).await?
+    }
+
+    pub async fn clear(&self) -> anyhow::Result<()> {
+        let queue = self.clone();
+        tokio::task::spawn_blocking(move || queue.clear_blocking()).await?
+    }
+
+    // a queue that can't be read anymore is moved aside, otherwise it would block relaying for good
+    fn load_blocking(&self) -> anyhow::Result<Vec<AlertmanagerAlert>> {
+        let content = match fs::read(&self.settings.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_slice(&content) {
+            Ok(alerts) => Ok(alerts),
+            Err(e) => {
+                let corrupt = self.settings.path.with_extension("corrupt");
+                warn!(
+                    "Discarding unreadable relay queue {:?}, moving it to {corrupt:?}: {e}",
+                    self.settings.path
+                );
+                fs::rename(&self.settings.path, corrupt)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    // the newest version of an alert replaces older queued ones, the oldest alerts go first when full
+    fn enqueue_blocking(&self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        let mut queued: HashMap<BTreeMap<String, String>, AlertmanagerAlert> = self
+            .load_blocking()?
+            .into_iter()
+            .map(|a| (a.labels().clone(), a))
+            .collect();
+        for alert in alerts {
+            queued.insert(alert.labels().clone(), alert.clone());
+        }
+
+        let queued = queued
+            .into_values()
+            .sorted_by(|a, b| b.ends_at().cmp(a.ends_at()))
+            .take(self.settings.max_alerts)
+            .collect_vec();
+        debug!("{} alerts queued for Alertmanager", queued.len());
+        self.store(&queued)
+    }
+
+    fn clear_blocking(&self) -> anyhow::Result<()> {
+        match fs::remove_file(&self.settings.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn store(&self, alerts: &[AlertmanagerAlert]) -> anyhow::Result<()> {
+        let tmp = self.settings.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(alerts)?)?;
+        fs::rename(tmp, &self.settings.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_queue_is_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("relay-queue-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("queue.json");
+        fs::write(&path, b"{not json").unwrap();
+
+        let queue = RelayQueue::new(RelayQueueSettings {
+            path: path.clone(),
+            max_alerts: max_alerts_default(),
+        });
+        assert!(queue.load_blocking().unwrap().is_empty());
+        assert!(!path.exists());
+        assert!(path.with_extension("corrupt").exists());
+
+        queue.enqueue_blocking(&[]).unwrap();
+        assert!(queue.load_blocking().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
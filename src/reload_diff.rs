@@ -0,0 +1,235 @@
+use crate::alertmanager::{AlertmanagerAlert, prepare_alert};
+use crate::config::Settings;
+use crate::enrichment::AlertEnrichment;
+use crate::trap_db::TrapDb;
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use utoipa::ToSchema;
+
+const ALERTS_SHOWN: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ValueChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlertDiff {
+    pub hash: u64,
+    pub name: String,
+    pub labels: Vec<ValueChange>,
+    pub annotations: Vec<ValueChange>,
+    // whether the alert is suppressed or fails to enrich at all
+    pub status: Vec<ValueChange>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReloadDiff {
+    pub time: String,
+    pub source: String,
+    pub definitions_before: usize,
+    pub definitions_after: usize,
+    pub alerts: usize,
+    pub changed: usize,
+    pub diffs: Vec<AlertDiff>,
+}
+
+// what the sinks would get to see for one alert
+#[derive(Debug, PartialEq)]
+struct Enriched {
+    name: String,
+    labels: BTreeMap<String, String>,
+    annotations: BTreeMap<String, String>,
+    status: BTreeMap<String, String>,
+}
+
+impl Enriched {
+    fn diff(&self, hash: u64, after: &Enriched) -> Option<AlertDiff> {
+        if self == after {
+            return None;
+        }
+        Some(AlertDiff {
+            hash,
+            name: after.name.clone(),
+            labels: changes(&self.labels, &after.labels),
+            annotations: changes(&self.annotations, &after.annotations),
+            status: changes(&self.status, &after.status),
+        })
+    }
+}
+
+fn changes(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> Vec<ValueChange> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| ValueChange {
+            name: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+        })
+        .collect()
+}
+
+// swaps in reloaded rules, recording what they do to the current alerts when configured to
+pub struct EnrichmentReloader {
+    enrichment: Arc<AlertEnrichment>,
+    db: Arc<TrapDb>,
+    // the rule sources can be changed by a SIGHUP, later Git reloads have to use them as well
+    settings: RwLock<Arc<Settings>>,
+    last_diff: RwLock<Option<Arc<ReloadDiff>>>,
+}
+
+impl EnrichmentReloader {
+    pub fn new(enrichment: Arc<AlertEnrichment>, db: Arc<TrapDb>) -> Self {
+        Self {
+            enrichment,
+            settings: RwLock::new(db.settings().clone()),
+            db,
+            last_diff: RwLock::default(),
+        }
+    }
+
+    pub fn enrichment(&self) -> &Arc<AlertEnrichment> {
+        &self.enrichment
+    }
+
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn replace_settings(&self, settings: Settings) {
+        *self.settings.write().unwrap() = Arc::new(settings);
+    }
+
+    pub fn last_diff(&self) -> Option<Arc<ReloadDiff>> {
+        self.last_diff.read().unwrap().clone()
+    }
+
+    pub async fn replace(&self, reloaded: AlertEnrichment, source: &str) {
+        if !self.db.settings().record_reload_diffs() {
+            self.enrichment.replace(reloaded);
+            return;
+        }
+
+        let definitions_before = self.enrichment.count();
+        let before = self.snapshot().await;
+        self.enrichment.replace(reloaded);
+        let after = self.snapshot().await;
+
+        let mut diffs: Vec<AlertDiff> = after
+            .iter()
+            .filter_map(|(hash, enriched)| before.get(hash)?.diff(*hash, enriched))
+            .collect();
+        diffs.sort_by(|a, b| a.name.cmp(&b.name).then(a.hash.cmp(&b.hash)));
+
+        info!(
+            "Enrichment reload from {source} changed {} of {} alerts",
+            diffs.len(),
+            after.len()
+        );
+        for diff in &diffs {
+            for change in diff
+                .labels
+                .iter()
+                .chain(&diff.annotations)
+                .chain(&diff.status)
+            {
+                debug!(
+                    "Reload changed {} ({}): {} {:?} -> {:?}",
+                    diff.name, diff.hash, change.name, change.before, change.after
+                );
+            }
+        }
+
+        let changed = diffs.len();
+        diffs.truncate(ALERTS_SHOWN);
+        let diff = ReloadDiff {
+            time: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            source: source.to_string(),
+            definitions_before,
+            definitions_after: self.enrichment.count(),
+            alerts: after.len(),
+            changed,
+            diffs,
+        };
+        *self.last_diff.write().unwrap() = Some(Arc::new(diff));
+    }
+
+    async fn snapshot(&self) -> HashMap<u64, Enriched> {
+        let settings = self.db.settings();
+        let alerts: Vec<(u64, AlertmanagerAlert)> = self
+            .db
+            .cached_alerts()
+            .await
+            .iter()
+            .map(|alert| (alert.hash(), AlertmanagerAlert::from_alert(alert, settings)))
+            .collect();
+        let active: Vec<AlertmanagerAlert> = alerts.iter().map(|(_, a)| a.clone()).collect();
+        self.enrichment.set_active_alerts(&active);
+
+        alerts
+            .into_iter()
+            .map(|(hash, mut alert)| {
+                let mut status = BTreeMap::new();
+                if let Err(e) = prepare_alert(&mut alert, &self.enrichment, settings) {
+                    status.insert("error".to_string(), e.to_string());
+                }
+                if let Some(reason) = alert.suppressed() {
+                    status.insert("suppressed".to_string(), reason.to_string());
+                }
+                let enriched = Enriched {
+                    name: alert.name().to_string(),
+                    labels: alert.labels().clone(),
+                    annotations: alert.annotations().clone(),
+                    status,
+                };
+                (hash, enriched)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reload_diff::{ValueChange, changes};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn value_changes() {
+        let before = BTreeMap::from([
+            ("site".to_string(), "fra1".to_string()),
+            ("team".to_string(), "net".to_string()),
+        ]);
+        let after = BTreeMap::from([
+            ("site".to_string(), "fra2".to_string()),
+            ("owner".to_string(), "noc".to_string()),
+        ]);
+
+        let change = |name: &str, before: Option<&str>, after: Option<&str>| ValueChange {
+            name: name.to_string(),
+            before: before.map(str::to_string),
+            after: after.map(str::to_string),
+        };
+        assert_eq!(
+            changes(&before, &after),
+            vec![
+                change("owner", None, Some("noc")),
+                change("site", Some("fra1"), Some("fra2")),
+                change("team", Some("net"), None),
+            ]
+        );
+        assert!(changes(&before, &before).is_empty());
+    }
+}
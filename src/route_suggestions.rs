@@ -0,0 +1,246 @@
+use crate::alertmanager::AlertmanagerAlert;
+use crate::alerts::Severity;
+use crate::config::Settings;
+use crate::sanitize::yaml_quoted;
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::str::FromStr;
+
+const NAMES_SHOWN: usize = 5;
+
+// Alertmanager routes matching what is currently being relayed, one per community with a
+// child route per severity
+pub struct RouteSuggestions {
+    community_label: String,
+    group_by: Vec<String>,
+    communities: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+    // by community and severity, None for the community's own receiver
+    receivers: BTreeMap<(String, Option<String>), String>,
+}
+
+impl RouteSuggestions {
+    pub fn from_alerts(alerts: &[AlertmanagerAlert], settings: &Settings) -> Self {
+        let community_label = settings.alertmanager_community_label().to_string();
+        let mut group_by = vec!["alertname".to_string(), community_label.clone()];
+        // grouping by a label some alerts lack would put those into one group of their own
+        if let Some(instance) = settings.instance_label()
+            && !alerts.is_empty()
+            && alerts.iter().all(|a| a.labels().contains_key(instance))
+        {
+            group_by.push(instance.to_string());
+        }
+
+        let mut communities: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+        for alert in alerts {
+            communities
+                .entry(alert.community().to_string())
+                .or_default()
+                .entry(alert.severity().to_string())
+                .or_default()
+                .insert(alert.name().to_string());
+        }
+
+        // different values can clean up to the same name, like "core net" and "core-net"
+        let mut taken = HashSet::from(["snmp-traps".to_string()]);
+        let mut receivers = BTreeMap::new();
+        for (community, severities) in &communities {
+            let parts = [community.as_str()];
+            receivers.insert(
+                (community.clone(), None),
+                unique_name(receiver_name(&parts), &mut taken),
+            );
+            for (severity, _) in sorted_by_severity(severities) {
+                let parts = [community.as_str(), severity.as_str()];
+                receivers.insert(
+                    (community.clone(), Some(severity.clone())),
+                    unique_name(receiver_name(&parts), &mut taken),
+                );
+            }
+        }
+
+        RouteSuggestions {
+            community_label,
+            group_by,
+            communities,
+            receivers,
+        }
+    }
+
+    fn receiver(&self, community: &str, severity: Option<&str>) -> &str {
+        &self.receivers[&(community.to_string(), severity.map(str::to_string))]
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+        let mut line = |text: String| {
+            out.push_str(&text);
+            out.push('\n');
+        };
+
+        line("# Alertmanager routing suggested from the currently relayed alerts".to_string());
+        line("route:".to_string());
+        line("  receiver: snmp-traps".to_string());
+        line(format!(
+            "  group_by: [{}]",
+            self.group_by.iter().map(|l| yaml_quoted(l)).join(", ")
+        ));
+        if !self.communities.is_empty() {
+            line("  routes:".to_string());
+        }
+        for (community, severities) in &self.communities {
+            let receiver = self.receiver(community, None);
+            line(format!(
+                "    - matchers: [{}]",
+                yaml_quoted(&matcher(&self.community_label, community))
+            ));
+            line(format!("      receiver: {receiver}"));
+            line("      routes:".to_string());
+            for (severity, names) in sorted_by_severity(severities) {
+                line(format!("        # {}", name_summary(names)));
+                line(format!(
+                    "        - matchers: [{}]",
+                    yaml_quoted(&matcher("severity", severity))
+                ));
+                line(format!(
+                    "          receiver: {}",
+                    self.receiver(community, Some(severity))
+                ));
+            }
+        }
+
+        line(String::new());
+        line("# every receiver still needs its integrations, like webhook_configs".to_string());
+        line("receivers:".to_string());
+        line("  - name: snmp-traps".to_string());
+        for (community, severities) in &self.communities {
+            line(format!("  - name: {}", self.receiver(community, None)));
+            for (severity, _) in sorted_by_severity(severities) {
+                line(format!(
+                    "  - name: {}",
+                    self.receiver(community, Some(severity))
+                ));
+            }
+        }
+        out
+    }
+}
+
+// most severe first, whatever isn't a known severity last
+fn sorted_by_severity(
+    severities: &BTreeMap<String, BTreeSet<String>>,
+) -> Vec<(&String, &BTreeSet<String>)> {
+    severities
+        .iter()
+        .sorted_by_key(|(severity, _)| {
+            Severity::from_str(severity)
+                .ok()
+                .and_then(|s| Severity::ALL.iter().position(|known| *known == s))
+                .unwrap_or(Severity::ALL.len())
+        })
+        .collect()
+}
+
+fn name_summary(names: &BTreeSet<String>) -> String {
+    let shown = names.iter().take(NAMES_SHOWN).join(", ");
+    match names.len().saturating_sub(NAMES_SHOWN) {
+        0 => shown,
+        more => format!("{shown} and {more} more"),
+    }
+}
+
+fn receiver_name(parts: &[&str]) -> String {
+    let name = parts
+        .iter()
+        .map(|part| {
+            part.chars()
+                .map(|c| match c {
+                    'a'..='z' | '0'..='9' => c,
+                    'A'..='Z' => c.to_ascii_lowercase(),
+                    _ => '-',
+                })
+                .collect::<String>()
+        })
+        .join("-");
+    format!("snmp-{name}")
+}
+
+fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+    let unique = (1..)
+        .map(|i| match i {
+            1 => name.clone(),
+            i => format!("{name}-{i}"),
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .expect("some suffix is free");
+    taken.insert(unique.clone());
+    unique
+}
+
+fn matcher(label: &str, value: &str) -> String {
+    format!("{label}={}", serde_json::Value::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alertmanager::AlertmanagerAlert;
+    use crate::alerts::Severity;
+    use crate::config::test_settings;
+    use crate::route_suggestions::RouteSuggestions;
+    use itertools::Itertools;
+    use serde_norway::Value;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn routes_by_community_and_severity() {
+        let settings = test_settings("");
+        let now = OffsetDateTime::now_utc();
+        let alert = |name: &str, community: &str, severity: Severity| {
+            AlertmanagerAlert::new(&settings, now, now, name, community, severity, None)
+        };
+        let alerts = [
+            alert("linkDown", "core net", Severity::Warning),
+            alert("bgpDown", "core net", Severity::Critical),
+            alert("upsOnBattery", "power", Severity::Critical),
+        ];
+
+        let yaml = RouteSuggestions::from_alerts(&alerts, &settings).to_yaml();
+        let config: Value = serde_norway::from_str(&yaml).unwrap();
+        let routes = &config["route"]["routes"];
+        assert_eq!(routes[0]["matchers"][0], r#"community="core net""#);
+        assert_eq!(routes[0]["receiver"], "snmp-core-net");
+        assert_eq!(
+            routes[0]["routes"][0]["matchers"][0],
+            r#"severity="critical""#
+        );
+        assert_eq!(routes[0]["routes"][1]["receiver"], "snmp-core-net-warning");
+        assert_eq!(routes[1]["routes"][0]["receiver"], "snmp-power-critical");
+        assert_eq!(config["receivers"].as_sequence().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn receiver_names_stay_unique() {
+        let settings = test_settings("");
+        let now = OffsetDateTime::now_utc();
+        let alert = |community: &str, severity: Severity| {
+            AlertmanagerAlert::new(&settings, now, now, "linkDown", community, severity, None)
+        };
+        let alerts = [
+            alert("core net", Severity::Warning),
+            alert("core-net", Severity::Warning),
+            alert("x", Severity::Warning),
+            alert("x-warning", Severity::Warning),
+        ];
+
+        let yaml = RouteSuggestions::from_alerts(&alerts, &settings).to_yaml();
+        let config: Value = serde_norway::from_str(&yaml).unwrap();
+        let names = config["receivers"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|r| r["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names.iter().unique().count(), names.len());
+        assert!(names.contains(&"snmp-core-net-2"));
+        assert!(names.contains(&"snmp-x-warning-2"));
+    }
+}
@@ -0,0 +1,110 @@
+use itertools::Itertools;
+use log::warn;
+use serde::Serialize;
+use sqlx::postgres::PgRow;
+use sqlx::{Column, Row};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use time::{OffsetDateTime, PrimitiveDateTime};
+use utoipa::ToSchema;
+
+const SAMPLES_PER_REASON: usize = 5;
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RowErrorReason {
+    pub count: usize,
+    pub samples: Vec<BTreeMap<String, Option<String>>>,
+}
+
+// the invalid rows of one mapping cycle, grouped by why they were rejected
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RowErrorReport {
+    pub checked_at: Option<String>,
+    pub total: usize,
+    pub reasons: BTreeMap<String, RowErrorReason>,
+    #[serde(skip)]
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+// an invalid row with the reason, identified by its content so repeated cycles don't duplicate it
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub hash: i64,
+    pub reason: String,
+    pub row: BTreeMap<String, Option<String>>,
+}
+
+impl DeadLetter {
+    fn new(reason: String, row: BTreeMap<String, Option<String>>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        DeadLetter {
+            hash: hasher.finish() as i64,
+            reason,
+            row,
+        }
+    }
+}
+
+impl RowErrorReport {
+    pub fn new() -> Self {
+        RowErrorReport {
+            checked_at: Some(OffsetDateTime::now_utc().to_string()),
+            ..RowErrorReport::default()
+        }
+    }
+
+    pub fn record(&mut self, error: &anyhow::Error, row: &PgRow) {
+        self.total += 1;
+        let message = error.to_string();
+        let sample = row_sample(row);
+        let reason = self.reasons.entry(message.clone()).or_default();
+        reason.count += 1;
+        if reason.samples.len() < SAMPLES_PER_REASON {
+            reason.samples.push(sample.clone());
+        }
+        self.dead_letters.push(DeadLetter::new(message, sample));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    // one line per cycle instead of one per row, a misconfigured snmptrapd produces thousands
+    pub fn log_summary(&self) {
+        if self.is_empty() {
+            return;
+        }
+
+        let reasons = self
+            .reasons
+            .iter()
+            .sorted_by_key(|(_, reason)| std::cmp::Reverse(reason.count))
+            .map(|(message, reason)| format!("{}x {message}", reason.count))
+            .join("; ");
+        warn!(
+            "Skipped {} invalid alert database rows: {reasons}",
+            self.total
+        );
+    }
+}
+
+pub fn row_sample(row: &PgRow) -> BTreeMap<String, Option<String>> {
+    row.columns()
+        .iter()
+        .map(|col| {
+            let value = row
+                .try_get::<'_, Option<String>, _>(col.ordinal())
+                .or_else(|_| {
+                    row.try_get::<'_, Option<PrimitiveDateTime>, _>(col.ordinal())
+                        .map(|t| t.map(|t| t.to_string()))
+                })
+                .or_else(|_| {
+                    row.try_get::<'_, Option<i64>, _>(col.ordinal())
+                        .map(|n| n.map(|n| n.to_string()))
+                })
+                .unwrap_or_else(|_| Some("(unsupported type)".to_string()));
+            (col.name().to_string(), value)
+        })
+        .collect()
+}
@@ -0,0 +1,145 @@
+use crate::enrichment::{AlertEnrichmentFile, is_rule_file};
+use anyhow::{Context, bail};
+use flate2::read::GzDecoder;
+use log::debug;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RulePack {
+    source: String,
+    sha256: Option<String>,
+}
+
+impl RulePack {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.starts_with("http://") || self.source.starts_with("https://")
+    }
+
+    pub async fn load(&self) -> anyhow::Result<Vec<AlertEnrichmentFile>> {
+        let data = self.fetch().await?;
+        self.verify(&data)?;
+        extract(&data)
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<u8>> {
+        if !self.is_remote() {
+            return Ok(fs::read(&self.source)?);
+        }
+        if self.sha256.is_none() {
+            bail!("remote rule packs need a sha256 checksum");
+        }
+
+        let response = reqwest::get(&self.source).await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    fn verify(&self, data: &[u8]) -> anyhow::Result<()> {
+        let Some(expected) = &self.sha256 else {
+            return Ok(());
+        };
+
+        let actual = hex::encode(Sha256::digest(data));
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            bail!("checksum mismatch, expected {expected} but got {actual}");
+        }
+        Ok(())
+    }
+}
+
+// tar, tar.gz and zip are told apart by their magic bytes since URLs don't always carry an extension
+pub fn extract(data: &[u8]) -> anyhow::Result<Vec<AlertEnrichmentFile>> {
+    if data.starts_with(ZIP_MAGIC) {
+        extract_zip(data)
+    } else if data.starts_with(GZIP_MAGIC) {
+        extract_tar(GzDecoder::new(data))
+    } else {
+        extract_tar(data)
+    }
+}
+
+fn extract_tar(data: impl Read) -> anyhow::Result<Vec<AlertEnrichmentFile>> {
+    let mut archive = tar::Archive::new(data);
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if !entry.header().entry_type().is_file() || !is_rule_file(Path::new(&name)) {
+            debug!("Skipping {name:?} in rule pack");
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        files.push(AlertEnrichmentFile::parse(&content).with_context(|| format!("in {name:?}"))?);
+    }
+
+    Ok(files)
+}
+
+fn extract_zip(data: &[u8]) -> anyhow::Result<Vec<AlertEnrichmentFile>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut files = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+        if !entry.is_file() || !is_rule_file(Path::new(&name)) {
+            debug!("Skipping {name:?} in rule pack");
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        files.push(AlertEnrichmentFile::parse(&content).with_context(|| format!("in {name:?}"))?);
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule_pack::{RulePack, extract};
+    use std::io::Write;
+
+    const RULES: &str =
+        "alerts:\n  - name: linkDown\n    labels:\n      team: network\n    drop_labels: []\n";
+
+    #[test]
+    fn extracts_tar_and_zip() {
+        let mut tar = tar::Builder::new(Vec::new());
+        for name in ["cisco/link.yaml", "README.md"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(RULES.len() as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, name, RULES.as_bytes())
+                .unwrap();
+        }
+        let tar = tar.into_inner().unwrap();
+        assert_eq!(extract(&tar).unwrap().len(), 1);
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("apc.yml", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(RULES.as_bytes()).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        assert_eq!(extract(&zip).unwrap().len(), 1);
+
+        let pack = RulePack {
+            source: "pack.zip".to_string(),
+            sha256: Some("00".to_string()),
+        };
+        assert!(pack.verify(&zip).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::enrichment::AlertEnrichment;
+use crate::reload_diff::EnrichmentReloader;
+use anyhow::bail;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitRulesSettings {
+    url: String,
+    #[serde(default = "branch_default")]
+    branch: String,
+    dir: PathBuf,
+    subdir: Option<PathBuf>,
+    #[serde(
+        default = "interval_sec_default",
+        deserialize_with = "crate::config::interval_sec"
+    )]
+    interval_sec: u64,
+}
+
+fn branch_default() -> String {
+    "main".to_string()
+}
+
+fn interval_sec_default() -> u64 {
+    300
+}
+
+impl GitRulesSettings {
+    pub fn rules_dir(&self) -> PathBuf {
+        match &self.subdir {
+            Some(subdir) => self.dir.join(subdir),
+            None => self.dir.clone(),
+        }
+    }
+}
+
+pub struct GitRuleSync {
+    settings: GitRulesSettings,
+}
+
+impl GitRuleSync {
+    pub fn new(settings: GitRulesSettings) -> Self {
+        Self { settings }
+    }
+
+    // a stale checkout is better than no rules when the Git server is down during a restart
+    pub async fn initial_sync(&self) -> anyhow::Result<()> {
+        match self.sync().await {
+            Ok(_) => Ok(()),
+            Err(e) if self.settings.dir.join(".git").is_dir() => {
+                warn!(
+                    "Couldn't update enrichment rules from Git, using the existing checkout: {e:#}"
+                );
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn run_sync_blocking(&self, reloader: Arc<EnrichmentReloader>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.settings.interval_sec));
+        interval.tick().await;
+
+        // a failed reload is retried on the next poll, even if the revision didn't change again
+        let mut reload_pending = false;
+        loop {
+            interval.tick().await;
+
+            match self.sync().await {
+                Ok(false) if !reload_pending => debug!("Enrichment rules in Git are unchanged"),
+                Ok(_) => match AlertEnrichment::load_configured(&reloader.settings()).await {
+                    Ok(reloaded) => {
+                        info!(
+                            "Reloaded {} alert enrichments after Git change",
+                            reloaded.count()
+                        );
+                        reloader.replace(reloaded, "Git").await;
+                        reload_pending = false;
+                    }
+                    Err(e) => {
+                        warn!("Keeping previous enrichments, reload failed: {e:#}");
+                        reload_pending = true;
+                    }
+                },
+                Err(e) => warn!("Couldn't update enrichment rules from Git: {e:#}"),
+            }
+        }
+    }
+
+    // returns whether the checked out revision changed
+    async fn sync(&self) -> anyhow::Result<bool> {
+        let dir = &self.settings.dir;
+        if !dir.join(".git").is_dir() {
+            info!("Cloning enrichment rules from {}", self.settings.url);
+            git(
+                None,
+                &[
+                    "clone",
+                    "--branch",
+                    &self.settings.branch,
+                    "--single-branch",
+                ],
+            )
+            .arg(&self.settings.url)
+            .arg(dir)
+            .status_checked()
+            .await?;
+            return Ok(true);
+        }
+
+        let before = git(Some(dir), &["rev-parse", "HEAD"])
+            .output_checked()
+            .await?;
+        git(Some(dir), &["fetch", "origin", &self.settings.branch])
+            .status_checked()
+            .await?;
+        // the checkout mirrors the reviewed branch exactly, local edits don't survive a sync
+        git(Some(dir), &["reset", "--hard", "FETCH_HEAD"])
+            .status_checked()
+            .await?;
+        let after = git(Some(dir), &["rev-parse", "HEAD"])
+            .output_checked()
+            .await?;
+
+        if before != after {
+            info!("Enrichment rules updated to {after}");
+        }
+        Ok(before != after)
+    }
+}
+
+fn git(dir: Option<&Path>, args: &[&str]) -> Command {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    cmd.args(args).kill_on_drop(true);
+    cmd
+}
+
+trait CommandExt {
+    async fn status_checked(&mut self) -> anyhow::Result<()>;
+    async fn output_checked(&mut self) -> anyhow::Result<String>;
+}
+
+impl CommandExt for Command {
+    async fn status_checked(&mut self) -> anyhow::Result<()> {
+        self.output_checked().await.map(drop)
+    }
+
+    async fn output_checked(&mut self) -> anyhow::Result<String> {
+        let output = self.output().await?;
+        if !output.status.success() {
+            bail!(
+                "git failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
@@ -1,5 +1,10 @@
 use std::collections::BTreeMap;
 
+// JSON strings are valid YAML, whatever the value contains
+pub fn yaml_quoted(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
 pub fn greedy_truncate_labels_prefix(labels: &mut BTreeMap<String, String>) -> String {
     let prefix = find_greedy_label_prefix(labels);
 
@@ -80,3 +85,16 @@ pub fn clean_alert_name(mut name: String) -> String {
 
     name
 }
+
+// Alertmanager only accepts label and annotation names matching [a-zA-Z_][a-zA-Z0-9_]*
+pub fn clean_label_name(name: &str) -> String {
+    let mut clean: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if clean.is_empty() || clean.starts_with(|c: char| c.is_ascii_digit()) {
+        clean.insert(0, '_');
+    }
+
+    clean
+}
@@ -0,0 +1,45 @@
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiteMapping {
+    subnet: IpNet,
+    labels: BTreeMap<String, String>,
+}
+
+// the most specific subnet wins, so a rack can be carved out of its site's range
+pub fn site_labels(sites: &[SiteMapping], source: IpAddr) -> Option<&BTreeMap<String, String>> {
+    sites
+        .iter()
+        .filter(|site| site.subnet.contains(&source))
+        .max_by_key(|site| site.subnet.prefix_len())
+        .map(|site| &site.labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::test_settings;
+    use crate::sites::site_labels;
+
+    #[test]
+    fn most_specific_site() {
+        let settings = test_settings(
+            r#"
+sites:
+  - subnet: 10.1.0.0/16
+    labels: { site: fra1, region: eu-central }
+  - subnet: 10.1.2.0/24
+    labels: { site: fra1, region: eu-central, rack: r12 }
+"#,
+        );
+        let sites = settings.sites();
+
+        let rack = site_labels(sites, "10.1.2.3".parse().unwrap()).unwrap();
+        assert_eq!(rack.get("rack").map(String::as_str), Some("r12"));
+        let site = site_labels(sites, "10.1.3.3".parse().unwrap()).unwrap();
+        assert_eq!(site.get("rack"), None);
+        assert!(site_labels(sites, "192.0.2.1".parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,615 @@
+use anyhow::{Context, anyhow, bail};
+use std::fmt::Display;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_IP_ADDRESS: u8 = 0x40;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_OPAQUE: u8 = 0x44;
+const TAG_COUNTER64: u8 = 0x46;
+const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+const TAG_NO_SUCH_INSTANCE: u8 = 0x81;
+const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+pub const SYS_UPTIME_OID: &str = "1.3.6.1.2.1.1.3.0";
+pub const SNMP_TRAP_OID: &str = "1.3.6.1.6.3.1.1.4.1.0";
+const SNMP_TRAPS_PREFIX: &str = "1.3.6.1.6.3.1.1.5";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1 = 0,
+    V2c = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PduType {
+    GetRequest = 0xa0,
+    GetNextRequest = 0xa1,
+    Response = 0xa2,
+    SetRequest = 0xa3,
+    TrapV1 = 0xa4,
+    GetBulkRequest = 0xa5,
+    InformRequest = 0xa6,
+    TrapV2 = 0xa7,
+    Report = 0xa8,
+}
+
+impl TryFrom<u8> for PduType {
+    type Error = anyhow::Error;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        Ok(match tag {
+            0xa0 => PduType::GetRequest,
+            0xa1 => PduType::GetNextRequest,
+            0xa2 => PduType::Response,
+            0xa3 => PduType::SetRequest,
+            0xa4 => PduType::TrapV1,
+            0xa5 => PduType::GetBulkRequest,
+            0xa6 => PduType::InformRequest,
+            0xa7 => PduType::TrapV2,
+            0xa8 => PduType::Report,
+            _ => bail!("unknown PDU type {tag:#04x}"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid(Vec<u32>);
+
+impl Oid {
+    pub fn components(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn starts_with(&self, prefix: &Oid) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+}
+
+impl Display for Oid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for c in &self.0 {
+            if !first {
+                write!(f, ".")?;
+            }
+            write!(f, "{c}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Oid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components: Vec<u32> = s
+            .trim_start_matches('.')
+            .split('.')
+            .map(u32::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("invalid OID {s:?}"))?;
+
+        if components.len() < 2 {
+            bail!("OID {s:?} needs at least two components");
+        }
+
+        Ok(Oid(components))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    ObjectIdentifier(Oid),
+    IpAddress(Ipv4Addr),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Opaque(Vec<u8>),
+    Counter64(u64),
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(v) => write!(f, "{v}"),
+            Value::OctetString(bytes) => match std::str::from_utf8(bytes) {
+                Ok(s) if !s.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+                    write!(f, "{s}")
+                }
+                _ => write!(f, "{}", hex_string(bytes)),
+            },
+            Value::Null => Ok(()),
+            Value::ObjectIdentifier(oid) => write!(f, "{oid}"),
+            Value::IpAddress(ip) => write!(f, "{ip}"),
+            Value::Counter32(v) | Value::Gauge32(v) | Value::TimeTicks(v) => write!(f, "{v}"),
+            Value::Opaque(bytes) => write!(f, "{}", hex_string(bytes)),
+            Value::Counter64(v) => write!(f, "{v}"),
+            Value::NoSuchObject => write!(f, "noSuchObject"),
+            Value::NoSuchInstance => write!(f, "noSuchInstance"),
+            Value::EndOfMibView => write!(f, "endOfMibView"),
+        }
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarBind {
+    pub oid: Oid,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrapV1 {
+    pub enterprise: Oid,
+    pub agent_addr: Ipv4Addr,
+    pub generic_trap: i64,
+    pub specific_trap: i64,
+    pub time_stamp: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pdu {
+    pub pdu_type: PduType,
+    pub request_id: i32,
+    pub error_status: i32,
+    pub error_index: i32,
+    pub trap_v1: Option<TrapV1>,
+    pub varbinds: Vec<VarBind>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub version: Version,
+    pub community: String,
+    pub pdu: Pdu,
+}
+
+impl Message {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Message> {
+        let mut reader = Reader::new(data);
+        let mut msg = reader.sequence()?;
+
+        let version = match msg.integer()? {
+            0 => Version::V1,
+            1 => Version::V2c,
+            v => bail!("unsupported SNMP version {v}"),
+        };
+        let community = String::from_utf8_lossy(&msg.octet_string()?).into_owned();
+
+        let (tag, body) = msg.tlv()?;
+        let pdu_type = PduType::try_from(tag)?;
+        let mut body = Reader::new(body);
+
+        let pdu = if pdu_type == PduType::TrapV1 {
+            let enterprise = body.oid()?;
+            let (tag, addr) = body.tlv()?;
+            if tag != TAG_IP_ADDRESS || addr.len() != 4 {
+                bail!("invalid agent address in v1 trap");
+            }
+            let agent_addr = Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]);
+            let generic_trap = body.integer()?;
+            let specific_trap = body.integer()?;
+            let (_, ticks) = body.tlv()?;
+            let time_stamp = decode_unsigned(ticks)? as u32;
+
+            Pdu {
+                pdu_type,
+                request_id: 0,
+                error_status: 0,
+                error_index: 0,
+                trap_v1: Some(TrapV1 {
+                    enterprise,
+                    agent_addr,
+                    generic_trap,
+                    specific_trap,
+                    time_stamp,
+                }),
+                varbinds: body.varbinds()?,
+            }
+        } else {
+            Pdu {
+                pdu_type,
+                request_id: body.integer()? as i32,
+                error_status: body.integer()? as i32,
+                error_index: body.integer()? as i32,
+                trap_v1: None,
+                varbinds: body.varbinds()?,
+            }
+        };
+
+        Ok(Message {
+            version,
+            community,
+            pdu,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        match &self.pdu.trap_v1 {
+            Some(trap) => {
+                write_tlv(&mut body, TAG_OID, &encode_oid(&trap.enterprise));
+                write_tlv(&mut body, TAG_IP_ADDRESS, &trap.agent_addr.octets());
+                write_tlv(&mut body, TAG_INTEGER, &encode_integer(trap.generic_trap));
+                write_tlv(&mut body, TAG_INTEGER, &encode_integer(trap.specific_trap));
+                write_tlv(
+                    &mut body,
+                    TAG_TIMETICKS,
+                    &encode_unsigned(trap.time_stamp as u64),
+                );
+            }
+            None => {
+                write_tlv(
+                    &mut body,
+                    TAG_INTEGER,
+                    &encode_integer(self.pdu.request_id as i64),
+                );
+                write_tlv(
+                    &mut body,
+                    TAG_INTEGER,
+                    &encode_integer(self.pdu.error_status as i64),
+                );
+                write_tlv(
+                    &mut body,
+                    TAG_INTEGER,
+                    &encode_integer(self.pdu.error_index as i64),
+                );
+            }
+        }
+
+        let mut varbinds = Vec::new();
+        for varbind in &self.pdu.varbinds {
+            let mut entry = Vec::new();
+            write_tlv(&mut entry, TAG_OID, &encode_oid(&varbind.oid));
+            encode_value(&mut entry, &varbind.value);
+            write_tlv(&mut varbinds, TAG_SEQUENCE, &entry);
+        }
+        write_tlv(&mut body, TAG_SEQUENCE, &varbinds);
+
+        let mut msg = Vec::new();
+        write_tlv(&mut msg, TAG_INTEGER, &encode_integer(self.version as i64));
+        write_tlv(&mut msg, TAG_OCTET_STRING, self.community.as_bytes());
+        write_tlv(&mut msg, self.pdu.pdu_type as u8, &body);
+
+        let mut out = Vec::new();
+        write_tlv(&mut out, TAG_SEQUENCE, &msg);
+        out
+    }
+
+    pub fn inform_response(&self) -> Option<Message> {
+        if self.pdu.pdu_type != PduType::InformRequest {
+            return None;
+        }
+
+        let mut response = self.clone();
+        response.pdu.pdu_type = PduType::Response;
+        response.pdu.error_status = 0;
+        response.pdu.error_index = 0;
+        Some(response)
+    }
+
+    pub fn is_notification(&self) -> bool {
+        matches!(
+            self.pdu.pdu_type,
+            PduType::TrapV1 | PduType::TrapV2 | PduType::InformRequest
+        )
+    }
+
+    pub fn trap_oid(&self) -> Option<Oid> {
+        if let Some(trap) = &self.pdu.trap_v1 {
+            let oid = if trap.generic_trap == 6 {
+                format!("{}.0.{}", trap.enterprise, trap.specific_trap)
+            } else {
+                format!("{SNMP_TRAPS_PREFIX}.{}", trap.generic_trap + 1)
+            };
+            return oid.parse().ok();
+        }
+
+        let trap_oid = Oid::from_str(SNMP_TRAP_OID).ok()?;
+        self.pdu
+            .varbinds
+            .iter()
+            .find(|v| v.oid == trap_oid)
+            .and_then(|v| match &v.value {
+                Value::ObjectIdentifier(oid) => Some(oid.clone()),
+                _ => None,
+            })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn tlv(&mut self) -> anyhow::Result<(u8, &'a [u8])> {
+        let [tag, first, rest @ ..] = self.data else {
+            bail!("truncated BER header");
+        };
+
+        let (len, rest) = if first & 0x80 == 0 {
+            (*first as usize, rest)
+        } else {
+            let octets = (first & 0x7f) as usize;
+            if octets == 0 || octets > 4 || rest.len() < octets {
+                bail!("unsupported BER length encoding");
+            }
+            let len = rest[..octets]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, &rest[octets..])
+        };
+
+        if rest.len() < len {
+            bail!("truncated BER value");
+        }
+
+        self.data = &rest[len..];
+        Ok((*tag, &rest[..len]))
+    }
+
+    fn expect(&mut self, expected: u8) -> anyhow::Result<&'a [u8]> {
+        let (tag, value) = self.tlv()?;
+        if tag != expected {
+            bail!("expected BER tag {expected:#04x}, found {tag:#04x}");
+        }
+        Ok(value)
+    }
+
+    fn sequence(&mut self) -> anyhow::Result<Reader<'a>> {
+        Ok(Reader::new(self.expect(TAG_SEQUENCE)?))
+    }
+
+    fn integer(&mut self) -> anyhow::Result<i64> {
+        decode_integer(self.expect(TAG_INTEGER)?)
+    }
+
+    fn octet_string(&mut self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.expect(TAG_OCTET_STRING)?.to_vec())
+    }
+
+    fn oid(&mut self) -> anyhow::Result<Oid> {
+        decode_oid(self.expect(TAG_OID)?)
+    }
+
+    fn varbinds(&mut self) -> anyhow::Result<Vec<VarBind>> {
+        let mut list = self.sequence()?;
+        let mut varbinds = Vec::new();
+        while !list.is_empty() {
+            let mut entry = list.sequence()?;
+            let oid = entry.oid()?;
+            let (tag, value) = entry.tlv()?;
+            varbinds.push(VarBind {
+                oid,
+                value: decode_value(tag, value)?,
+            });
+        }
+        Ok(varbinds)
+    }
+}
+
+fn decode_integer(bytes: &[u8]) -> anyhow::Result<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        bail!("invalid integer length {}", bytes.len());
+    }
+    let init = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Ok(bytes.iter().fold(init, |acc, b| (acc << 8) | *b as i64))
+}
+
+fn decode_unsigned(bytes: &[u8]) -> anyhow::Result<u64> {
+    let bytes = match bytes {
+        [0, rest @ ..] => rest,
+        _ => bytes,
+    };
+    if bytes.len() > 8 {
+        bail!("unsigned integer too large");
+    }
+    Ok(bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn decode_oid(bytes: &[u8]) -> anyhow::Result<Oid> {
+    let Some((first, rest)) = bytes.split_first() else {
+        bail!("empty OID");
+    };
+
+    let mut components = if *first >= 80 {
+        vec![2, (*first - 80) as u32]
+    } else {
+        vec![(*first / 40) as u32, (*first % 40) as u32]
+    };
+
+    let mut current: u32 = 0;
+    for b in rest {
+        current = current
+            .checked_shl(7)
+            .ok_or_else(|| anyhow!("OID component overflow"))?
+            | (b & 0x7f) as u32;
+        if b & 0x80 == 0 {
+            components.push(current);
+            current = 0;
+        }
+    }
+
+    Ok(Oid(components))
+}
+
+fn decode_value(tag: u8, bytes: &[u8]) -> anyhow::Result<Value> {
+    Ok(match tag {
+        TAG_INTEGER => Value::Integer(decode_integer(bytes)?),
+        TAG_OCTET_STRING => Value::OctetString(bytes.to_vec()),
+        TAG_NULL => Value::Null,
+        TAG_OID => Value::ObjectIdentifier(decode_oid(bytes)?),
+        TAG_IP_ADDRESS => match bytes {
+            [a, b, c, d] => Value::IpAddress(Ipv4Addr::new(*a, *b, *c, *d)),
+            _ => bail!("invalid IpAddress length {}", bytes.len()),
+        },
+        TAG_COUNTER32 => Value::Counter32(decode_unsigned(bytes)? as u32),
+        TAG_GAUGE32 => Value::Gauge32(decode_unsigned(bytes)? as u32),
+        TAG_TIMETICKS => Value::TimeTicks(decode_unsigned(bytes)? as u32),
+        TAG_OPAQUE => Value::Opaque(bytes.to_vec()),
+        TAG_COUNTER64 => Value::Counter64(decode_unsigned(bytes)?),
+        TAG_NO_SUCH_OBJECT => Value::NoSuchObject,
+        TAG_NO_SUCH_INSTANCE => Value::NoSuchInstance,
+        TAG_END_OF_MIB_VIEW => Value::EndOfMibView,
+        _ => bail!("unsupported value type {tag:#04x}"),
+    })
+}
+
+fn write_tlv(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(value);
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn encode_unsigned(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    let mut out = bytes[skip..].to_vec();
+    if out[0] & 0x80 != 0 {
+        out.insert(0, 0);
+    }
+    out
+}
+
+fn encode_oid(oid: &Oid) -> Vec<u8> {
+    let components = oid.components();
+    let mut out = Vec::new();
+    let (first, rest) = match components {
+        [a, b, rest @ ..] => (a * 40 + b, rest),
+        [a] => (a * 40, &[][..]),
+        [] => return out,
+    };
+
+    for c in std::iter::once(&first).chain(rest) {
+        let mut chunk = vec![(*c & 0x7f) as u8];
+        let mut c = *c >> 7;
+        while c > 0 {
+            chunk.push((c & 0x7f) as u8 | 0x80);
+            c >>= 7;
+        }
+        out.extend(chunk.iter().rev());
+    }
+    out
+}
+
+fn encode_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Integer(v) => write_tlv(out, TAG_INTEGER, &encode_integer(*v)),
+        Value::OctetString(bytes) => write_tlv(out, TAG_OCTET_STRING, bytes),
+        Value::Null => write_tlv(out, TAG_NULL, &[]),
+        Value::ObjectIdentifier(oid) => write_tlv(out, TAG_OID, &encode_oid(oid)),
+        Value::IpAddress(ip) => write_tlv(out, TAG_IP_ADDRESS, &ip.octets()),
+        Value::Counter32(v) => write_tlv(out, TAG_COUNTER32, &encode_unsigned(*v as u64)),
+        Value::Gauge32(v) => write_tlv(out, TAG_GAUGE32, &encode_unsigned(*v as u64)),
+        Value::TimeTicks(v) => write_tlv(out, TAG_TIMETICKS, &encode_unsigned(*v as u64)),
+        Value::Opaque(bytes) => write_tlv(out, TAG_OPAQUE, bytes),
+        Value::Counter64(v) => write_tlv(out, TAG_COUNTER64, &encode_unsigned(*v)),
+        Value::NoSuchObject => write_tlv(out, TAG_NO_SUCH_OBJECT, &[]),
+        Value::NoSuchInstance => write_tlv(out, TAG_NO_SUCH_INSTANCE, &[]),
+        Value::EndOfMibView => write_tlv(out, TAG_END_OF_MIB_VIEW, &[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snmp::{Message, Oid, Pdu, PduType, SNMP_TRAP_OID, Value, VarBind, Version};
+
+    fn inform() -> Message {
+        Message {
+            version: Version::V2c,
+            community: "public".to_string(),
+            pdu: Pdu {
+                pdu_type: PduType::InformRequest,
+                request_id: 1234567,
+                error_status: 0,
+                error_index: 0,
+                trap_v1: None,
+                varbinds: vec![
+                    VarBind {
+                        oid: SNMP_TRAP_OID.parse().unwrap(),
+                        value: Value::ObjectIdentifier("1.3.6.1.4.1.8072.2.3.0.1".parse().unwrap()),
+                    },
+                    VarBind {
+                        oid: "1.3.6.1.4.1.8072.2.3.2.1".parse().unwrap(),
+                        value: Value::Integer(-300),
+                    },
+                    VarBind {
+                        oid: "1.3.6.1.2.1.2.2.1.2.1".parse().unwrap(),
+                        value: Value::OctetString(b"eth0".repeat(40)),
+                    },
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let msg = inform();
+        let decoded = Message::decode(&msg.encode()).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(
+            decoded.trap_oid(),
+            Some("1.3.6.1.4.1.8072.2.3.0.1".parse::<Oid>().unwrap())
+        );
+    }
+
+    #[test]
+    fn inform_is_acknowledged() {
+        let response = inform().inform_response().unwrap();
+        assert_eq!(response.pdu.pdu_type, PduType::Response);
+        assert_eq!(response.pdu.request_id, 1234567);
+        assert!(response.inform_response().is_none());
+    }
+}
@@ -0,0 +1,342 @@
+use crate::sanitize::yaml_quoted;
+use anyhow::{Context, bail};
+use ipnet::IpNet;
+use itertools::Itertools;
+use log::warn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub struct AuthCommunity {
+    pub name: String,
+    pub types: Vec<String>,
+    pub source: Option<String>,
+}
+
+impl AuthCommunity {
+    // only logged or executed traps reach us, `net` alone just forwards them
+    fn is_received(&self) -> bool {
+        self.types.iter().any(|t| t == "log" || t == "execute")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AuthUser {
+    pub name: String,
+    pub types: Vec<String>,
+    pub level: Option<String>,
+}
+
+// the access control parts of an snmptrapd.conf, everything else is left to snmptrapd
+#[derive(Debug, Default)]
+pub struct SnmptrapdConf {
+    pub communities: Vec<AuthCommunity>,
+    pub users: Vec<AuthUser>,
+    pub disable_authorization: bool,
+}
+
+impl SnmptrapdConf {
+    pub fn load(file: &Path) -> anyhow::Result<SnmptrapdConf> {
+        Self::parse(&fs::read_to_string(file)?)
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<SnmptrapdConf> {
+        let mut conf = SnmptrapdConf::default();
+        for (number, line) in content.lines().enumerate() {
+            let tokens = tokenize(line);
+            let Some((directive, args)) = tokens.split_first() else {
+                continue;
+            };
+            let result = match directive.to_lowercase().as_str() {
+                "authcommunity" => parse_community(args).map(|c| conf.communities.push(c)),
+                "authuser" => parse_user(args).map(|u| conf.users.push(u)),
+                "disableauthorization" => {
+                    conf.disable_authorization = args
+                        .first()
+                        .is_some_and(|v| matches!(v.as_str(), "yes" | "true" | "1"));
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            result.with_context(|| format!("line {}: {line:?}", number + 1))?;
+        }
+        Ok(conf)
+    }
+
+    // a full configuration to start from, placeholders included
+    pub fn starter_config(&self, origin: &str) -> String {
+        let received = self
+            .communities
+            .iter()
+            .filter(|c| c.is_received())
+            .collect_vec();
+        let names = received
+            .iter()
+            .map(|c| c.name.as_str())
+            .unique()
+            .collect_vec();
+        let comment = if self.disable_authorization { "# " } else { "" };
+
+        let mut out = String::new();
+        let mut line = |text: String| {
+            out.push_str(&text);
+            out.push('\n');
+        };
+
+        line(format!("# Starter configuration imported from {origin}"));
+        line("web_url: http://localhost:7788".to_string());
+        line("db_connection_url: postgres://snmptrapd@localhost/net_snmp".to_string());
+        line("alertmanager_url: http://localhost:9093".to_string());
+        line(String::new());
+
+        if self.disable_authorization {
+            line("# snmptrapd accepts every community (disableAuthorization yes),".to_string());
+            line("# uncomment to only accept the ones it was configured with".to_string());
+        }
+        if names.is_empty() {
+            line("# no authCommunity entries logging or executing traps were found".to_string());
+        } else {
+            line(format!("{comment}source_filter:"));
+            line(format!("{comment}  allow_communities:"));
+            for name in &names {
+                line(format!("{comment}    - {}", yaml_quoted(name)));
+            }
+            match allowed_sources(&received) {
+                AllowedSources::Shared(sources) => {
+                    line(format!("{comment}  allow_sources:"));
+                    for source in sources {
+                        line(format!("{comment}    - {source}"));
+                    }
+                }
+                AllowedSources::PerCommunity(communities) => {
+                    warn!(
+                        "The authCommunity sources differ per community, allow_sources is left commented out"
+                    );
+                    line(
+                        "  # the communities are limited to different sources, but the filter"
+                            .to_string(),
+                    );
+                    line(
+                        "  # applies to all of them. Uncomment to accept every listed source"
+                            .to_string(),
+                    );
+                    line("  # for every community".to_string());
+                    line("  # allow_sources:".to_string());
+                    for (name, sources) in communities {
+                        for source in sources {
+                            line(format!("  #   - {source} # {}", yaml_quoted(name)));
+                        }
+                    }
+                }
+                AllowedSources::Open => line(
+                    "# not every community is limited to source addresses, sources stay open"
+                        .to_string(),
+                ),
+            }
+        }
+        for community in self.communities.iter().filter(|c| !c.is_received()) {
+            line(format!(
+                "# authCommunity {} only allows {}, its traps never reach the database",
+                yaml_quoted(&community.name),
+                community.types.join(",")
+            ));
+        }
+        for user in &self.users {
+            line(format!(
+                "# SNMPv3 user {} ({}) has no community, allow the one its traps are stored with",
+                yaml_quoted(&user.name),
+                user.level.as_deref().unwrap_or("authNoPriv")
+            ));
+        }
+
+        if !names.is_empty() {
+            line(String::new());
+            line("communities:".to_string());
+            for name in &names {
+                line(format!("  {}:", yaml_quoted(name)));
+                line(format!("    name: {}", yaml_quoted(name)));
+            }
+
+            line(String::new());
+            line("# one route per community, fill in the routing keys".to_string());
+            line("# pagerduty:".to_string());
+            line("#   routes:".to_string());
+            for name in &names {
+                line(format!("#     - community: {}", yaml_quoted(name)));
+                line("#       routing_key: <routing key>".to_string());
+            }
+        }
+        out
+    }
+}
+
+enum AllowedSources<'a> {
+    Open,
+    Shared(BTreeSet<IpNet>),
+    PerCommunity(BTreeMap<&'a str, BTreeSet<IpNet>>),
+}
+
+// the filter is global, so sources only carry over as they are when every community is
+// limited to the same ones
+fn allowed_sources<'a>(communities: &[&'a AuthCommunity]) -> AllowedSources<'a> {
+    let mut by_community: BTreeMap<&str, BTreeSet<IpNet>> = BTreeMap::new();
+    for community in communities {
+        let Some(source) = community.source.as_deref().and_then(parse_source) else {
+            return AllowedSources::Open;
+        };
+        by_community
+            .entry(&community.name)
+            .or_default()
+            .insert(source);
+    }
+
+    match by_community.values().all_equal_value() {
+        Ok(sources) => AllowedSources::Shared(sources.clone()),
+        Err(Some(_)) => AllowedSources::PerCommunity(by_community),
+        Err(None) => AllowedSources::Open,
+    }
+}
+
+fn parse_source(source: &str) -> Option<IpNet> {
+    source
+        .parse()
+        .ok()
+        .or_else(|| source.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+// authCommunity TYPES COMMUNITY [SOURCE [OID | -v VIEW]]
+fn parse_community(args: &[String]) -> anyhow::Result<AuthCommunity> {
+    let [types, name, rest @ ..] = args else {
+        bail!("authCommunity needs the allowed types and a community");
+    };
+    Ok(AuthCommunity {
+        name: name.clone(),
+        types: split_types(types),
+        source: rest
+            .first()
+            .filter(|s| s.as_str() != "default" && !s.starts_with('-'))
+            .cloned(),
+    })
+}
+
+// authUser TYPES [-s MODEL] USER [LEVEL [OID | -v VIEW]]
+fn parse_user(args: &[String]) -> anyhow::Result<AuthUser> {
+    let Some((types, mut rest)) = args.split_first() else {
+        bail!("authUser needs the allowed types and a user");
+    };
+    if rest.first().is_some_and(|a| a == "-s") {
+        rest = rest.get(2..).unwrap_or_default();
+    }
+    let Some((name, rest)) = rest.split_first() else {
+        bail!("authUser needs a user");
+    };
+    Ok(AuthUser {
+        name: name.clone(),
+        types: split_types(types),
+        level: rest.first().filter(|l| !l.starts_with('-')).cloned(),
+    })
+}
+
+fn split_types(types: &str) -> Vec<String> {
+    types.split(',').map(|t| t.trim().to_lowercase()).collect()
+}
+
+// whitespace separated, double quotes group, a # outside of quotes starts a comment
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert_default();
+            }
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => tokens.extend(current.take()),
+            c => current.get_or_insert_default().push(c),
+        }
+    }
+    tokens.extend(current);
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Settings;
+    use crate::snmptrapd_import::SnmptrapdConf;
+
+    #[test]
+    fn starter_config_from_communities() {
+        let conf = SnmptrapdConf::parse(
+            r#"
+# local traps
+authCommunity log,execute public 10.0.0.0/8
+authCommunity log "core net" 192.0.2.1
+authCommunity net relay
+authUser log -s usm alice authPriv
+traphandle default /usr/bin/true
+"#,
+        )
+        .unwrap();
+        assert_eq!(conf.communities.len(), 3);
+        assert_eq!(conf.communities[1].name, "core net");
+        assert_eq!(conf.users[0].name, "alice");
+        assert_eq!(conf.users[0].level.as_deref(), Some("authPriv"));
+
+        let settings = Settings::from_yaml(&conf.starter_config("snmptrapd.conf")).unwrap();
+        let filter = settings.source_filter();
+        let source = |ip: &str| Some(ip.parse().unwrap());
+        assert_eq!(filter.rejection_reason(source("10.1.2.3"), "public"), None);
+        assert_eq!(
+            filter.rejection_reason(source("192.0.2.1"), "core net"),
+            None
+        );
+        assert!(
+            filter
+                .rejection_reason(source("10.1.2.3"), "relay")
+                .is_some()
+        );
+        // the sources differ by community, merging them would let each use the others'
+        assert_eq!(
+            filter.rejection_reason(source("198.51.100.1"), "public"),
+            None
+        );
+        assert!(
+            conf.starter_config("snmptrapd.conf")
+                .contains(r#"  #   - 192.0.2.1/32 # "core net""#)
+        );
+        assert_eq!(
+            settings
+                .community_display("core net")
+                .and_then(|d| d.name()),
+            Some("core net")
+        );
+    }
+
+    #[test]
+    fn shared_sources_are_allowed() {
+        let conf = SnmptrapdConf::parse(
+            "authCommunity log public 10.0.0.0/8\n\
+             authCommunity log private 10.0.0.0/8\n",
+        )
+        .unwrap();
+
+        let settings = Settings::from_yaml(&conf.starter_config("snmptrapd.conf")).unwrap();
+        let source = |ip: &str| Some(ip.parse().unwrap());
+        assert_eq!(
+            settings
+                .source_filter()
+                .rejection_reason(source("10.1.2.3"), "private"),
+            None
+        );
+        assert!(
+            settings
+                .source_filter()
+                .rejection_reason(source("198.51.100.1"), "public")
+                .is_some()
+        );
+    }
+}
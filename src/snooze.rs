@@ -0,0 +1,57 @@
+use crate::audit::AuditEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+pub const MAX_SNOOZE: Duration = Duration::days(7);
+
+// unlike a clear the trap rows stay, the alert is only hidden until the snooze runs out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snooze {
+    #[serde(with = "time::serde::rfc3339")]
+    pub until: OffsetDateTime,
+    pub entry: AuditEntry,
+}
+
+impl Snooze {
+    pub fn new(duration: Duration, entry: AuditEntry) -> Snooze {
+        Snooze {
+            until: entry.time + duration,
+            entry,
+        }
+    }
+
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        now < self.until
+    }
+}
+
+pub fn active_snoozes(snoozes: &HashMap<u64, Snooze>) -> HashMap<u64, Snooze> {
+    let now = OffsetDateTime::now_utc();
+    snoozes
+        .iter()
+        .filter(|(_, snooze)| snooze.is_active(now))
+        .map(|(hash, snooze)| (*hash, snooze.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::AuditEntry;
+    use crate::snooze::{Snooze, active_snoozes};
+    use std::collections::HashMap;
+    use time::Duration;
+
+    #[test]
+    fn snoozes_expire() {
+        let entry = AuditEntry::new("snooze", 1, "linkDown", "", "test");
+        let snoozes = HashMap::from([
+            (1, Snooze::new(Duration::minutes(30), entry.clone())),
+            (2, Snooze::new(Duration::minutes(-1), entry)),
+        ]);
+
+        let active = active_snoozes(&snoozes);
+        assert!(active.contains_key(&1));
+        assert!(!active.contains_key(&2));
+    }
+}
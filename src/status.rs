@@ -0,0 +1,173 @@
+use crate::alertmanager::RelayStatus;
+use crate::config::Settings;
+use crate::enrichment::AlertEnrichment;
+use crate::notifier::NotifierStats;
+use crate::trap_db::{CacheStats, TrapDb};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+static STARTED_AT: LazyLock<OffsetDateTime> = LazyLock::new(OffsetDateTime::now_utc);
+
+// called early in main, otherwise the uptime would start with the first status request
+pub fn mark_started() {
+    LazyLock::force(&STARTED_AT);
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SinkStatus {
+    pub successes: u64,
+    pub failures: u64,
+    pub last_success: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Status {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub started_at: String,
+    pub uptime_sec: i64,
+    pub last_relay: Option<String>,
+    pub failed_deliveries: usize,
+    pub sinks: BTreeMap<String, SinkStatus>,
+    pub cache: CacheStats,
+    pub enrichments: usize,
+    pub config: ConfigSummary,
+}
+
+impl Status {
+    pub async fn collect(
+        db: &TrapDb,
+        relay_status: &RelayStatus,
+        notifier_stats: &NotifierStats,
+        enrichment: &AlertEnrichment,
+    ) -> Status {
+        let failed_deliveries = relay_status
+            .deliveries()
+            .await
+            .values()
+            .filter(|d| d.last_error.is_some())
+            .count();
+        let sinks = notifier_stats
+            .sinks()
+            .await
+            .iter()
+            .map(|(name, stats)| {
+                let status = SinkStatus {
+                    successes: stats.successes,
+                    failures: stats.failures,
+                    last_success: stats.last_success.map(|t| t.to_string()),
+                };
+                (name.clone(), status)
+            })
+            .collect();
+
+        Status {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GIT_COMMIT"),
+            started_at: STARTED_AT.to_string(),
+            uptime_sec: (OffsetDateTime::now_utc() - *STARTED_AT).whole_seconds(),
+            last_relay: relay_status.last_success().await.map(|t| t.to_string()),
+            failed_deliveries,
+            sinks,
+            cache: db.cache_stats().await,
+            enrichments: enrichment.count(),
+            config: ConfigSummary::from(db.settings().as_ref()),
+        }
+    }
+}
+
+// only what helps telling deployments apart, nothing that could carry credentials
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConfigSummary {
+    pub web_url: String,
+    pub web_listen: Vec<String>,
+    pub trap_listen: Vec<String>,
+    pub database: String,
+    pub alertmanager_url: String,
+    pub announce_sec: i64,
+    pub sql_aggregation: bool,
+    pub leader_election: bool,
+    pub redis: bool,
+    pub oidc: bool,
+    pub api_keys: usize,
+    pub rule_packs: usize,
+    pub rules_git: bool,
+    pub notifiers: Vec<&'static str>,
+    pub pipelines: Vec<String>,
+}
+
+impl From<&Settings> for ConfigSummary {
+    fn from(settings: &Settings) -> Self {
+        let notifiers = [
+            ("oncall", settings.oncall().is_some()),
+            ("opsgenie", settings.opsgenie().is_some()),
+            ("pagerduty", settings.pagerduty().is_some()),
+            ("chat", !settings.chat().is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, configured)| configured.then_some(name))
+        .collect();
+
+        ConfigSummary {
+            web_url: settings.web_url().to_string(),
+            web_listen: settings
+                .web_listen()
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            trap_listen: settings
+                .trap_listen()
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            database: redact_url(settings.db_url()),
+            alertmanager_url: redact_url(settings.alertmanager_url()),
+            announce_sec: settings.alertmanager_announce_duration().whole_seconds(),
+            sql_aggregation: settings.sql_aggregation(),
+            leader_election: settings.leader_election(),
+            redis: settings.redis_url().is_some(),
+            oidc: settings.oidc().is_some(),
+            api_keys: settings.api_keys().len(),
+            rule_packs: settings.rule_packs().len(),
+            rules_git: settings.rules_git().is_some(),
+            notifiers,
+            pipelines: settings
+                .pipelines()
+                .iter()
+                .map(|p| p.name.clone())
+                .collect(),
+        }
+    }
+}
+
+// credentials can hide in the userinfo as well as in query parameters like `password=`
+fn redact_url(url: &str) -> String {
+    let Ok(mut url) = Url::parse(url) else {
+        return "(unparseable)".to_string();
+    };
+    _ = url.set_password(None);
+    url.set_query(None);
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::status::redact_url;
+
+    #[test]
+    fn urls_are_redacted() {
+        assert_eq!(
+            redact_url("postgres://snmp:secret@db:5432/traps?password=secret"),
+            "postgres://snmp@db:5432/traps"
+        );
+        assert_eq!(
+            redact_url("http://alertmanager:9093"),
+            "http://alertmanager:9093/"
+        );
+        assert_eq!(redact_url("not a url"), "(unparseable)");
+    }
+}
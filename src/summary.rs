@@ -0,0 +1,104 @@
+use crate::alertmanager::RelayStatus;
+use crate::alerts::Severity;
+use crate::trap_db::TrapDb;
+use itertools::Itertools;
+use serde::Serialize;
+use std::cmp;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use time::ext::NumericalDuration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Summary {
+    pub total: usize,
+    pub by_severity: BTreeMap<String, usize>,
+    pub by_community: BTreeMap<String, usize>,
+    pub added_last_hour: usize,
+    pub resolved_last_hour: usize,
+    pub snoozed: usize,
+    pub last_relay: Option<String>,
+}
+
+impl Summary {
+    pub async fn collect(db: &TrapDb, relay_status: &RelayStatus) -> Summary {
+        let hour_ago = OffsetDateTime::now_utc() - 1.hours();
+
+        let mut by_severity: BTreeMap<String, usize> =
+            Severity::ALL.iter().map(|s| (s.to_string(), 0)).collect();
+        let mut by_community = BTreeMap::new();
+        let mut added_last_hour = 0;
+
+        let alerts = db.cached_alerts().await;
+        for alert in alerts.iter() {
+            *by_severity.entry(alert.severity().to_string()).or_default() += 1;
+            *by_community
+                .entry(alert.community().to_string())
+                .or_default() += 1;
+            if alert.earliest() >= hour_ago {
+                added_last_hour += 1;
+            }
+        }
+        let total = alerts.len();
+        drop(alerts);
+
+        Summary {
+            total,
+            by_severity,
+            by_community,
+            added_last_hour,
+            resolved_last_hour: db.resolved_since(hour_ago).await,
+            snoozed: db.snoozed_alerts().await.len(),
+            last_relay: relay_status.last_success().await.map(|t| t.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WallboardAlert {
+    pub name: String,
+    pub severity: String,
+    pub community: String,
+    pub count: usize,
+    pub last_seen: String,
+}
+
+// kept small on purpose, wallboards poll this and only have room for a handful of lines
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Wallboard {
+    pub total: usize,
+    pub by_severity: BTreeMap<String, usize>,
+    pub top: Vec<WallboardAlert>,
+}
+
+impl Wallboard {
+    pub async fn collect(db: &TrapDb, limit: usize) -> Wallboard {
+        let mut by_severity: BTreeMap<String, usize> =
+            Severity::ALL.iter().map(|s| (s.to_string(), 0)).collect();
+
+        let snoozed = db.snoozed_alerts().await;
+        let alerts = db.cached_alerts().await;
+        for alert in alerts.iter() {
+            *by_severity.entry(alert.severity().to_string()).or_default() += 1;
+        }
+        let top = alerts
+            .iter()
+            .filter(|alert| !snoozed.contains_key(&alert.hash()))
+            .sorted_by_key(|a| (cmp::Reverse(a.severity() as u8), cmp::Reverse(a.latest())))
+            .take(limit)
+            .map(|alert| WallboardAlert {
+                name: alert.pretty_name(),
+                severity: alert.severity().to_string(),
+                community: alert.community().to_string(),
+                count: alert.count(),
+                last_seen: alert.latest().to_string(),
+            })
+            .collect();
+
+        Wallboard {
+            total: alerts.len(),
+            by_severity,
+            top,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::enrichment::AlertEnrichment;
+use crate::reload_diff::EnrichmentReloader;
+use log::{debug, info, warn};
+use std::net::TcpListener;
+use std::os::fd::FromRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
+
+const LISTEN_FDS_START: i32 = 3;
+
+// the sd_notify protocol is one datagram per state change to $NOTIFY_SOCKET, no library needed
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = (|| {
+        let addr = match path.as_encoded_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)
+    })();
+    if let Err(e) = result {
+        warn!("Failed to notify systemd about {state:?}: {e}");
+    }
+}
+
+// sockets passed by systemd socket activation, only if they were meant for this process
+pub fn listen_fds() -> Vec<TcpListener> {
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<i32>().ok())
+        .unwrap_or(0);
+    if !for_us || count <= 0 {
+        return Vec::new();
+    }
+
+    info!("Using {count} web listener sockets passed by systemd");
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        // SAFETY: systemd hands these descriptors over exclusively to the process in LISTEN_PID
+        .map(|fd| unsafe { TcpListener::from_raw_fd(fd) })
+        .collect()
+}
+
+pub fn start_watchdog() {
+    let Some(interval) = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(|usec| Duration::from_micros(usec) / 2)
+    else {
+        return;
+    };
+
+    debug!("Pinging the systemd watchdog every {interval:?}");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+// a reload re-reads the configuration, but only the alert rule sources and rules are swapped in
+pub fn start_reload_on_hangup(reloader: Arc<EnrichmentReloader>) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+            reload_rules(&reloader).await;
+            notify("READY=1");
+        }
+    });
+
+    Ok(())
+}
+
+async fn reload_rules(reloader: &EnrichmentReloader) {
+    let settings = match reloader.settings().reload() {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!(
+                "Keeping the running configuration and rules, reading the configuration failed: {e:#}"
+            );
+            return;
+        }
+    };
+    match AlertEnrichment::load_configured(&settings).await {
+        Ok(reloaded) => {
+            info!("Reloaded {} alert rules on SIGHUP", reloaded.count());
+            reloader.replace_settings(settings);
+            reloader.replace(reloaded, "SIGHUP").await;
+        }
+        Err(e) => warn!("Keeping previous alert rules, reload failed: {e:#}"),
+    }
+}
+
+fn monotonic_usec() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: clock_gettime only writes to the provided timespec
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1_000
+}
@@ -1,23 +1,133 @@
-use crate::alerts::{Alert, map_traps_to_alerts};
-use log::{error, warn};
-use sqlx::postgres::PgRow;
-use sqlx::{PgPool, Postgres, QueryBuilder};
-use std::collections::HashSet;
+use crate::alertmanager::AlertmanagerAlert;
+use crate::alerts::{
+    Alert, FIRST_TIME_COLUMN, OCCURRENCES_COLUMN, generate_alerts, map_aggregates_to_alerts,
+    map_traps_to_alerts, merge_received_alert,
+};
+use crate::audit::AuditEntry;
+use crate::bucketing::bucket_rule;
+use crate::config::{ExpiryAction, Settings};
+use crate::identity_preview::{IdentityRules, MergePreview, preview};
+use crate::leader::LeaderElection;
+use crate::reboot::{drop_cleared_reboots, is_reboot_alert};
+use crate::redis_store::RedisStore;
+use crate::row_errors::{DeadLetter, RowErrorReport, row_sample};
+use crate::snooze::{Snooze, active_snoozes};
+use crate::trap_search::{TrapSearch, TrapSearchPage};
+use itertools::Itertools;
+use log::{error, info, warn};
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgListener, PgPoolOptions, PgRow};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
+use time::ext::NumericalDuration;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
 use tokio::sync::{RwLock, RwLockReadGuard};
 use tokio::time::Instant;
+use utoipa::ToSchema;
+
+const RESOLVED_HISTORY_HOURS: i64 = 1;
+const CLEARED_HISTORY_DAYS: i64 = 7;
+const REQUIRED_COLUMNS: &[&str] = &["time", "name", "community"];
+const SOURCE_COLUMNS: &[&str] = &["source", "host"];
+const UPTIME_COLUMN: &str = "sysUpTime.0";
+pub const CACHE_TTL: Duration = Duration::from_secs(5);
+const NOTIFY_CACHE_TTL: Duration = Duration::from_secs(60);
+const NOTIFY_DEBOUNCE: Duration = Duration::from_millis(200);
+const NOTIFY_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const QUERY_TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+const DEAD_LETTER_BATCH: usize = 1000;
+// every alert binds a handful of values, this keeps a statement well below the bind limit
+const DELETE_BATCH: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearOutcome {
+    Cleared,
+    NotFound,
+    // neither stored traps nor a received copy could be matched, nothing was removed
+    Undeletable,
+}
+
+// occurrences from `after` (inclusive) up to `before` (exclusive), unbounded sides match everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClearRange {
+    pub after: Option<OffsetDateTime>,
+    pub before: Option<OffsetDateTime>,
+}
+
+impl ClearRange {
+    pub fn is_all(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    pub fn contains(&self, time: OffsetDateTime) -> bool {
+        self.after.is_none_or(|after| time >= after)
+            && self.before.is_none_or(|before| time < before)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeadLetterEntry {
+    pub hash: i64,
+    pub reason: String,
+    #[schema(value_type = Object)]
+    pub row: serde_json::Value,
+    pub first_seen: String,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct CacheStats {
+    pub alerts: usize,
+    pub received: usize,
+    pub cleared: usize,
+    pub age_sec: f64,
+    pub notify_active: bool,
+    pub shared: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ColumnStats {
+    pub name: String,
+    pub data_type: String,
+    pub null_ratio: f64,
+    pub distinct: i64,
+    // what the pipeline uses the column for, like name, time, community, dropped or label
+    pub roles: Vec<&'static str>,
+    // the Alertmanager label the column ends up as
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrapSchema {
+    pub rows: i64,
+    pub columns: Vec<ColumnStats>,
+}
 
-#[derive(Clone)]
 pub struct TrapDb {
     pool: PgPool,
     cached_alerts: Arc<RwLock<HashSet<Alert>>>,
     last_update: Arc<RwLock<Instant>>,
+    resolved_history: Arc<RwLock<Vec<OffsetDateTime>>>,
+    received_alerts: Arc<RwLock<HashSet<Alert>>>,
+    cleared_alerts: Arc<RwLock<HashMap<u64, AuditEntry>>>,
+    snoozed_alerts: Arc<RwLock<HashMap<u64, Snooze>>>,
+    notify_active: Arc<AtomicBool>,
+    row_errors: Arc<RwLock<RowErrorReport>>,
+    dead_lettered: Arc<RwLock<HashSet<i64>>>,
+    redis: Option<RedisStore>,
+    settings: Arc<Settings>,
 }
 
 impl TrapDb {
-    pub fn new(conn_url: &str) -> anyhow::Result<TrapDb> {
-        let pool = PgPool::connect_lazy(conn_url)?;
+    pub fn new(settings: Arc<Settings>) -> anyhow::Result<TrapDb> {
+        let mut options = PgConnectOptions::from_str(settings.db_url())?;
+        if let Some(timeout) = settings.db_query_timeout() {
+            options = options.options([("statement_timeout", timeout.as_millis().to_string())]);
+        }
+        let pool = PgPoolOptions::new().connect_lazy_with(options);
 
         Ok(TrapDb {
             pool,
@@ -27,11 +137,50 @@ impl TrapDb {
                     .checked_sub(Duration::from_secs(99999))
                     .expect("Instant should not overflow"),
             )),
+            resolved_history: Arc::default(),
+            received_alerts: Arc::default(),
+            cleared_alerts: Arc::default(),
+            snoozed_alerts: Arc::default(),
+            notify_active: Arc::default(),
+            row_errors: Arc::default(),
+            dead_lettered: Arc::default(),
+            redis: None,
+            settings,
         })
     }
 
+    pub fn settings(&self) -> &Arc<Settings> {
+        &self.settings
+    }
+
+    pub async fn row_errors(&self) -> RwLockReadGuard<'_, RowErrorReport> {
+        self.row_errors.read().await
+    }
+
+    pub async fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            alerts: self.cached_alerts.read().await.len(),
+            received: self.received_alerts.read().await.len(),
+            cleared: self.cleared_alerts.read().await.len(),
+            age_sec: self.last_update.read().await.elapsed().as_secs_f64(),
+            notify_active: self.notify_active.load(Ordering::Relaxed),
+            shared: self.redis.is_some(),
+        }
+    }
+
+    pub fn with_redis(mut self, redis: RedisStore) -> Self {
+        self.redis = Some(redis);
+        self
+    }
+
     pub async fn cached_alerts<'a>(&'a self) -> RwLockReadGuard<'a, HashSet<Alert>> {
-        if self.last_update.read().await.elapsed() > Duration::from_secs(5) {
+        // with notifications flowing, polling is only a fallback for missed ones
+        let ttl = if self.notify_active.load(Ordering::Relaxed) {
+            NOTIFY_CACHE_TTL
+        } else {
+            CACHE_TTL
+        };
+        if self.last_update.read().await.elapsed() > ttl {
             self.update_cache().await;
         }
 
@@ -39,75 +188,1125 @@ impl TrapDb {
     }
 
     pub async fn update_cache(&self) {
-        match self.fetch_alerts().await {
+        let fetched = match &self.redis {
+            Some(redis) => self.fetch_shared_alerts(redis).await,
+            None => {
+                match self.stored_snoozes().await {
+                    Ok(snoozed) => *self.snoozed_alerts.write().await = snoozed,
+                    Err(e) => warn!("Failed to load snoozed alerts: {e}"),
+                }
+                self.fetch_alerts().await
+            }
+        };
+        match fetched {
             Err(e) => error!("Error fetching alerts: {}", e),
             Ok(alerts) => {
-                *self.cached_alerts.write().await = alerts;
+                let cleared = self.cleared_alerts.read().await;
+                let mut cached_alerts = self.cached_alerts.write().await;
+                // cleared alerts are gone as well, but they were archived when they were cleared
+                let resolved: Vec<Alert> = cached_alerts
+                    .difference(&alerts)
+                    .filter(|a| {
+                        cleared
+                            .get(&a.hash())
+                            .is_none_or(|entry| entry.time < a.latest())
+                    })
+                    .cloned()
+                    .collect();
+                *cached_alerts = alerts;
+                drop(cached_alerts);
+                drop(cleared);
+
+                self.record_resolved(resolved.len()).await;
+                if let Some(archive) = self.settings.archive() {
+                    archive.archive_logged("resolved", resolved);
+                }
                 *self.last_update.write().await = Instant::now();
             }
         }
     }
 
-    pub async fn fetch_raw_traps(&self) -> anyhow::Result<Vec<PgRow>> {
-        let traps = sqlx::query(
+    // replicas share one snapshot so the database is only queried once per TTL
+    async fn fetch_shared_alerts(&self, redis: &RedisStore) -> anyhow::Result<HashSet<Alert>> {
+        match redis.cleared_alerts().await {
+            Ok(cleared) => *self.cleared_alerts.write().await = cleared,
+            Err(e) => warn!("Failed to load cleared alerts from Redis: {e}"),
+        }
+        match redis.snoozed_alerts().await {
+            Ok(snoozed) => *self.snoozed_alerts.write().await = snoozed,
+            Err(e) => warn!("Failed to load snoozed alerts from Redis: {e}"),
+        }
+
+        match redis.cached_alerts().await {
+            Ok(Some(alerts)) => return Ok(alerts),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to load alert cache from Redis: {e}"),
+        }
+
+        let alerts = self.fetch_alerts().await?;
+        if let Err(e) = redis.store_cached_alerts(&alerts, CACHE_TTL).await {
+            warn!("Failed to store alert cache in Redis: {e}");
+        }
+        Ok(alerts)
+    }
+
+    async fn record_resolved(&self, amount: usize) {
+        let now = OffsetDateTime::now_utc();
+        let mut history = self.resolved_history.write().await;
+        history.retain(|t| now - *t < RESOLVED_HISTORY_HOURS.hours());
+        history.extend(std::iter::repeat_n(now, amount));
+    }
+
+    pub async fn resolved_since(&self, since: OffsetDateTime) -> usize {
+        self.resolved_history
+            .read()
+            .await
+            .iter()
+            .filter(|t| **t >= since)
+            .count()
+    }
+
+    pub async fn trap_columns(&self) -> anyhow::Result<Vec<String>> {
+        let columns = self
+            .bounded(
+                sqlx::query_scalar(
+                    r#"
+        SELECT column_name::text FROM information_schema.columns WHERE table_name = 'snmp_trap' ORDER BY ordinal_position
+    "#,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+
+        Ok(columns)
+    }
+
+    pub async fn check_schema(&self) -> anyhow::Result<()> {
+        let columns = self.trap_columns().await?;
+
+        if columns.is_empty() {
+            error!(
+                "Table \"snmp_trap\" not found or has no columns. Is the trap receiver writing to this database?"
+            );
+            return Ok(());
+        }
+
+        for required in REQUIRED_COLUMNS {
+            if *required == "community" && self.settings.community_fallback().is_configured() {
+                continue;
+            }
+            if !columns.iter().any(|c| c == required) {
+                error!(
+                    "Table \"snmp_trap\" is missing the required column {required:?}. Rows will be skipped as invalid until it is added."
+                );
+            }
+        }
+
+        for column in &columns {
+            if !REQUIRED_COLUMNS.contains(&column.as_str())
+                && AlertmanagerAlert::is_restricted_label(&self.settings, column)
+            {
+                warn!(
+                    "Column {column:?} collides with a reserved Alertmanager label and will be exposed as {:?}",
+                    AlertmanagerAlert::collision_safe_label(&self.settings, column)
+                );
+            }
+        }
+
+        info!("Trap table schema checked ({} columns)", columns.len());
+
+        if self.settings.dead_letter() {
+            self.install_dead_letter_table().await?;
+        }
+        // Redis holds the snoozes otherwise
+        if self.redis.is_none() {
+            self.install_snooze_table().await?;
+        }
+        Ok(())
+    }
+
+    // one scan counts the whole table, so this is an onboarding tool rather than something to poll
+    pub async fn schema_report(&self) -> anyhow::Result<TrapSchema> {
+        let types: Vec<(String, String)> = self
+            .bounded(
+                sqlx::query_as(
+                    r#"
+        SELECT column_name::text, data_type::text FROM information_schema.columns
+        WHERE table_name = 'snmp_trap' ORDER BY ordinal_position
+    "#,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        if types.is_empty() {
+            anyhow::bail!("table \"snmp_trap\" not found or has no columns");
+        }
+
+        let counts = types
+            .iter()
+            .map(|(column, _)| {
+                let quoted = quote_identifier(column);
+                format!("count({quoted}), count(DISTINCT {quoted}::text)")
+            })
+            .join(", ");
+        let row = self
+            .bounded(
+                sqlx::query(&format!(r#"SELECT count(*), {counts} FROM "snmp_trap""#))
+                    .fetch_one(&self.pool),
+            )
+            .await?;
+
+        let rows: i64 = row.try_get(0)?;
+        let mut stats = Vec::with_capacity(types.len());
+        for (i, (name, data_type)) in types.into_iter().enumerate() {
+            let non_null: i64 = row.try_get(1 + i * 2)?;
+            let distinct: i64 = row.try_get(2 + i * 2)?;
+            let roles = column_roles(&self.settings, &name);
+            let label = roles.contains(&"label").then(|| {
+                AlertmanagerAlert::collision_safe_label(
+                    &self.settings,
+                    self.settings.mapped_label(&name),
+                )
+            });
+            stats.push(ColumnStats {
+                null_ratio: match rows {
+                    0 => 0.0,
+                    rows => (rows - non_null) as f64 / rows as f64,
+                },
+                name,
+                data_type,
+                distinct,
+                roles,
+                label,
+            });
+        }
+
+        Ok(TrapSchema {
+            rows,
+            columns: stats,
+        })
+    }
+
+    async fn install_dead_letter_table(&self) -> anyhow::Result<()> {
+        sqlx::query(
             r#"
-        SELECT * FROM "snmp_trap"
+        CREATE TABLE IF NOT EXISTS "snmp_trap_dead_letter" (
+            row_hash BIGINT PRIMARY KEY,
+            reason TEXT NOT NULL,
+            row_data JSONB NOT NULL,
+            first_seen TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
     "#,
         )
-        .fetch_all(&self.pool)
+        .execute(&self.pool)
         .await?;
 
+        info!("Dead-lettering invalid trap rows into \"snmp_trap_dead_letter\"");
+        Ok(())
+    }
+
+    // invalid rows stay in the trap table, so every cycle sees them again. Only new ones are written
+    async fn store_dead_letters(&self, letters: Vec<DeadLetter>) -> anyhow::Result<()> {
+        let known = self.dead_lettered.read().await.clone();
+        let new = letters
+            .into_iter()
+            .filter(|l| !known.contains(&l.hash))
+            .unique_by(|l| l.hash)
+            .collect_vec();
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        for chunk in new.chunks(DEAD_LETTER_BATCH) {
+            let mut query = QueryBuilder::<Postgres>::new(
+                r#"INSERT INTO "snmp_trap_dead_letter" (row_hash, reason, row_data) "#,
+            );
+            query.push_values(chunk, |mut row, letter| {
+                row.push_bind(letter.hash)
+                    .push_bind(letter.reason.clone())
+                    .push_bind(serde_json::to_string(&letter.row).unwrap_or_default())
+                    .push_unseparated("::jsonb");
+            });
+            query.push(" ON CONFLICT (row_hash) DO NOTHING");
+            self.bounded(query.build().execute(&self.pool)).await?;
+        }
+
+        info!("Dead-lettered {} new invalid trap rows", new.len());
+        self.dead_lettered
+            .write()
+            .await
+            .extend(new.iter().map(|l| l.hash));
+        Ok(())
+    }
+
+    pub async fn dead_letters(&self, limit: i64) -> anyhow::Result<Vec<DeadLetterEntry>> {
+        let rows: Vec<(i64, String, String, OffsetDateTime)> = self
+            .bounded(
+                sqlx::query_as(
+                    r#"
+        SELECT row_hash, reason, row_data::text, first_seen FROM "snmp_trap_dead_letter"
+        ORDER BY first_seen DESC LIMIT $1
+    "#,
+                )
+                .bind(limit)
+                .fetch_all(&self.pool),
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(hash, reason, row, first_seen)| DeadLetterEntry {
+                hash,
+                reason,
+                row: serde_json::from_str(&row).unwrap_or_default(),
+                first_seen: first_seen.to_string(),
+            })
+            .collect())
+    }
+
+    pub async fn install_notify_trigger(&self, channel: &str) -> anyhow::Result<()> {
+        let function = format!(
+            r#"
+        CREATE OR REPLACE FUNCTION snmp_trap_notify() RETURNS trigger AS $$
+        BEGIN
+            PERFORM pg_notify('{channel}', NEW.name);
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql
+    "#
+        );
+        sqlx::query(&function).execute(&self.pool).await?;
+        sqlx::query(r#"DROP TRIGGER IF EXISTS snmp_trap_notify ON "snmp_trap""#)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#"
+        CREATE TRIGGER snmp_trap_notify AFTER INSERT ON "snmp_trap"
+        FOR EACH ROW EXECUTE FUNCTION snmp_trap_notify()
+    "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        info!("Installed insert notification trigger on \"snmp_trap\" for channel {channel:?}");
+        Ok(())
+    }
+
+    pub async fn run_notify_listener_blocking(&self, channel: &str) {
+        loop {
+            if let Err(e) = self.listen_for_notifications(channel).await {
+                warn!("Lost Postgres notification listener on {channel:?}: {e}");
+            }
+            self.notify_active.store(false, Ordering::Relaxed);
+            tokio::time::sleep(NOTIFY_RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn listen_for_notifications(&self, channel: &str) -> anyhow::Result<()> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(channel).await?;
+        self.notify_active.store(true, Ordering::Relaxed);
+        info!("Listening for trap notifications on channel {channel:?}");
+
+        // notifications received while the connection was down are lost
+        self.update_cache().await;
+
+        loop {
+            listener.recv().await?;
+            // inserts arrive in bursts, collapse them into a single refresh
+            while let Ok(notification) =
+                tokio::time::timeout(NOTIFY_DEBOUNCE, listener.recv()).await
+            {
+                notification?;
+            }
+            self.update_cache().await;
+        }
+    }
+
+    async fn notify_peers(&self) {
+        let Some(channel) = self.settings.notify_channel() else {
+            return;
+        };
+        if let Err(e) = sqlx::query("SELECT pg_notify($1, '')")
+            .bind(channel)
+            .execute(&self.pool)
+            .await
+        {
+            warn!("Failed to notify other instances on {channel:?}: {e}");
+        }
+    }
+
+    pub async fn fetch_raw_traps(&self) -> anyhow::Result<Vec<PgRow>> {
+        let traps = self
+            .bounded(
+                sqlx::query(
+                    r#"
+        SELECT * FROM "snmp_trap"
+    "#,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+
         Ok(traps)
     }
 
+    // the raw table keeps traps that never became noteworthy, the newest come first
+    pub async fn search_traps(
+        &self,
+        search: &TrapSearch,
+        columns: &[String],
+    ) -> anyhow::Result<TrapSearchPage> {
+        let mut query = make_search_query(&self.settings, search, columns);
+        let rows = self.bounded(query.build().fetch_all(&self.pool)).await?;
+
+        Ok(TrapSearchPage::new(
+            rows.iter().map(row_sample).collect(),
+            search,
+        ))
+    }
+
+    // groups identical traps in the database so only one row per alert is transferred
+    async fn fetch_aggregated_traps(&self) -> anyhow::Result<(Vec<PgRow>, Vec<PgRow>)> {
+        let columns = self.trap_columns().await?;
+        let aggregates = self
+            .bounded(
+                sqlx::query(&make_aggregation_query(&self.settings, &columns))
+                    .fetch_all(&self.pool),
+            )
+            .await?;
+
+        // uptimes have to be compared occurrence by occurrence
+        let uptimes = match make_uptime_query(&self.settings, &columns) {
+            Some(query) if self.settings.reboot_detection() => {
+                self.bounded(sqlx::query(&query).fetch_all(&self.pool))
+                    .await?
+            }
+            _ => Vec::new(),
+        };
+
+        Ok((aggregates, uptimes))
+    }
+
+    // the same rows the alerts are made of, mapped with the proposed rules next to the current ones
+    pub async fn preview_identity(&self, rules: &IdentityRules) -> anyhow::Result<MergePreview> {
+        let proposed = rules.apply(&self.settings);
+        Ok(if self.settings.sql_aggregation() {
+            let (aggregates, _) = self.fetch_aggregated_traps().await?;
+            preview(
+                &aggregates,
+                Alert::from_aggregate_row,
+                &self.settings,
+                &proposed,
+            )
+        } else {
+            let traps = self.fetch_raw_traps().await?;
+            preview(&traps, Alert::from_row, &self.settings, &proposed)
+        })
+    }
+
     pub async fn fetch_alerts(&self) -> anyhow::Result<HashSet<Alert>> {
-        let traps = self.fetch_raw_traps().await?;
-        Ok(map_traps_to_alerts(&traps))
+        let mut errors = RowErrorReport::new();
+        let alerts = if self.settings.sql_aggregation() {
+            let (aggregates, uptimes) = self.fetch_aggregated_traps().await?;
+            map_aggregates_to_alerts(&aggregates, &uptimes, &self.settings, &mut errors)
+        } else {
+            let traps = self.fetch_raw_traps().await?;
+            map_traps_to_alerts(&traps, &self.settings, &mut errors)
+        };
+        errors.log_summary();
+        let dead_letters = std::mem::take(&mut errors.dead_letters);
+        *self.row_errors.write().await = errors;
+        if self.settings.dead_letter()
+            && let Err(e) = self.store_dead_letters(dead_letters).await
+        {
+            warn!("Failed to dead-letter invalid trap rows: {e}");
+        }
+        let received = self.received_alerts().await;
+        let alerts = generate_alerts(alerts.into_iter().chain(received));
+        let mut alerts =
+            drop_cleared_reboots(&self.settings, alerts, &*self.cleared_alerts.read().await);
+
+        if let Some(expiry) = self.settings.alert_expiry()
+            && expiry.action == ExpiryAction::Hide
+        {
+            alerts.retain(|a| !expiry.is_expired(a));
+        }
+
+        Ok(alerts)
+    }
+
+    pub async fn run_expiry_blocking(&self, leader: &LeaderElection) {
+        let mut interval = tokio::time::interval(
+            self.settings
+                .alertmanager_announce_duration()
+                .unsigned_abs(),
+        );
+        loop {
+            interval.tick().await;
+            if leader.is_leader() {
+                self.clear_expired_alerts().await;
+            }
+        }
     }
 
-    pub async fn clear_alerts(&self, hash: u64) -> anyhow::Result<()> {
-        let alerts = self.cached_alerts().await.clone();
+    pub async fn clear_expired_alerts(&self) {
+        let Some(expiry) = self.settings.alert_expiry() else {
+            return;
+        };
+        if expiry.action != ExpiryAction::Clear {
+            return;
+        }
 
+        let expired = self
+            .cached_alerts()
+            .await
+            .iter()
+            .filter(|a| expiry.is_expired(a))
+            .cloned()
+            .collect_vec();
+        if expired.is_empty() {
+            return;
+        }
+
+        // the whole sweep is deleted together and refreshes the cache once
+        let deleted = match self.delete_alerts(&expired, ClearRange::default()).await {
+            Ok(deleted) => deleted,
+            Err(e) => {
+                warn!("Failed to clear {} expired alerts: {e}", expired.len());
+                return;
+            }
+        };
+        let reason = format!("no occurrence for more than {}s", expiry.max_age_sec);
+        for alert in &deleted {
+            self.record_clear(alert, ClearRange::default(), &reason, "alert expiry")
+                .await;
+        }
+        self.update_cache().await;
+        self.notify_peers().await;
+    }
+
+    async fn received_alerts(&self) -> HashSet<Alert> {
+        if let Some(redis) = &self.redis {
+            match redis.received_alerts().await {
+                Ok(received) => return received,
+                Err(e) => warn!("Failed to load received alerts from Redis: {e}"),
+            }
+        }
+        self.received_alerts.read().await.clone()
+    }
+
+    pub async fn ingest(&self, alert: Alert) {
+        if let Some(redis) = &self.redis {
+            if let Err(e) = redis.ingest(alert.clone()).await {
+                warn!("Failed to store received alert in Redis: {e}");
+            }
+            self.invalidate_shared_cache(redis).await;
+        }
+        merge_received_alert(&mut *self.received_alerts.write().await, alert.clone());
+        merge_received_alert(&mut *self.cached_alerts.write().await, alert);
+    }
+
+    async fn invalidate_shared_cache(&self, redis: &RedisStore) {
+        if let Err(e) = redis.invalidate_cache().await {
+            warn!("Failed to invalidate alert cache in Redis: {e}");
+        }
+    }
+
+    pub async fn clear_alerts(
+        &self,
+        hash: u64,
+        range: ClearRange,
+        reason: &str,
+        actor: &str,
+    ) -> anyhow::Result<ClearOutcome> {
+        let alerts = self.cached_alerts().await;
+        let Some(alert) = alerts.iter().find(|a| a.hash() == hash).cloned() else {
+            warn!("Alert lookup by hash supplied no results. Already deleted?");
+            return Ok(ClearOutcome::NotFound);
+        };
+        drop(alerts);
+
+        let deleted = self
+            .delete_alerts(std::slice::from_ref(&alert), range)
+            .await?;
+        if deleted.is_empty() {
+            return Ok(ClearOutcome::Undeletable);
+        }
+        self.record_clear(&alert, range, reason, actor).await;
+        self.update_cache().await;
+        self.notify_peers().await;
+
+        Ok(ClearOutcome::Cleared)
+    }
+
+    // only what was actually deleted is recorded
+    async fn record_clear(&self, alert: &Alert, range: ClearRange, reason: &str, actor: &str) {
+        let action = if range.is_all() {
+            "clear"
+        } else {
+            "clear_range"
+        };
+        let hash = alert.hash();
+        let entry = AuditEntry::new(action, hash, alert.raw_name(), reason, actor)
+            .with_pipeline(self.settings.pipeline());
+        entry.record(&self.settings).await;
+
+        // occurrences outside the range keep the alert going, it didn't come back after a clear
+        if alert.times().iter().any(|t| !range.contains(*t)) {
+            return;
+        }
+
+        let mut cleared = self.cleared_alerts.write().await;
+        let expired = cleared
+            .iter()
+            .filter(|(_, e)| entry.time - e.time >= CLEARED_HISTORY_DAYS.days())
+            .map(|(hash, _)| *hash)
+            .collect_vec();
+        for hash in &expired {
+            cleared.remove(hash);
+        }
+        cleared.insert(hash, entry.clone());
+        drop(cleared);
+        // other replicas store theirs at the same time, so only this entry is written
+        if let Some(redis) = &self.redis
+            && let Err(e) = redis.store_cleared(hash, &entry, &expired).await
+        {
+            warn!("Failed to store cleared alerts in Redis: {e}");
+        }
+    }
+
+    pub async fn snooze_alert(
+        &self,
+        hash: u64,
+        duration: time::Duration,
+        reason: &str,
+        actor: &str,
+    ) -> anyhow::Result<()> {
+        let alerts = self.cached_alerts().await;
         let Some(alert) = alerts.iter().find(|a| a.hash() == hash) else {
             warn!("Alert lookup by hash supplied no results. Already deleted?");
             return Ok(());
         };
 
-        self.delete_alert(alert).await?;
-        self.update_cache().await;
+        let reason = match reason.is_empty() {
+            true => format!("snoozed for {duration}"),
+            false => reason.to_string(),
+        };
+        let entry = AuditEntry::new("snooze", hash, alert.raw_name(), reason, actor)
+            .with_pipeline(self.settings.pipeline());
+        drop(alerts);
+        let snooze = Snooze::new(duration, entry);
+
+        // standby replicas only know about the snooze through the shared state
+        let mut snoozed = self.snoozed_alerts.write().await;
+        let expired = snoozed
+            .iter()
+            .filter(|(_, s)| !s.is_active(snooze.entry.time))
+            .map(|(hash, _)| *hash)
+            .collect_vec();
+        match &self.redis {
+            Some(redis) => redis.store_snoozed(hash, &snooze, &expired).await?,
+            None => self.store_snooze(hash, &snooze).await?,
+        }
+        for hash in &expired {
+            snoozed.remove(hash);
+        }
+        snoozed.insert(hash, snooze.clone());
+        drop(snoozed);
+        snooze.entry.record(&self.settings).await;
+
+        self.notify_peers().await;
 
         Ok(())
     }
 
-    pub async fn delete_alert(&self, alert: &Alert) -> anyhow::Result<()> {
-        make_label_query(alert).build().execute(&self.pool).await?;
+    async fn install_snooze_table(&self) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+        CREATE TABLE IF NOT EXISTS "snmp_trap_snooze" (
+            alert_hash BIGINT PRIMARY KEY,
+            until TIMESTAMPTZ NOT NULL,
+            snooze JSONB NOT NULL
+        )
+    "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
+        info!("Storing snoozes in \"snmp_trap_snooze\"");
+        Ok(())
+    }
+
+    async fn store_snooze(&self, hash: u64, snooze: &Snooze) -> anyhow::Result<()> {
+        self.bounded(
+            sqlx::query(
+                r#"
+        INSERT INTO "snmp_trap_snooze" (alert_hash, until, snooze) VALUES ($1, $2, $3::jsonb)
+        ON CONFLICT (alert_hash) DO UPDATE SET until = EXCLUDED.until, snooze = EXCLUDED.snooze
+    "#,
+            )
+            .bind(hash as i64)
+            .bind(snooze.until)
+            .bind(serde_json::to_string(snooze)?)
+            .execute(&self.pool),
+        )
+        .await?;
+        self.bounded(
+            sqlx::query(r#"DELETE FROM "snmp_trap_snooze" WHERE until <= now()"#)
+                .execute(&self.pool),
+        )
+        .await?;
         Ok(())
     }
-}
 
-fn make_label_query(alert: &'_ Alert) -> QueryBuilder<'_, Postgres> {
-    let mut builder = QueryBuilder::new("DELETE FROM snmp_trap WHERE name = ");
+    async fn stored_snoozes(&self) -> anyhow::Result<HashMap<u64, Snooze>> {
+        let rows: Vec<(i64, String)> = self
+            .bounded(
+                sqlx::query_as(
+                    r#"SELECT alert_hash, snooze::text FROM "snmp_trap_snooze" WHERE until > now()"#,
+                )
+                .fetch_all(&self.pool),
+            )
+            .await?;
+        rows.into_iter()
+            .map(|(hash, snooze)| Ok((hash as u64, serde_json::from_str(&snooze)?)))
+            .collect()
+    }
 
-    builder.push_bind(alert.raw_name());
-    builder.push(r#" AND community = "#);
-    builder.push_bind(alert.community());
+    // expired snoozes are left in place until the next one is stored, they simply stop matching
+    pub async fn snoozed_alerts(&self) -> HashMap<u64, Snooze> {
+        active_snoozes(&*self.snoozed_alerts.read().await)
+    }
 
-    for label in alert.raw_labels().iter() {
-        if label.0.contains('"') {
-            error!(
-                "Label {:?} contains unquoted string in alert {}. Since the label key is used as the database field, this shouldn't happen. Skipping.",
-                label.0,
+    pub async fn cleared_alerts<'a>(&'a self) -> RwLockReadGuard<'a, HashMap<u64, AuditEntry>> {
+        self.cleared_alerts.read().await
+    }
+
+    // alerts without stored traps and without a received copy can't be deleted and are left out,
+    // the returned ones are gone
+    pub async fn delete_alerts(
+        &self,
+        alerts: &[Alert],
+        range: ClearRange,
+    ) -> anyhow::Result<Vec<Alert>> {
+        let received = self.received_alerts().await;
+        let columns = self.trap_columns().await?;
+        let (deletable, undeletable): (Vec<&Alert>, Vec<&Alert>) =
+            alerts.iter().partition(|alert| {
+                received.contains(*alert)
+                    || is_cleared_reboot(&self.settings, alert, range)
+                    || has_label_columns(&self.settings, alert, &columns)
+            });
+        for alert in undeletable {
+            warn!(
+                "Alert {} has labels without a trap table column and no received copy, nothing to delete",
                 alert.raw_name()
             );
+        }
+        if deletable.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // the rows go first, a failing statement leaves the received copies untouched
+        let with_rows = deletable
+            .iter()
+            .copied()
+            .filter(|alert| {
+                !is_reboot_alert(&self.settings, alert)
+                    && has_label_columns(&self.settings, alert, &columns)
+            })
+            .collect_vec();
+        for chunk in with_rows.chunks(DELETE_BATCH) {
+            if let Some(mut query) = make_delete_query(&self.settings, chunk, &columns, range) {
+                self.bounded(query.build().execute(&self.pool)).await?;
+            }
+        }
+
+        for alert in &deletable {
+            let remaining = received
+                .get(*alert)
+                .cloned()
+                .and_then(|a| a.retain_times(|t| !range.contains(t)));
+            self.received_alerts.write().await.remove(*alert);
+            if let Some(redis) = &self.redis
+                && let Err(e) = redis.remove_received(alert).await
+            {
+                warn!("Failed to remove received alert from Redis: {e}");
+            }
+            if let Some(remaining) = remaining {
+                self.ingest(remaining).await;
+            }
+        }
+        // before the rows are gone, another replica could cache them again right away
+        if let Some(redis) = &self.redis {
+            self.invalidate_shared_cache(redis).await;
+        }
+
+        let deleted = deletable.into_iter().cloned().collect_vec();
+        if let Some(archive) = self.settings.archive() {
+            archive.archive_logged("cleared", deleted.clone());
+        }
+        Ok(deleted)
+    }
+
+    // statement_timeout stops the query on the server, dropping the future frees the caller even
+    // when the server or the network doesn't answer at all
+    async fn bounded<T>(
+        &self,
+        query: impl Future<Output = Result<T, sqlx::Error>>,
+    ) -> anyhow::Result<T> {
+        let Some(timeout) = self.settings.db_query_timeout() else {
+            return Ok(query.await?);
+        };
+
+        match tokio::time::timeout(timeout + QUERY_TIMEOUT_GRACE, query).await {
+            Ok(result) => Ok(result?),
+            Err(_) => anyhow::bail!("database query timed out after {timeout:?}"),
+        }
+    }
+}
+
+fn make_aggregation_query(settings: &Settings, columns: &[String]) -> String {
+    let mut select = Vec::new();
+    let mut group = Vec::new();
+    for column in columns {
+        let quoted = quote_identifier(column);
+        if column == "time" {
+            continue;
+        } else if SOURCE_COLUMNS.contains(&column.as_str()) {
+            // the sender port changes with every trap, only the address identifies the agent
+            select.push(format!("min({quoted}) AS {quoted}"));
+            if settings.instance_label().is_some() {
+                group.push(format!("split_part({quoted}, ']:', 1)"));
+            }
+        } else if settings.drop_columns().contains(column) && !is_fallback_column(settings, column)
+        {
             continue;
+        } else {
+            select.push(quoted.clone());
+            group.push(quoted);
         }
+    }
+
+    format!(
+        r#"SELECT {}, min("time") AS "{FIRST_TIME_COLUMN}", max("time") AS "time", count(*) AS "{OCCURRENCES_COLUMN}" FROM "snmp_trap" GROUP BY {}"#,
+        select.join(", "),
+        group.join(", "),
+    )
+}
+
+fn make_uptime_query(settings: &Settings, columns: &[String]) -> Option<String> {
+    if !columns.iter().any(|c| c == UPTIME_COLUMN) {
+        return None;
+    }
+
+    let selected = ["time", UPTIME_COLUMN]
+        .into_iter()
+        .chain(SOURCE_COLUMNS.iter().copied())
+        .filter(|c| columns.iter().any(|column| column == c))
+        .map(|c| format!(r#""{c}""#))
+        .chain([format!(
+            r#"{} AS "community""#,
+            community_expression(settings, columns)
+        )])
+        .join(", ");
+    Some(format!(
+        r#"SELECT {selected} FROM "snmp_trap" WHERE "{UPTIME_COLUMN}" IS NOT NULL"#
+    ))
+}
+
+fn make_search_query<'a>(
+    settings: &Settings,
+    search: &'a TrapSearch,
+    columns: &[String],
+) -> QueryBuilder<'a, Postgres> {
+    let mut builder = QueryBuilder::new(r#"SELECT * FROM "snmp_trap" AS t WHERE TRUE"#);
+
+    if let Some(from) = search.from {
+        builder.push(r#" AND "time" >= "#);
+        builder.push_bind(utc_timestamp(from));
+    }
+    if let Some(to) = search.to {
+        builder.push(r#" AND "time" < "#);
+        builder.push_bind(utc_timestamp(to));
+    }
+    if let Some(community) = &search.community {
+        builder.push(" AND ");
+        builder.push(community_expression(settings, columns));
+        builder.push(" = ");
+        builder.push_bind(community);
+    }
+    if let Some(oid) = &search.oid {
+        builder.push(" AND ");
+        builder.push(name_expression(settings, columns));
+        builder.push(" = ");
+        builder.push_bind(oid);
+    }
 
-        builder.push(r#" AND ""#);
-        builder.push(label.0);
-        builder.push(r#"" = "#);
-        builder.push_bind(label.1);
+    // the text of the whole row covers every column at once
+    let target = match &search.column {
+        Some(column) => format!("{}::text", quote_identifier(column)),
+        None => "t::text".to_string(),
+    };
+    if let Some(like) = &search.like {
+        builder.push(format!(" AND {target} ILIKE "));
+        builder.push_bind(like);
+    }
+    if let Some(regex) = &search.regex {
+        builder.push(format!(" AND {target} ~ "));
+        builder.push_bind(regex);
     }
 
+    builder.push(r#" ORDER BY "time" DESC LIMIT "#);
+    builder.push_bind(search.limit() + 1);
+    builder.push(" OFFSET ");
+    builder.push_bind(search.offset());
     builder
 }
+
+// the clear is recorded and hides the covered resets, a part of them would just come back
+fn is_cleared_reboot(settings: &Settings, alert: &Alert, range: ClearRange) -> bool {
+    is_reboot_alert(settings, alert) && alert.times().iter().all(|t| range.contains(*t))
+}
+
+// a label without a matching column can't stem from the trap table, so there is nothing to delete
+fn has_label_columns(settings: &Settings, alert: &Alert, columns: &[String]) -> bool {
+    alert
+        .raw_labels()
+        .keys()
+        .filter_map(|label| label_column(settings, label))
+        .all(|column| columns.iter().any(|c| c == column))
+}
+
+// one statement for many alerts, the rows of each are matched by their own conditions
+fn make_delete_query<'a>(
+    settings: &Settings,
+    alerts: &[&'a Alert],
+    columns: &[String],
+    range: ClearRange,
+) -> Option<QueryBuilder<'a, Postgres>> {
+    if alerts.is_empty() {
+        return None;
+    }
+
+    let mut builder = QueryBuilder::new("DELETE FROM snmp_trap WHERE ");
+    for (i, alert) in alerts.iter().enumerate() {
+        if i > 0 {
+            builder.push(" OR ");
+        }
+        builder.push("(");
+        push_alert_conditions(&mut builder, settings, alert, columns, range);
+        builder.push(")");
+    }
+    Some(builder)
+}
+
+fn push_alert_conditions<'a>(
+    builder: &mut QueryBuilder<'a, Postgres>,
+    settings: &Settings,
+    alert: &'a Alert,
+    columns: &[String],
+    range: ClearRange,
+) {
+    builder.push(name_expression(settings, columns));
+    builder.push(" = ");
+    builder.push_bind(alert.raw_name());
+    builder.push(" AND ");
+    builder.push(community_expression(settings, columns));
+    builder.push(" = ");
+    builder.push_bind(alert.community());
+
+    // trap times are stored without a time zone in UTC
+    if let Some(after) = range.after {
+        builder.push(r#" AND "time" >= "#);
+        builder.push_bind(utc_timestamp(after));
+    }
+    if let Some(before) = range.before {
+        builder.push(r#" AND "time" < "#);
+        builder.push_bind(utc_timestamp(before));
+    }
+
+    for (label, value) in alert.raw_labels().iter() {
+        let Some(column) = label_column(settings, label) else {
+            continue;
+        };
+
+        // a bucketed value matches every number of its bucket
+        if let Some((lower, upper)) =
+            bucket_rule(settings.label_buckets(), label).and_then(|rule| rule.range_of(value))
+        {
+            let number = numeric_expression(column);
+            if let Some(lower) = lower {
+                builder.push(format!(" AND {number} >= "));
+                builder.push_bind(lower);
+            }
+            if let Some(upper) = upper {
+                builder.push(format!(" AND {number} < "));
+                builder.push_bind(upper);
+            }
+            continue;
+        }
+
+        builder.push(" AND ");
+        builder.push(quote_identifier(column));
+        builder.push(" = ");
+        builder.push_bind(value);
+    }
+}
+
+// matches how the name was picked when mapping the row, including the fallback columns
+fn name_expression(settings: &Settings, columns: &[String]) -> String {
+    coalesce_expression("name", settings.name_fallback_columns(), None, columns)
+}
+
+fn community_expression(settings: &Settings, columns: &[String]) -> String {
+    let fallback = settings.community_fallback();
+    coalesce_expression("community", fallback.columns(), fallback.value(), columns)
+}
+
+fn coalesce_expression(
+    column: &str,
+    fallbacks: &[String],
+    value: Option<&str>,
+    columns: &[String],
+) -> String {
+    if fallbacks.is_empty() && value.is_none() {
+        return quote_identifier(column);
+    }
+
+    let candidates = std::iter::once(column)
+        .chain(fallbacks.iter().map(String::as_str))
+        .filter(|c| columns.iter().any(|column| column == c))
+        .map(|c| format!("NULLIF({}::text, '')", quote_identifier(c)))
+        .chain(value.map(quote_literal))
+        .join(", ");
+    if candidates.is_empty() {
+        return "NULL".to_string();
+    }
+
+    format!("COALESCE({candidates})")
+}
+
+// mirrors how rows are mapped to alerts in `Alert::from_row`
+fn column_roles(settings: &Settings, column: &str) -> Vec<&'static str> {
+    let column_name = column.to_string();
+    let mut roles = Vec::new();
+    match column {
+        "time" => roles.push("time"),
+        "name" => roles.push("name"),
+        "community" => roles.push("community"),
+        _ => {}
+    }
+    if settings.name_fallback_columns().contains(&column_name) {
+        roles.push("name_fallback");
+    }
+    if settings
+        .community_fallback()
+        .columns()
+        .contains(&column_name)
+    {
+        roles.push("community_fallback");
+    }
+    if SOURCE_COLUMNS.contains(&column) {
+        roles.push("source");
+    }
+    if column == UPTIME_COLUMN && settings.reboot_detection() {
+        roles.push("uptime");
+    }
+    if settings.drop_columns().contains(&column_name) {
+        roles.push("dropped");
+    } else if !REQUIRED_COLUMNS.contains(&column) {
+        roles.push("label");
+    }
+    roles
+}
+
+fn is_fallback_column(settings: &Settings, column: &String) -> bool {
+    settings.name_fallback_columns().contains(column)
+        || settings.community_fallback().columns().contains(column)
+}
+
+fn utc_timestamp(time: OffsetDateTime) -> PrimitiveDateTime {
+    let time = time.to_offset(UtcOffset::UTC);
+    PrimitiveDateTime::new(time.date(), time.time())
+}
+
+fn quote_identifier(column: &str) -> String {
+    format!(r#""{}""#, column.replace('"', r#""""#))
+}
+
+// NULL for values that aren't numbers, a failing cast would abort the whole query
+fn numeric_expression(column: &str) -> String {
+    let text = format!("trim({}::text)", quote_identifier(column));
+    format!(
+        r"(CASE WHEN {text} ~ '^[-+]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][-+]?[0-9]+)?$' THEN {text}::double precision END)"
+    )
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// labels added by us have no column to match on, renamed ones map back to their original column
+fn label_column<'a>(settings: &'a Settings, label: &'a str) -> Option<&'a str> {
+    if Some(label) == settings.instance_label() || label == settings.source_filter().tag_label() {
+        return None;
+    }
+
+    let label = match label.strip_prefix(settings.restricted_label_prefix()) {
+        Some(column) if AlertmanagerAlert::is_restricted_label(settings, column) => column,
+        _ => label,
+    };
+    Some(settings.mapped_column(label))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alerts::Alert;
+    use crate::config::{Settings, test_settings};
+    use crate::trap_db::{ClearRange, has_label_columns, make_delete_query};
+    use std::collections::BTreeMap;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn clear_query_maps_renamed_labels_to_their_column() {
+        let settings = test_settings("");
+        // a column named like a reserved label is exposed with the prefix
+        let alert = Alert::from_occurrence(
+            "linkDown".to_string(),
+            "public".to_string(),
+            OffsetDateTime::now_utc(),
+            BTreeMap::from([("trap_alertname".to_string(), "ifDown".to_string())]),
+        );
+        let columns = ["time", "name", "community", "alertname"].map(String::from);
+
+        assert!(has_label_columns(&settings, &alert, &columns));
+        let query = make_delete_query(&settings, &[&alert], &columns, ClearRange::default())
+            .expect("an alert was given");
+        assert!(query.sql().ends_with(r#" AND "alertname" = $3)"#));
+    }
+
+    #[test]
+    fn zero_announce_interval_is_rejected() {
+        let settings = |extra: &str| {
+            Settings::from_yaml(&format!(
+                "web_url: http://localhost:7788\n\
+                 db_connection_url: postgres://localhost/snmp\n\
+                 alertmanager_url: http://localhost:9093\n\
+                 {extra}"
+            ))
+        };
+
+        assert!(settings("alertmanager_announce_sec: 0").is_err());
+        assert!(settings("alertmanager_announce_sec_by_severity: {critical: 0}").is_err());
+        assert!(settings("alertmanager_announce_sec_by_severity: {critical: 30}").is_ok());
+        assert!(
+            settings(
+                "pipelines: [{name: b, db_connection_url: postgres://b, \
+                 alertmanager_url: http://b, alertmanager_announce_sec: 0}]"
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use itertools::Itertools;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use utoipa::{IntoParams, ToSchema};
+
+const LIMIT_DEFAULT: i64 = 100;
+const LIMIT_MAX: i64 = 1000;
+
+// html forms send empty fields for everything left blank
+fn non_empty<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err: Display>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+fn non_empty_time<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.trim().is_empty() => OffsetDateTime::parse(value.trim(), &Rfc3339)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, IntoParams)]
+pub struct TrapSearch {
+    // only traps received at or after this time
+    #[serde(
+        default,
+        deserialize_with = "non_empty_time",
+        serialize_with = "time::serde::rfc3339::option::serialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[param(value_type = Option<String>, format = DateTime)]
+    pub from: Option<OffsetDateTime>,
+    // only traps received before this time
+    #[serde(
+        default,
+        deserialize_with = "non_empty_time",
+        serialize_with = "time::serde::rfc3339::option::serialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[param(value_type = Option<String>, format = DateTime)]
+    pub to: Option<OffsetDateTime>,
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub community: Option<String>,
+    // the trap OID, as stored in the name column
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub oid: Option<String>,
+    // the column `like` and `regex` match on, the whole row when left out
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub column: Option<String>,
+    // a SQL LIKE pattern, matched case-insensitively
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub like: Option<String>,
+    // a POSIX regular expression
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub regex: Option<String>,
+    // at most 1000 traps per page
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub limit: Option<i64>,
+    #[serde(
+        default,
+        deserialize_with = "non_empty",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub offset: Option<i64>,
+}
+
+impl TrapSearch {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(LIMIT_DEFAULT).clamp(1, LIMIT_MAX)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    pub fn validate(&self, columns: &[String]) -> Result<(), String> {
+        if let Some(column) = &self.column
+            && !columns.contains(column)
+        {
+            return Err(format!("Unknown column {column:?}"));
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to)
+            && from >= to
+        {
+            return Err("`from` has to be before `to`".to_string());
+        }
+        Ok(())
+    }
+}
+
+// the query string of the same search at another page, for the browser's paging links
+pub fn page_query(search: &TrapSearch, offset: i64) -> String {
+    let page = TrapSearch {
+        offset: Some(offset),
+        ..search.clone()
+    };
+    serde_urlencoded::to_string(page).unwrap_or_default()
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrapSearchPage {
+    pub traps: Vec<BTreeMap<String, Option<String>>>,
+    pub offset: i64,
+    // set when there are more traps to page through
+    pub next_offset: Option<i64>,
+}
+
+impl TrapSearchPage {
+    // one more trap than requested is fetched to tell whether another page follows
+    pub fn new(mut traps: Vec<BTreeMap<String, Option<String>>>, search: &TrapSearch) -> Self {
+        let more = traps.len() as i64 > search.limit();
+        traps.truncate(search.limit() as usize);
+        TrapSearchPage {
+            offset: search.offset(),
+            next_offset: more.then(|| search.offset() + search.limit()),
+            traps,
+        }
+    }
+}
+
+// quoted as in RFC 4180, missing values become empty fields
+pub fn to_csv(columns: &[String], traps: &[BTreeMap<String, Option<String>>]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+
+    let mut csv = columns.iter().map(|c| field(c)).join(",");
+    csv.push_str("\r\n");
+    for trap in traps {
+        let row = columns
+            .iter()
+            .map(|c| {
+                field(
+                    trap.get(c)
+                        .cloned()
+                        .flatten()
+                        .as_deref()
+                        .unwrap_or_default(),
+                )
+            })
+            .join(",");
+        csv.push_str(&row);
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trap_search::{TrapSearch, TrapSearchPage, to_csv};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn pages() {
+        let search = TrapSearch {
+            limit: Some(2),
+            offset: Some(4),
+            ..TrapSearch::default()
+        };
+        let page = TrapSearchPage::new(vec![BTreeMap::new(); 3], &search);
+        assert_eq!(page.traps.len(), 2);
+        assert_eq!(page.next_offset, Some(6));
+
+        let page = TrapSearchPage::new(vec![BTreeMap::new(); 2], &search);
+        assert_eq!(page.next_offset, None);
+
+        let columns = vec!["time".to_string(), "name".to_string()];
+        let unknown = TrapSearch {
+            column: Some("ifIndex".to_string()),
+            ..TrapSearch::default()
+        };
+        assert!(unknown.validate(&columns).is_err());
+        assert!(search.validate(&columns).is_ok());
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let columns = vec!["name".to_string(), "message".to_string()];
+        let trap = BTreeMap::from([
+            ("name".to_string(), Some("linkDown".to_string())),
+            ("message".to_string(), Some(r#"port "1", down"#.to_string())),
+        ]);
+        let empty = BTreeMap::from([("name".to_string(), None)]);
+
+        assert_eq!(
+            to_csv(&columns, &[trap, empty]),
+            "name,message\r\nlinkDown,\"port \"\"1\"\", down\"\r\n,\r\n"
+        );
+    }
+}
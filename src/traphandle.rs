@@ -0,0 +1,195 @@
+use crate::alerts::{Alert, parse_source_address};
+use crate::config::CONFIG;
+use crate::listener::TrapIngest;
+use crate::reboot::parse_uptime;
+use crate::snmp::{SNMP_TRAP_OID, SYS_UPTIME_OID};
+use crate::trap_db::TrapDb;
+use anyhow::{Context, anyhow};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+const TRAP_COMMUNITY_OID: &str = "1.3.6.1.6.3.18.1.4.0";
+const MAX_TRAP_SIZE: u64 = 1024 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct TraphandleTrap {
+    pub name: String,
+    pub community: String,
+    pub source: Option<IpAddr>,
+    pub uptime: Option<u32>,
+    pub varbinds: BTreeMap<String, String>,
+}
+
+impl TraphandleTrap {
+    pub fn to_alert(&self) -> Alert {
+        let labels = self
+            .varbinds
+            .iter()
+            .map(|(oid, value)| (CONFIG.mapped_label(oid).to_string(), value.clone()))
+            .collect();
+        Alert::from_occurrence(
+            self.name.clone(),
+            self.community.clone(),
+            OffsetDateTime::now_utc(),
+            labels,
+        )
+        .with_agent_address(self.source, CONFIG.instance_label())
+    }
+}
+
+// snmptrapd hands traps to traphandle programs as the sending host name, the transport
+// address and one "OID value" line per varbind
+pub fn parse_traphandle(input: &str) -> anyhow::Result<TraphandleTrap> {
+    let mut lines = input.lines();
+    let host = lines.next().ok_or_else(|| anyhow!("missing host line"))?;
+    let address = lines
+        .next()
+        .ok_or_else(|| anyhow!("missing address line"))?;
+    let source = parse_source_address(address).or_else(|| parse_source_address(host));
+
+    let mut name = None;
+    let mut community = String::new();
+    let mut uptime = None;
+    let mut varbinds = BTreeMap::new();
+    for line in lines.map(str::trim).filter(|l| !l.is_empty()) {
+        let (oid, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let oid = oid.trim_start_matches('.');
+        let value = unquote(value.trim());
+
+        if is_oid(oid, SNMP_TRAP_OID, "snmpTrapOID.0") {
+            name = Some(value.trim_start_matches('.').to_string());
+        } else if is_oid(oid, TRAP_COMMUNITY_OID, "snmpTrapCommunity.0") {
+            community = value.to_string();
+        } else if is_oid(oid, SYS_UPTIME_OID, "sysUpTime.0")
+            || is_oid(oid, SYS_UPTIME_OID, "sysUpTimeInstance")
+        {
+            uptime = parse_uptime(value);
+        } else if !value.is_empty() {
+            varbinds.insert(oid.to_string(), value.to_string());
+        }
+    }
+
+    Ok(TraphandleTrap {
+        name: name.ok_or_else(|| anyhow!("trap without snmpTrapOID.0"))?,
+        community,
+        source,
+        uptime,
+        varbinds,
+    })
+}
+
+// symbolic names depend on the MIBs loaded by snmptrapd, so accept both renderings
+fn is_oid(oid: &str, numeric: &str, symbol: &str) -> bool {
+    oid == numeric || oid.rsplit("::").next() == Some(symbol)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+pub async fn forward_stdin(path: &Path) -> anyhow::Result<()> {
+    let mut input = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)?;
+    parse_traphandle(&input)?;
+
+    let mut stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("connecting to {}", path.display()))?;
+    stream.write_all(input.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+pub struct TraphandleListener {
+    listener: UnixListener,
+    ingest: Arc<TrapIngest>,
+}
+
+impl TraphandleListener {
+    pub fn bind(path: PathBuf, db: Arc<TrapDb>) -> anyhow::Result<Self> {
+        // a socket left behind by a previous run would make the bind fail
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        info!("Listening for traphandle traps on {}", path.display());
+
+        Ok(TraphandleListener {
+            listener,
+            ingest: Arc::new(TrapIngest::new(db)),
+        })
+    }
+
+    pub async fn run_listener_blocking(&self) {
+        loop {
+            match self.listener.accept().await {
+                // a slow sender would hold up every other trap until its read times out
+                Ok((stream, _)) => {
+                    let ingest = self.ingest.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, &ingest).await {
+                            warn!("Dropping traphandle trap: {e}");
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept traphandle connection: {e}"),
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream, ingest: &TrapIngest) -> anyhow::Result<()> {
+    let mut input = String::new();
+    tokio::time::timeout(
+        READ_TIMEOUT,
+        stream.take(MAX_TRAP_SIZE).read_to_string(&mut input),
+    )
+    .await
+    .context("timed out reading trap")??;
+
+    let trap = parse_traphandle(&input)?;
+    let Some(alert) = CONFIG.source_filter().apply(trap.to_alert()) else {
+        return Ok(());
+    };
+    ingest.ingest(alert, trap.source, trap.uptime).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traphandle::parse_traphandle;
+
+    #[test]
+    fn traphandle_format() {
+        let trap = parse_traphandle(
+            "switch1.example.com\n\
+             UDP: [192.0.2.10]:50123->[192.0.2.1]:162\n\
+             DISMAN-EVENT-MIB::sysUpTimeInstance 0:0:01:00.00\n\
+             SNMPv2-MIB::snmpTrapOID.0 IF-MIB::linkDown\n\
+             IF-MIB::ifDescr.3 \"GigabitEthernet0/3\"\n\
+             .1.3.6.1.6.3.18.1.4.0 \"public\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(trap.name, "IF-MIB::linkDown");
+        assert_eq!(trap.community, "public");
+        assert_eq!(trap.source, Some("192.0.2.10".parse().unwrap()));
+        assert_eq!(trap.uptime, Some(6000));
+        assert_eq!(
+            trap.varbinds.get("IF-MIB::ifDescr.3").map(String::as_str),
+            Some("GigabitEthernet0/3")
+        );
+        assert!(parse_traphandle("host\nUDP: [192.0.2.10]:1\n").is_err());
+    }
+}
@@ -1,65 +1,323 @@
+use crate::alertmanager::{AlertmanagerAlert, RelayStatus, prepare_alert};
 use crate::alerts::Alert;
-use crate::trap_db::TrapDb;
+use crate::auth::{API_KEY_HEADER, CsrfToken, cookie_path, forwarded_client};
+use crate::config::CONFIG;
+use crate::enrichment::AlertEnrichment;
+use crate::leader::LeaderElection;
+use crate::oidc::Session;
+use crate::snooze::MAX_SNOOZE;
+use crate::summary::Summary;
+use crate::trap_db::{CACHE_TTL, ClearOutcome, ClearRange, TrapDb};
+use crate::trap_search::{TrapSearch, TrapSearchPage, page_query, to_csv};
+use actix_web::cookie::{Cookie, SameSite};
 use actix_web::http::header;
-use actix_web::web::{Data, Form, Html};
-use actix_web::{HttpResponse, get, post};
+use actix_web::http::header::{CacheControl, CacheDirective, ETag, EntityTag, IfNoneMatch};
+use actix_web::web::{Data, Form, Path, Query, ReqData};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, get, post};
+use ipnet::IpNet;
 use itertools::Itertools;
 use log::error;
+use serde::de::DeserializeOwned;
+use serde::de::value::{Error as ValueError, StrDeserializer};
 use serde::{Deserialize, Serialize};
 use std::cmp;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{DefaultHasher, Hasher};
 use tera::{Context, Tera};
-use time::Duration;
+use time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+const RECENT_TIMES_SHOWN: usize = 50;
+const SPARKLINE_BUCKETS: usize = 24;
+const SPARKLINE_BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const THEME_COOKIE: &str = "theme";
+const DENSITY_COOKIE: &str = "density";
+const COLUMNS_COOKIE: &str = "columns";
+const COLUMNS_SEPARATOR: char = '|';
+const DISPLAY_COOKIE_MAX_AGE: Duration = Duration::days(365);
+
+#[derive(Serialize, ToSchema)]
 pub struct AlertView {
     pub hash: u64,
     pub severity: String,
     pub name: String,
-    pub times: Vec<String>,
+    pub count: usize,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub recent_times: Vec<String>,
+    pub sparkline: String,
     pub time_min: String,
     pub time_avg: String,
     pub time_max: String,
     pub labels: BTreeMap<String, String>,
     pub community: String,
+    pub community_name: String,
+    pub community_color: Option<String>,
+    pub in_maintenance: Option<String>,
+    pub delivered_at: Option<String>,
+    pub delivery_error: Option<String>,
 }
 
 impl From<&Alert> for AlertView {
     fn from(alert: &Alert) -> Self {
         let severity = alert.severity().to_string();
         let name = alert.pretty_name();
-        let labels = alert.pretty_labels();
-        let times = alert.times().iter().map(|t| t.to_string()).collect();
+        let mut labels = alert.pretty_labels(&CONFIG);
+        labels.extend(alert.volatile_labels().clone());
+        let recent_times = alert
+            .times()
+            .iter()
+            .rev()
+            .take(RECENT_TIMES_SHOWN)
+            .map(|t| t.to_string())
+            .collect();
         let time_min = format!("{:.3}", alert.interval_min().unwrap_or(Duration::ZERO));
         let time_avg = format!("{:.3}", alert.interval_avg().unwrap_or(Duration::ZERO));
         let time_max = format!("{:.3}", alert.interval_max().unwrap_or(Duration::ZERO));
 
+        let display = CONFIG.community_display(alert.community());
+
         AlertView {
             hash: alert.hash(),
             severity,
             name,
-            times,
+            count: alert.count(),
+            first_seen: alert.earliest().to_string(),
+            last_seen: alert.latest().to_string(),
+            recent_times,
+            sparkline: sparkline(&alert.occurrence_histogram(SPARKLINE_BUCKETS)),
             time_min,
             time_avg,
             time_max,
             labels,
             community: alert.community().to_string(),
+            community_name: display
+                .and_then(|d| d.name())
+                .unwrap_or(alert.community())
+                .to_string(),
+            community_color: display.and_then(|d| d.color()).map(str::to_string),
+            in_maintenance: None,
+            delivered_at: None,
+            delivery_error: None,
         }
     }
 }
 
-#[get("/")]
-async fn alerts_view(db: Data<TrapDb>, templates: Data<Tera>) -> Html {
-    let alerts: Vec<AlertView> = db
-        .cached_alerts()
+fn sparkline(histogram: &[usize]) -> String {
+    let max = histogram.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return String::new();
+    }
+
+    histogram
+        .iter()
+        .map(|&n| {
+            if n == 0 {
+                ' '
+            } else {
+                SPARKLINE_BARS[n * (SPARKLINE_BARS.len() - 1) / max]
+            }
+        })
+        .collect()
+}
+
+pub fn cache_control() -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Private,
+        CacheDirective::MaxAge(CACHE_TTL.as_secs() as u32),
+    ])
+}
+
+// pollers send back the tag of what they already have and get an empty 304 while nothing changed
+pub fn conditional_json<T: Serialize>(req: &HttpRequest, value: &T) -> HttpResponse {
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to serialize response: {e}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&body);
+    let tag = EntityTag::new_strong(format!("{:x}", hasher.finish()));
+
+    let unchanged = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&tag)),
+        None => false,
+    };
+    let mut res = match unchanged {
+        true => HttpResponse::NotModified(),
+        false => HttpResponse::Ok(),
+    };
+    res.insert_header(ETag(tag)).insert_header(cache_control());
+    match unchanged {
+        true => res.finish(),
+        false => res.content_type("application/json").body(body),
+    }
+}
+
+// windows can match labels enrichment adds, so they're evaluated like the relay does
+fn maintenance_window(alert: &Alert, enrichment: &AlertEnrichment) -> Option<String> {
+    let mut prepared = AlertmanagerAlert::from_alert(alert, &CONFIG);
+    prepare_alert(&mut prepared, enrichment, &CONFIG).ok()?;
+    prepared.maintenance().map(str::to_string)
+}
+
+pub async fn sorted_alert_views(
+    db: &TrapDb,
+    relay_status: &RelayStatus,
+    enrichment: &AlertEnrichment,
+) -> Vec<AlertView> {
+    let deliveries = relay_status.deliveries().await;
+    let snoozed = db.snoozed_alerts().await;
+    db.cached_alerts()
         .await
         .iter()
+        .filter(|alert| !snoozed.contains_key(&alert.hash()))
         .sorted_by_key(|a: &&Alert| cmp::Reverse(a.latest()))
-        .map(Into::into)
+        .map(|alert| {
+            let mut view = AlertView::from(alert);
+            view.in_maintenance = maintenance_window(alert, enrichment);
+            if let Some(delivery) = deliveries.get(&alert.hash()) {
+                view.delivered_at = delivery.last_success.map(|t| t.to_string());
+                view.delivery_error = delivery.last_error.clone();
+            }
+            view
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Density {
+    #[default]
+    Comfortable,
+    Compact,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DisplayOptions {
+    theme: Option<Theme>,
+    density: Option<Density>,
+}
+
+impl DisplayOptions {
+    // a query parameter picks the option and is remembered in a cookie for the next visits
+    fn resolve(self, req: &HttpRequest) -> DisplayOptions {
+        DisplayOptions {
+            theme: self.theme.or_else(|| from_cookie(req, THEME_COOKIE)),
+            density: self.density.or_else(|| from_cookie(req, DENSITY_COOKIE)),
+        }
+    }
+
+    fn cookies(&self) -> Vec<Cookie<'static>> {
+        let theme = self.theme.map(|t| (THEME_COOKIE, plain_value(&t)));
+        let density = self.density.map(|d| (DENSITY_COOKIE, plain_value(&d)));
+        [theme, density]
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| display_cookie(name, value))
+            .collect()
+    }
+}
+
+fn display_cookie(name: &'static str, value: String) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(cookie_path())
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(DISPLAY_COOKIE_MAX_AGE)
+        .finish()
+}
+
+// label columns for the table layout. The checkbox form always sends an empty `columns`,
+// so unchecking everything switches back to the cards
+fn selected_columns(req: &HttpRequest) -> (Vec<String>, Option<Cookie<'static>>) {
+    let query: Vec<(String, String)> =
+        serde_urlencoded::from_str(req.query_string()).unwrap_or_default();
+    if query.iter().any(|(key, _)| key == COLUMNS_COOKIE) {
+        let columns = query
+            .into_iter()
+            .filter(|(key, value)| key == COLUMNS_COOKIE && !value.is_empty())
+            .map(|(_, value)| value)
+            .unique()
+            .collect_vec();
+        let cookie = display_cookie(COLUMNS_COOKIE, columns.join(&COLUMNS_SEPARATOR.to_string()));
+        return (columns, Some(cookie));
+    }
+
+    let columns = req
+        .cookie(COLUMNS_COOKIE)
+        .map(|cookie| {
+            cookie
+                .value()
+                .split(COLUMNS_SEPARATOR)
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    (columns, None)
+}
+
+fn from_cookie<T: DeserializeOwned>(req: &HttpRequest, name: &str) -> Option<T> {
+    let cookie = req.cookie(name)?;
+    T::deserialize(StrDeserializer::<ValueError>::new(cookie.value())).ok()
+}
+
+fn plain_value(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+#[get("/")]
+async fn alerts_view(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    relay_status: Data<RelayStatus>,
+    enrichment: Data<AlertEnrichment>,
+    templates: Data<Tera>,
+    csrf_token: ReqData<CsrfToken>,
+    Query(selected): Query<DisplayOptions>,
+) -> HttpResponse {
+    let session = req.extensions().get::<Session>().cloned();
+    let summary = Summary::collect(&db, &relay_status).await;
+    let alerts = sorted_alert_views(&db, &relay_status, &enrichment).await;
+    let mut cookies = selected.cookies();
+    let display = selected.resolve(&req);
+    let (columns, columns_cookie) = selected_columns(&req);
+    cookies.extend(columns_cookie);
+    let label_names: BTreeSet<&String> = alerts.iter().flat_map(|a| a.labels.keys()).collect();
+    let community_names: BTreeMap<&String, &str> = summary
+        .by_community
+        .keys()
+        .filter_map(|c| Some((c, CONFIG.community_display(c)?.name()?)))
         .collect();
 
     let mut ctx = Context::new();
     ctx.insert("alerts", &alerts);
+    ctx.insert("summary", &summary);
+    ctx.insert("community_names", &community_names);
+    ctx.insert("csrf_token", &csrf_token.0);
+    ctx.insert("base_path", CONFIG.web_path_prefix());
+    ctx.insert("theme", &display.theme.unwrap_or_default());
+    ctx.insert("density", &display.density.unwrap_or_default());
+    ctx.insert("columns", &columns);
+    ctx.insert("label_names", &label_names);
+    if let Some(session) = session {
+        ctx.insert("session", &session);
+    }
 
     drop(alerts);
 
@@ -67,23 +325,324 @@ async fn alerts_view(db: Data<TrapDb>, templates: Data<Tera>) -> Html {
         .render("alerts_view", &ctx)
         .expect("Builtin Template render failed");
 
-    Html::new(rendered)
+    let mut response = HttpResponse::Ok();
+    response.insert_header(cache_control());
+    for cookie in cookies {
+        response.cookie(cookie);
+    }
+    response
+        .content_type("text/html; charset=utf-8")
+        .body(rendered)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LabelStages {
+    pub hash: u64,
+    pub name: String,
+    pub raw: BTreeMap<String, String>,
+    pub sanitized: BTreeMap<String, String>,
+    pub enriched: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+    pub suppressed: Option<String>,
+}
+
+impl LabelStages {
+    pub fn collect(alert: &Alert, enrichment: &AlertEnrichment) -> anyhow::Result<Self> {
+        let mut prepared = AlertmanagerAlert::from_alert(alert, &CONFIG);
+        prepare_alert(&mut prepared, enrichment, &CONFIG)?;
+
+        Ok(LabelStages {
+            hash: alert.hash(),
+            name: alert.raw_name().to_string(),
+            raw: alert.raw_labels().clone(),
+            sanitized: alert.pretty_labels(&CONFIG),
+            enriched: prepared.labels().clone(),
+            annotations: prepared.annotations().clone(),
+            suppressed: prepared.suppressed().map(str::to_string),
+        })
+    }
+}
+
+pub async fn find_label_stages(
+    db: &TrapDb,
+    enrichment: &AlertEnrichment,
+    hash: u64,
+) -> Option<anyhow::Result<LabelStages>> {
+    let alerts = db.cached_alerts().await;
+    let alert = alerts.iter().find(|a| a.hash() == hash)?;
+    Some(LabelStages::collect(alert, enrichment))
+}
+
+#[get("/alerts/{hash}/labels")]
+async fn label_stages_view(
+    db: Data<TrapDb>,
+    enrichment: Data<AlertEnrichment>,
+    templates: Data<Tera>,
+    hash: Path<u64>,
+) -> HttpResponse {
+    let stages = match find_label_stages(&db, &enrichment, hash.into_inner()).await {
+        Some(Ok(stages)) => stages,
+        Some(Err(e)) => {
+            error!("Failed to enrich alert for label view: {e}");
+            return HttpResponse::InternalServerError().body("Failed to enrich alert");
+        }
+        None => return HttpResponse::NotFound().body("Alert not found"),
+    };
+
+    let mut ctx = Context::new();
+    ctx.insert("stages", &stages);
+    ctx.insert("base_path", CONFIG.web_path_prefix());
+
+    let rendered = templates
+        .render("labels_view", &ctx)
+        .expect("Builtin Template render failed");
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(rendered)
+}
+
+async fn search_trap_page(
+    db: &TrapDb,
+    search: &TrapSearch,
+) -> Result<(Vec<String>, TrapSearchPage), HttpResponse> {
+    let columns = db.trap_columns().await.map_err(|e| {
+        error!("Failed to read trap columns: {e}");
+        HttpResponse::InternalServerError().body("Failed to search traps")
+    })?;
+    search
+        .validate(&columns)
+        .map_err(|reason| HttpResponse::BadRequest().body(reason))?;
+
+    let page = db.search_traps(search, &columns).await.map_err(|e| {
+        error!("Failed to search traps: {e}");
+        HttpResponse::InternalServerError().body(format!("Failed to search traps: {e}"))
+    })?;
+    Ok((columns, page))
 }
 
-#[derive(Deserialize)]
-struct AlertHash {
+// every column of the raw table, including the ones dropped before alerting
+#[get("/traps")]
+async fn traps_view(
+    db: Data<TrapDb>,
+    templates: Data<Tera>,
+    Query(search): Query<TrapSearch>,
+) -> HttpResponse {
+    let (columns, page) = match search_trap_page(&db, &search).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let mut ctx = Context::new();
+    ctx.insert("columns", &columns);
+    ctx.insert("page", &page);
+    ctx.insert("search", &search);
+    ctx.insert("limit", &search.limit());
+    ctx.insert(
+        "previous_query",
+        &(page.offset > 0).then(|| page_query(&search, (page.offset - search.limit()).max(0))),
+    );
+    ctx.insert(
+        "next_query",
+        &page.next_offset.map(|offset| page_query(&search, offset)),
+    );
+    ctx.insert("csv_query", &page_query(&search, page.offset));
+    ctx.insert("base_path", CONFIG.web_path_prefix());
+
+    let rendered = templates
+        .render("traps_view", &ctx)
+        .expect("Builtin Template render failed");
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(rendered)
+}
+
+#[get("/traps.csv")]
+async fn traps_csv(db: Data<TrapDb>, Query(search): Query<TrapSearch>) -> HttpResponse {
+    let (columns, page) = match search_trap_page(&db, &search).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            r#"attachment; filename="traps.csv""#,
+        ))
+        .body(to_csv(&columns, &page.traps))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ClearRequest {
     hash: u64,
+    reason: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    after: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    before: Option<OffsetDateTime>,
 }
 
+#[utoipa::path(
+    request_body(content = ClearRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 302, description = "Alert cleared, redirects to the alerts view"),
+        (status = 400, description = "Missing clear reason"),
+        (status = 401, description = "Invalid API key"),
+        (status = 403, description = "Missing or invalid CSRF token"),
+        (status = 500, description = "Database error while clearing"),
+        (status = 503, description = "Instance is a standby, the leader serves clears"),
+    ),
+    security(("api_key" = []))
+)]
 #[post("/api/clear")]
-async fn clear_alert(db: Data<TrapDb>, Form(alert): Form<AlertHash>) -> HttpResponse {
-    if let Err(e) = db.clear_alerts(alert.hash).await {
-        error!("Failed to clear alerts: {e}");
-        return HttpResponse::InternalServerError()
-            .body("Failed to clear alerts");
+async fn clear_alert(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    leader: Data<LeaderElection>,
+    Form(clear): Form<ClearRequest>,
+) -> HttpResponse {
+    if !leader.is_leader() {
+        return HttpResponse::ServiceUnavailable()
+            .body("This instance is on standby, clear the alert on the leader");
+    }
+
+    let reason = clear.reason.trim();
+    if reason.is_empty() {
+        return HttpResponse::BadRequest().body("A reason is required to clear an alert");
+    }
+
+    let actor = request_actor(&req);
+
+    let range = ClearRange {
+        after: clear.after,
+        before: clear.before,
+    };
+    if let (Some(after), Some(before)) = (range.after, range.before)
+        && after >= before
+    {
+        return HttpResponse::BadRequest().body("The clear range has to start before it ends");
+    }
+
+    match db.clear_alerts(clear.hash, range, reason, &actor).await {
+        Ok(ClearOutcome::Cleared | ClearOutcome::NotFound) => {}
+        Ok(ClearOutcome::Undeletable) => {
+            return HttpResponse::UnprocessableEntity()
+                .body("The alert has no stored traps that could be deleted");
+        }
+        Err(e) => {
+            error!("Failed to clear alerts: {e}");
+            return HttpResponse::InternalServerError().body("Failed to clear alerts");
+        }
     }
 
     HttpResponse::Found()
-        .insert_header((header::LOCATION, "/"))
+        .insert_header((header::LOCATION, format!("{}/", CONFIG.web_path_prefix())))
         .finish()
 }
+
+fn request_actor(req: &HttpRequest) -> String {
+    describe_actor(req, CONFIG.trusted_proxies())
+}
+
+// a signed in user is named in the audit trail, other requests only by how and where they came in
+fn describe_actor(req: &HttpRequest, trusted_proxies: &[IpNet]) -> String {
+    let actor = if let Some(session) = req.extensions().get::<Session>() {
+        session.user.clone()
+    } else if req.headers().contains_key(API_KEY_HEADER) {
+        "api key".to_string()
+    } else {
+        "web ui".to_string()
+    };
+    match req
+        .peer_addr()
+        .and_then(|peer| forwarded_client(peer.ip(), req.headers(), trusted_proxies))
+    {
+        Some(ip) => format!("{actor} from {ip}"),
+        None => actor,
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SnoozeRequest {
+    hash: u64,
+    minutes: u32,
+    #[serde(default)]
+    reason: String,
+}
+
+#[utoipa::path(
+    request_body(content = SnoozeRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 302, description = "Alert snoozed, redirects to the alerts view"),
+        (status = 400, description = "Snooze duration out of range"),
+        (status = 401, description = "Invalid API key"),
+        (status = 403, description = "Missing or invalid CSRF token"),
+        (status = 503, description = "Instance is a standby, the leader serves snoozes"),
+    ),
+    security(("api_key" = []))
+)]
+#[post("/api/snooze")]
+async fn snooze_alert(
+    req: HttpRequest,
+    db: Data<TrapDb>,
+    leader: Data<LeaderElection>,
+    Form(snooze): Form<SnoozeRequest>,
+) -> HttpResponse {
+    if !leader.is_leader() {
+        return HttpResponse::ServiceUnavailable()
+            .body("This instance is on standby, snooze the alert on the leader");
+    }
+
+    let duration = Duration::minutes(snooze.minutes as i64);
+    if duration.is_zero() || duration > MAX_SNOOZE {
+        return HttpResponse::BadRequest().body(format!(
+            "A snooze has to last between one minute and {MAX_SNOOZE}"
+        ));
+    }
+
+    let actor = request_actor(&req);
+    if let Err(e) = db
+        .snooze_alert(snooze.hash, duration, snooze.reason.trim(), &actor)
+        .await
+    {
+        error!("Failed to snooze alert: {e}");
+        return HttpResponse::InternalServerError().body("Failed to snooze alert");
+    }
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, format!("{}/", CONFIG.web_path_prefix())))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::API_KEY_HEADER;
+    use crate::oidc::{Role, Session};
+    use crate::web::describe_actor;
+    use actix_web::HttpMessage;
+    use actix_web::test::TestRequest;
+    use time::ext::NumericalDuration;
+
+    #[test]
+    fn audit_actor_names_the_signed_in_user() {
+        let peer = "192.0.2.7:51000".parse().unwrap();
+
+        let req = TestRequest::default().peer_addr(peer).to_http_request();
+        req.extensions_mut()
+            .insert(Session::new("alice".to_string(), Role::Operator, 1.hours()));
+        assert_eq!(describe_actor(&req, &[]), "alice from 192.0.2.7");
+
+        let req = TestRequest::default()
+            .peer_addr(peer)
+            .insert_header((API_KEY_HEADER, "key"))
+            .to_http_request();
+        assert_eq!(describe_actor(&req, &[]), "api key from 192.0.2.7");
+
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(describe_actor(&req, &[]), "web ui");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::alerts::{Alert, Severity};
+use crate::config::CONFIG;
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+const DEFAULT_COMMUNITY: &str = "webhook";
+
+#[derive(Debug, Deserialize, ToSchema)]
+// simple payloads go first: they require `name`, which Alertmanager payloads never carry
+#[serde(untagged)]
+pub enum IncomingAlerts {
+    Simple(Vec<SimplePayload>),
+    SingleSimple(SimplePayload),
+    Alertmanager(Vec<AlertmanagerPayload>),
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerPayload {
+    labels: BTreeMap<String, String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    starts_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SimplePayload {
+    name: String,
+    #[schema(value_type = Option<String>)]
+    severity: Option<Severity>,
+    community: Option<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    time: Option<OffsetDateTime>,
+}
+
+impl IncomingAlerts {
+    pub fn into_alerts(self) -> anyhow::Result<Vec<Alert>> {
+        match self {
+            IncomingAlerts::Alertmanager(payloads) => payloads
+                .into_iter()
+                .map(AlertmanagerPayload::into_alert)
+                .collect(),
+            IncomingAlerts::Simple(payloads) => Ok(payloads
+                .into_iter()
+                .map(SimplePayload::into_alert)
+                .collect()),
+            IncomingAlerts::SingleSimple(payload) => Ok(vec![payload.into_alert()]),
+        }
+    }
+}
+
+impl AlertmanagerPayload {
+    fn into_alert(mut self) -> anyhow::Result<Alert> {
+        let name = self
+            .labels
+            .remove("alertname")
+            .ok_or_else(|| anyhow!("alert without an alertname label"))?;
+        let community = self
+            .labels
+            .remove(CONFIG.alertmanager_community_label())
+            .unwrap_or_else(|| DEFAULT_COMMUNITY.to_string());
+
+        Ok(Alert::from_occurrence(
+            name,
+            community,
+            self.starts_at.unwrap_or_else(OffsetDateTime::now_utc),
+            self.labels,
+        ))
+    }
+}
+
+impl SimplePayload {
+    fn into_alert(mut self) -> Alert {
+        if let Some(severity) = self.severity {
+            self.labels
+                .insert("severity".to_string(), severity.to_string());
+        }
+
+        Alert::from_occurrence(
+            self.name,
+            self.community
+                .unwrap_or_else(|| DEFAULT_COMMUNITY.to_string()),
+            self.time.unwrap_or_else(OffsetDateTime::now_utc),
+            self.labels,
+        )
+    }
+}
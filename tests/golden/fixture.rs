@@ -0,0 +1,149 @@
+use anyhow::{Context, bail};
+use indexmap::IndexMap;
+use serde_norway::{Mapping, Value};
+use snmp_trap_alertmanager::config::Settings;
+use sqlx::{Executor, PgPool, Postgres, QueryBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const EXPECTED_FILE: &str = "expected.json";
+const TRAPS_FILE: &str = "traps.yaml";
+const SETTINGS_FILE: &str = "settings.yaml";
+const RULES_DIR: &str = "rules";
+
+// one directory per case: the trap rows, optional settings and enrichment rules, and the
+// Alertmanager payload they are expected to turn into
+pub struct Fixture {
+    pub name: String,
+    pub dir: PathBuf,
+    traps: Vec<IndexMap<String, Value>>,
+    settings: Mapping,
+}
+
+impl Fixture {
+    pub fn load_all(dir: &Path) -> anyhow::Result<Vec<Fixture>> {
+        let mut fixtures: Vec<Fixture> = dir
+            .read_dir()?
+            .map(|entry| Ok::<_, anyhow::Error>(entry?.path()))
+            .filter(|path| path.as_ref().is_ok_and(|p| p.is_dir()))
+            .map(|path| {
+                let path = path?;
+                Fixture::load(&path).with_context(|| format!("fixture {path:?}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(fixtures)
+    }
+
+    pub fn load(dir: &Path) -> anyhow::Result<Fixture> {
+        let name = dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("fixture directory without a name")?
+            .to_string();
+        let traps: Vec<IndexMap<String, Value>> =
+            serde_norway::from_str(&fs::read_to_string(dir.join(TRAPS_FILE))?)?;
+        if traps.is_empty() {
+            bail!("no trap rows in {TRAPS_FILE}");
+        }
+
+        let settings_file = dir.join(SETTINGS_FILE);
+        let settings = if settings_file.exists() {
+            serde_norway::from_str(&fs::read_to_string(settings_file)?)?
+        } else {
+            Mapping::new()
+        };
+
+        Ok(Fixture {
+            name,
+            dir: dir.to_path_buf(),
+            traps,
+            settings,
+        })
+    }
+
+    pub fn rules_dir(&self) -> Option<PathBuf> {
+        Some(self.dir.join(RULES_DIR)).filter(|dir| dir.is_dir())
+    }
+
+    pub fn database_name(&self) -> String {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | '0'..='9' => c,
+                'A'..='Z' => c.to_ascii_lowercase(),
+                _ => '_',
+            })
+            .collect();
+        format!("golden_{name}")
+    }
+
+    // the fixture's own settings win, except for where the traps are
+    pub fn settings(&self, db_url: &str) -> anyhow::Result<Settings> {
+        let mut settings = self.settings.clone();
+        for (key, value) in [
+            ("web_url", "http://snmp-trap-alertmanager.test"),
+            ("alertmanager_url", "http://alertmanager.test"),
+        ] {
+            if !settings.contains_key(key) {
+                settings.insert(key.into(), value.into());
+            }
+        }
+        settings.insert("db_connection_url".into(), db_url.into());
+        Settings::from_yaml(&serde_norway::to_string(&settings)?)
+    }
+
+    // every column but the time is text, like snmptrapd's sql output leaves them
+    pub async fn seed(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let mut columns: Vec<&str> = vec!["time"];
+        for row in &self.traps {
+            for column in row.keys() {
+                if !columns.contains(&column.as_str()) {
+                    columns.push(column);
+                }
+            }
+        }
+
+        let definitions = columns
+            .iter()
+            .map(|column| match *column {
+                "time" => r#""time" timestamp NOT NULL"#.to_string(),
+                column => format!(r#""{}" text"#, column.replace('"', "\"\"")),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        pool.execute(format!(r#"CREATE TABLE "snmp_trap" ({definitions})"#).as_str())
+            .await?;
+
+        let mut builder = QueryBuilder::<Postgres>::new("INSERT INTO \"snmp_trap\" (");
+        let mut separated = builder.separated(", ");
+        for column in &columns {
+            separated.push(format!(r#""{}""#, column.replace('"', "\"\"")));
+        }
+        builder.push(") ");
+        builder.push_values(&self.traps, |mut row_builder, row| {
+            for column in &columns {
+                let value = row.get(*column).and_then(scalar);
+                row_builder.push_bind(value);
+                if *column == "time" {
+                    row_builder.push_unseparated("::timestamp");
+                }
+            }
+        });
+        builder.build().execute(pool).await?;
+        Ok(())
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        other => serde_norway::to_string(other)
+            .ok()
+            .map(|s| s.trim_end().to_string()),
+    }
+}
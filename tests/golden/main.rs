@@ -0,0 +1,137 @@
+// Seeds the trap rows of every case in tests/golden/cases into its own Postgres database and
+// compares what would be posted to Alertmanager with the case's expected.json.
+//
+// GOLDEN_DATABASE_URL points the cases at an existing server, otherwise a container is started.
+// Both need a database, so the test only runs with `cargo test -- --ignored` and fails without
+// one. UPDATE_GOLDEN=1 rewrites the expected payloads.
+mod fixture;
+
+use crate::fixture::{EXPECTED_FILE, Fixture};
+use snmp_trap_alertmanager::alertmanager::{AlertmanagerRelay, RelayStatus};
+use snmp_trap_alertmanager::calendar::OnCallCalendars;
+use snmp_trap_alertmanager::enrichment::AlertEnrichment;
+use snmp_trap_alertmanager::escalation::Escalations;
+use snmp_trap_alertmanager::notifier::prepared_alerts;
+use snmp_trap_alertmanager::trap_db::TrapDb;
+use sqlx::{Executor, PgPool};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::ContainerAsync;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use time::OffsetDateTime;
+use time::macros::datetime;
+
+const CASES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/cases");
+// end times are derived from the time of the cycle, so it's pinned
+const NOW: OffsetDateTime = datetime!(2025-06-01 12:00 UTC);
+
+struct Server {
+    url: String,
+    _container: Option<ContainerAsync<Postgres>>,
+}
+
+async fn start_server() -> Server {
+    if let Ok(url) = std::env::var("GOLDEN_DATABASE_URL") {
+        return Server {
+            url,
+            _container: None,
+        };
+    }
+
+    let container = Postgres::default()
+        .start()
+        .await
+        .expect("golden tests need GOLDEN_DATABASE_URL or a container runtime");
+    let host = container
+        .get_host()
+        .await
+        .expect("container host should be known");
+    let port = container
+        .get_host_port_ipv4(5432)
+        .await
+        .expect("container port should be mapped");
+    Server {
+        url: format!("postgres://postgres:postgres@{host}:{port}/postgres"),
+        _container: Some(container),
+    }
+}
+
+fn database_url(server_url: &str, database: &str) -> String {
+    let mut url = reqwest::Url::parse(server_url).expect("database url should be valid");
+    url.set_path(database);
+    url.to_string()
+}
+
+async fn run_case(server: &PgPool, server_url: &str, case: &Fixture) -> anyhow::Result<String> {
+    let database = case.database_name();
+    server
+        .execute(format!(r#"DROP DATABASE IF EXISTS "{database}" WITH (FORCE)"#).as_str())
+        .await?;
+    server
+        .execute(format!(r#"CREATE DATABASE "{database}""#).as_str())
+        .await?;
+
+    let url = database_url(server_url, &database);
+    let pool = PgPool::connect(&url).await?;
+    case.seed(&pool).await?;
+    pool.close().await;
+
+    let settings = Arc::new(case.settings(&url)?);
+    let db = TrapDb::new(settings.clone())?;
+    let mut enrichment = AlertEnrichment::new();
+    if let Some(rules) = case.rules_dir() {
+        enrichment.load_directory(&rules)?;
+    }
+    let escalations = Escalations::load(settings.escalations(), None);
+    let alerts = prepared_alerts(&db, &enrichment, &escalations, &OnCallCalendars::default()).await;
+
+    let mut relay = AlertmanagerRelay::new(settings, Arc::new(RelayStatus::default()));
+    let (_, mut payload) = relay.payload(&alerts, NOW);
+    // the relay doesn't care about the order, the comparison does
+    payload.sort_by_cached_key(|alert| serde_json::to_string(alert.labels()).unwrap_or_default());
+
+    Ok(serde_json::to_string_pretty(&payload)? + "\n")
+}
+
+#[tokio::test]
+#[ignore = "needs Postgres through GOLDEN_DATABASE_URL or a container runtime"]
+async fn golden_alertmanager_payloads() {
+    let cases = Fixture::load_all(Path::new(CASES_DIR)).expect("golden cases should load");
+    let server = start_server().await;
+    let pool = PgPool::connect(&server.url)
+        .await
+        .expect("golden database server should be reachable");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let actual = match run_case(&pool, &server.url, case).await {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {e:#}", case.name));
+                continue;
+            }
+        };
+
+        let expected_file = case.dir.join(EXPECTED_FILE);
+        if update {
+            fs::write(&expected_file, &actual).expect("expected payload should be writable");
+            continue;
+        }
+        match fs::read_to_string(&expected_file) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}: payload differs\n--- expected\n{expected}--- actual\n{actual}",
+                case.name
+            )),
+            Err(e) => failures.push(format!(
+                "{}: {e}, run with UPDATE_GOLDEN=1 to record it",
+                case.name
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}